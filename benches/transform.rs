@@ -0,0 +1,199 @@
+// Times `webgpu_transform` on generated modules of growing size.
+// Every module is twice as large as the previous one, so the time per instruction should stay
+// about the same, anything that grows with it is quadratic somewhere.
+//
+// cargo bench --bench transform
+
+use spirv_webgpu_transform::{Instruction, Module, Options, webgpu_transform};
+use std::time::{Duration, Instant};
+
+const SAMPLES_PER_TEXTURE: u32 = 4;
+const RUNS: u32 = 3;
+
+const OP_MEMORY_MODEL: u16 = 14;
+const OP_ENTRY_POINT: u16 = 15;
+const OP_EXECUTION_MODE: u16 = 16;
+const OP_CAPABILITY: u16 = 17;
+const OP_TYPE_VOID: u16 = 19;
+const OP_TYPE_FLOAT: u16 = 22;
+const OP_TYPE_VECTOR: u16 = 23;
+const OP_TYPE_IMAGE: u16 = 25;
+const OP_TYPE_SAMPLED_IMAGE: u16 = 27;
+const OP_TYPE_POINTER: u16 = 32;
+const OP_TYPE_FUNCTION: u16 = 33;
+const OP_CONSTANT: u16 = 43;
+const OP_CONSTANT_COMPOSITE: u16 = 44;
+const OP_FUNCTION: u16 = 54;
+const OP_FUNCTION_END: u16 = 56;
+const OP_VARIABLE: u16 = 59;
+const OP_LOAD: u16 = 61;
+const OP_STORE: u16 = 62;
+const OP_DECORATE: u16 = 71;
+const OP_IMAGE_SAMPLE_IMPLICIT_LOD: u16 = 87;
+const OP_IMAGE_SAMPLE_DREF_IMPLICIT_LOD: u16 = 89;
+const OP_LABEL: u16 = 248;
+const OP_RETURN: u16 = 253;
+
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_OUTPUT: u32 = 3;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+
+// A fragment shader with `texture_count` combined image samplers, each sampled a few times.
+// Every fourth one is also sampled for comparison, so both passes have work to do.
+fn generate_module(texture_count: u32) -> Vec<u32> {
+    let mut next_id = 1;
+    let mut id = || {
+        next_id += 1;
+        next_id - 1
+    };
+    let [void, float, v2float, v4float, image, sampled_image] = [(); 6].map(|_| id());
+    let [pointer, output_pointer, void_function, zero, coordinate] = [(); 5].map(|_| id());
+    let [main, label, o_color] = [(); 3].map(|_| id());
+    let textures = (0..texture_count).map(|_| id()).collect::<Vec<_>>();
+
+    let instruction = |opcode, result_type, result_id, operands: &[u32]| {
+        Instruction::new(opcode, result_type, result_id, operands.to_vec())
+    };
+    let mut instructions = vec![
+        instruction(OP_CAPABILITY, None, None, &[1]),
+        instruction(OP_MEMORY_MODEL, None, None, &[0, 1]),
+        // "main"
+        instruction(
+            OP_ENTRY_POINT,
+            None,
+            None,
+            &[4, main, 0x6e69616d, 0, o_color],
+        ),
+        instruction(OP_EXECUTION_MODE, None, None, &[main, 7]),
+        instruction(OP_DECORATE, None, None, &[o_color, DECORATION_LOCATION, 0]),
+    ];
+    for (binding, &texture) in textures.iter().enumerate() {
+        instructions.extend([
+            instruction(
+                OP_DECORATE,
+                None,
+                None,
+                &[texture, DECORATION_DESCRIPTOR_SET, 0],
+            ),
+            instruction(
+                OP_DECORATE,
+                None,
+                None,
+                &[texture, DECORATION_BINDING, binding as u32],
+            ),
+        ]);
+    }
+    instructions.extend([
+        instruction(OP_TYPE_VOID, None, Some(void), &[]),
+        instruction(OP_TYPE_FUNCTION, None, Some(void_function), &[void]),
+        instruction(OP_TYPE_FLOAT, None, Some(float), &[32]),
+        instruction(OP_TYPE_VECTOR, None, Some(v2float), &[float, 2]),
+        instruction(OP_TYPE_VECTOR, None, Some(v4float), &[float, 4]),
+        instruction(OP_TYPE_IMAGE, None, Some(image), &[float, 1, 2, 0, 0, 1, 0]),
+        instruction(OP_TYPE_SAMPLED_IMAGE, None, Some(sampled_image), &[image]),
+        instruction(
+            OP_TYPE_POINTER,
+            None,
+            Some(pointer),
+            &[STORAGE_CLASS_UNIFORM_CONSTANT, sampled_image],
+        ),
+        instruction(
+            OP_TYPE_POINTER,
+            None,
+            Some(output_pointer),
+            &[STORAGE_CLASS_OUTPUT, v4float],
+        ),
+        instruction(OP_CONSTANT, Some(float), Some(zero), &[0]),
+        instruction(
+            OP_CONSTANT_COMPOSITE,
+            Some(v2float),
+            Some(coordinate),
+            &[zero, zero],
+        ),
+        instruction(
+            OP_VARIABLE,
+            Some(output_pointer),
+            Some(o_color),
+            &[STORAGE_CLASS_OUTPUT],
+        ),
+    ]);
+    for &texture in textures.iter() {
+        instructions.push(instruction(
+            OP_VARIABLE,
+            Some(pointer),
+            Some(texture),
+            &[STORAGE_CLASS_UNIFORM_CONSTANT],
+        ));
+    }
+
+    instructions.extend([
+        instruction(OP_FUNCTION, Some(void), Some(main), &[0, void_function]),
+        instruction(OP_LABEL, None, Some(label), &[]),
+    ]);
+    for (idx, &texture) in textures.iter().enumerate() {
+        for _ in 0..SAMPLES_PER_TEXTURE {
+            let [loaded, sampled] = [(); 2].map(|_| id());
+            instructions.extend([
+                instruction(OP_LOAD, Some(sampled_image), Some(loaded), &[texture]),
+                instruction(
+                    OP_IMAGE_SAMPLE_IMPLICIT_LOD,
+                    Some(v4float),
+                    Some(sampled),
+                    &[loaded, coordinate],
+                ),
+                instruction(OP_STORE, None, None, &[o_color, sampled]),
+            ]);
+        }
+        if idx % 4 == 0 {
+            let [loaded, sampled] = [(); 2].map(|_| id());
+            instructions.extend([
+                instruction(OP_LOAD, Some(sampled_image), Some(loaded), &[texture]),
+                instruction(
+                    OP_IMAGE_SAMPLE_DREF_IMPLICIT_LOD,
+                    Some(float),
+                    Some(sampled),
+                    &[loaded, coordinate, zero],
+                ),
+            ]);
+        }
+    }
+    instructions.extend([
+        instruction(OP_RETURN, None, None, &[]),
+        instruction(OP_FUNCTION_END, None, None, &[]),
+    ]);
+
+    Module {
+        header: [0x07230203, 0x00010000, 0, id(), 0],
+        instructions,
+    }
+    .assemble()
+    .unwrap()
+}
+
+fn main() {
+    let options = Options::default();
+    println!(
+        "{:>9} {:>9} {:>12} {:>14}",
+        "textures", "words", "time", "ns per word"
+    );
+    for texture_count in [250, 500, 1000, 2000, 4000] {
+        let spv = generate_module(texture_count);
+
+        let mut best = Duration::MAX;
+        for _ in 0..RUNS {
+            let start = Instant::now();
+            std::hint::black_box(webgpu_transform(std::hint::black_box(&spv), &options).unwrap());
+            best = best.min(start.elapsed());
+        }
+
+        println!(
+            "{:>9} {:>9} {:>12.2?} {:>14.1}",
+            texture_count,
+            spv.len(),
+            best,
+            best.as_nanos() as f64 / spv.len() as f64
+        );
+    }
+}
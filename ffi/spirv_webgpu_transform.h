@@ -9,9 +9,18 @@ typedef void* TransformCorrectionMap;
 
 #define SPIRV_WEBGPU_TRANFORM_CORRECTION_MAP_NULL NULL
 
+// Where new sampler and texture bindings go, see `BindingAllocator`
+#define SPIRV_WEBGPU_TRANSFORM_BINDING_SHIFT 0
+#define SPIRV_WEBGPU_TRANSFORM_BINDING_APPEND 1
+#define SPIRV_WEBGPU_TRANSFORM_BINDING_EXTRA_DESCRIPTOR_SET 2
+// Use the set after the highest one in use with SPIRV_WEBGPU_TRANSFORM_BINDING_EXTRA_DESCRIPTOR_SET
+#define SPIRV_WEBGPU_TRANSFORM_EXTRA_DESCRIPTOR_SET_AUTO UINT32_MAX
+
 void spirv_webgpu_transform_combimgsampsplitter_alloc(uint32_t* in_spv, uint32_t in_count, uint32_t** out_spv, uint32_t* out_count, TransformCorrectionMap* correction_map);
+void spirv_webgpu_transform_combimgsampsplitter_alloc_with_bindings(uint32_t* in_spv, uint32_t in_count, uint32_t** out_spv, uint32_t* out_count, TransformCorrectionMap* correction_map, uint32_t binding_strategy, uint32_t extra_descriptor_set);
 void spirv_webgpu_transform_combimgsampsplitter_free(uint32_t* out_spv);
 void spirv_webgpu_transform_drefsplitter_alloc(uint32_t* in_spv, uint32_t in_count, uint32_t** out_spv, uint32_t* out_count, TransformCorrectionMap* correction_map);
+void spirv_webgpu_transform_drefsplitter_alloc_with_bindings(uint32_t* in_spv, uint32_t in_count, uint32_t** out_spv, uint32_t* out_count, TransformCorrectionMap* correction_map, uint32_t binding_strategy, uint32_t extra_descriptor_set);
 void spirv_webgpu_transform_drefsplitter_free(uint32_t* out_spv);
 
 void spirv_webgpu_transform_correction_map_free(TransformCorrectionMap correction_map);
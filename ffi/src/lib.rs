@@ -1,10 +1,32 @@
 #![allow(clippy::missing_safety_doc)]
 
 use core::{ffi, ptr, slice};
-use spirv_webgpu_transform::{CorrectionMap, combimgsampsplitter, drefsplitter};
+use spirv_webgpu_transform::{
+    AppendBindings, BindingAllocator, CorrectionMap, ExtraDescriptorSet, ShiftBindings,
+    combimgsampsplitter_with_allocator, drefsplitter_with_allocator,
+};
 
 type TransformCorrectionMap = *mut ffi::c_void;
 
+pub const SPIRV_WEBGPU_TRANSFORM_BINDING_SHIFT: u32 = 0;
+pub const SPIRV_WEBGPU_TRANSFORM_BINDING_APPEND: u32 = 1;
+pub const SPIRV_WEBGPU_TRANSFORM_BINDING_EXTRA_DESCRIPTOR_SET: u32 = 2;
+
+// `extra_descriptor_set` is only used by SPIRV_WEBGPU_TRANSFORM_BINDING_EXTRA_DESCRIPTOR_SET,
+// u32::MAX picks the set after the highest one in use.
+fn binding_allocator(
+    binding_strategy: u32,
+    extra_descriptor_set: u32,
+) -> Box<dyn BindingAllocator> {
+    match binding_strategy {
+        SPIRV_WEBGPU_TRANSFORM_BINDING_APPEND => Box::new(AppendBindings),
+        SPIRV_WEBGPU_TRANSFORM_BINDING_EXTRA_DESCRIPTOR_SET => Box::new(ExtraDescriptorSet {
+            descriptor_set: (extra_descriptor_set != u32::MAX).then_some(extra_descriptor_set),
+        }),
+        _ => Box::new(ShiftBindings),
+    }
+}
+
 pub unsafe fn alloc_or_pass_correction_map(
     map: *mut TransformCorrectionMap,
 ) -> &'static mut Option<CorrectionMap> {
@@ -34,11 +56,35 @@ pub unsafe extern "C" fn spirv_webgpu_transform_combimgsampsplitter_alloc(
     out_spv: *mut *const u32,
     out_count: *mut u32,
     correction_map: *mut TransformCorrectionMap,
+) {
+    unsafe {
+        spirv_webgpu_transform_combimgsampsplitter_alloc_with_bindings(
+            in_spv,
+            in_count,
+            out_spv,
+            out_count,
+            correction_map,
+            SPIRV_WEBGPU_TRANSFORM_BINDING_SHIFT,
+            u32::MAX,
+        )
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirv_webgpu_transform_combimgsampsplitter_alloc_with_bindings(
+    in_spv: *const u32,
+    in_count: u32,
+    out_spv: *mut *const u32,
+    out_count: *mut u32,
+    correction_map: *mut TransformCorrectionMap,
+    binding_strategy: u32,
+    extra_descriptor_set: u32,
 ) {
     let correction_map = unsafe { alloc_or_pass_correction_map(correction_map) };
+    let mut allocator = binding_allocator(binding_strategy, extra_descriptor_set);
 
     let in_spv = unsafe { slice::from_raw_parts(in_spv, in_count as usize) };
-    match combimgsampsplitter(in_spv, correction_map) {
+    match combimgsampsplitter_with_allocator(in_spv, correction_map, allocator.as_mut()) {
         Ok(spv) => unsafe {
             *out_count = spv.len() as u32;
             let leaked = Box::leak(spv.into_boxed_slice());
@@ -63,11 +109,35 @@ pub unsafe extern "C" fn spirv_webgpu_transform_drefsplitter_alloc(
     out_spv: *mut *const u32,
     out_count: *mut u32,
     correction_map: *mut TransformCorrectionMap,
+) {
+    unsafe {
+        spirv_webgpu_transform_drefsplitter_alloc_with_bindings(
+            in_spv,
+            in_count,
+            out_spv,
+            out_count,
+            correction_map,
+            SPIRV_WEBGPU_TRANSFORM_BINDING_SHIFT,
+            u32::MAX,
+        )
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn spirv_webgpu_transform_drefsplitter_alloc_with_bindings(
+    in_spv: *const u32,
+    in_count: u32,
+    out_spv: *mut *const u32,
+    out_count: *mut u32,
+    correction_map: *mut TransformCorrectionMap,
+    binding_strategy: u32,
+    extra_descriptor_set: u32,
 ) {
     let correction_map = unsafe { alloc_or_pass_correction_map(correction_map) };
+    let mut allocator = binding_allocator(binding_strategy, extra_descriptor_set);
 
     let in_spv = unsafe { slice::from_raw_parts(in_spv, in_count as usize) };
-    match drefsplitter(in_spv, correction_map) {
+    match drefsplitter_with_allocator(in_spv, correction_map, allocator.as_mut()) {
         Ok(spv) => unsafe {
             *out_count = spv.len() as u32;
             let leaked = Box::leak(spv.into_boxed_slice());
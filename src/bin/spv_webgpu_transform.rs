@@ -4,7 +4,7 @@ fn main() {
     let args: Vec<String> = env::args().collect();
 
     if args.len() != 4 {
-        eprintln!("Usage: spv_webgpu_transform <combimg|dref> <input.spv> <output.spv>");
+        eprintln!("Usage: spv_webgpu_transform <all|combimg|dref> <input.spv> <output.spv>");
         process::exit(1);
     }
 
@@ -15,20 +15,37 @@ fn main() {
 
     // ------
 
-    let spv = spirv_webgpu_transform::u8_slice_to_u32_vec(&spv_bytes);
+    let spv = match spirv_webgpu_transform::u8_slice_to_u32_vec(&spv_bytes) {
+        Ok(spv) => spv,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", input_path, e);
+            process::exit(1)
+        }
+    };
 
     let mut out_correction_map = None;
 
     let out_spv = match mode.as_str() {
-        "combimg" => {
-            spirv_webgpu_transform::combimgsampsplitter(&spv, &mut out_correction_map).unwrap()
+        "all" => {
+            spirv_webgpu_transform::webgpu_transform(&spv, &Default::default()).map(|output| {
+                out_correction_map = output.corrections;
+                output.spv
+            })
         }
-        "dref" => spirv_webgpu_transform::drefsplitter(&spv, &mut out_correction_map).unwrap(),
+        "combimg" => spirv_webgpu_transform::combimgsampsplitter(&spv, &mut out_correction_map),
+        "dref" => spirv_webgpu_transform::drefsplitter(&spv, &mut out_correction_map),
         mode => {
             eprintln!("unknown mode {:?}", mode);
             process::exit(1)
         }
     };
+    let out_spv = match out_spv {
+        Ok(out_spv) => out_spv,
+        Err(e) => {
+            eprintln!("Failed to transform {}: {}", input_path, e);
+            process::exit(1)
+        }
+    };
     let out_spv_bytes = spirv_webgpu_transform::u32_slice_to_u8_vec(&out_spv);
 
     // ------
@@ -36,6 +53,14 @@ fn main() {
     eprintln!("Writing patched result to {}", output_path);
     fs::write(output_path, out_spv_bytes).unwrap();
 
+    // With serde, the correction map is printed as JSON that can be stored next to the output.
+    #[cfg(feature = "serde")]
+    if let Some(correction_map) = out_correction_map {
+        eprintln!("Finished, patch summary: \n");
+        println!("{}", serde_json::to_string_pretty(&correction_map).unwrap());
+        return;
+    }
+
     // Remember to sort your hash maps!
     if let Some(correction_map) = out_correction_map {
         eprintln!("Finished, patch summary: \n");
@@ -48,7 +73,17 @@ fn main() {
             let mut bindings = set.bindings.iter().collect::<Vec<_>>();
             bindings.sort_by_key(|(k, _)| **k);
             for (binding_num, binding) in bindings {
-                println!("\tBinding {} <- {:?}", binding_num, binding.corrections);
+                let locations = binding
+                    .locations
+                    .iter()
+                    .map(|location| format!("({}, {})", location.descriptor_set, location.binding))
+                    .collect::<Vec<_>>();
+                println!(
+                    "\tBinding {} <- {:?} at {}",
+                    binding_num,
+                    binding.corrections,
+                    locations.join(", ")
+                );
             }
         }
     } else {
@@ -0,0 +1,250 @@
+use super::*;
+
+/// A descriptor set and binding pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DescriptorBinding {
+    pub descriptor_set: u32,
+    pub binding: u32,
+}
+
+/// A variable that was bound before the transform.
+#[derive(Debug, Clone, Copy)]
+pub struct BoundVariable {
+    pub id: u32,
+    pub location: DescriptorBinding,
+}
+
+/// A variable created by the transform, split off of the bound variable `original_id`.
+#[derive(Debug, Clone, Copy)]
+pub struct NewVariable {
+    pub id: u32,
+    pub original_id: u32,
+    /// Where `original_id` was bound before the transform.
+    pub original: DescriptorBinding,
+    pub correction_type: CorrectionType,
+}
+
+/// Decides where the variables created by a transform are bound, and where that leaves the
+/// variables that were already there.
+pub trait BindingAllocator {
+    /// `variables` are bound as they were before the transform, `new_variables` are sorted by
+    /// their original binding.
+    /// Every new variable must be given a binding that no other variable ends up at.
+    /// Existing variables that are not given a binding keep the one they had.
+    fn allocate(
+        &mut self,
+        variables: &[BoundVariable],
+        new_variables: &[NewVariable],
+    ) -> Result<HashMap<u32, DescriptorBinding>, TransformError>;
+}
+
+impl<T: BindingAllocator + ?Sized> BindingAllocator for &mut T {
+    fn allocate(
+        &mut self,
+        variables: &[BoundVariable],
+        new_variables: &[NewVariable],
+    ) -> Result<HashMap<u32, DescriptorBinding>, TransformError> {
+        (**self).allocate(variables, new_variables)
+    }
+}
+
+impl<T: BindingAllocator + ?Sized> BindingAllocator for Box<T> {
+    fn allocate(
+        &mut self,
+        variables: &[BoundVariable],
+        new_variables: &[NewVariable],
+    ) -> Result<HashMap<u32, DescriptorBinding>, TransformError> {
+        (**self).allocate(variables, new_variables)
+    }
+}
+
+/// New variables are bound right after the variable they were split off of, and every later
+/// binding in the descriptor set is shifted up to make room.
+/// This is the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShiftBindings;
+
+impl BindingAllocator for ShiftBindings {
+    fn allocate(
+        &mut self,
+        variables: &[BoundVariable],
+        new_variables: &[NewVariable],
+    ) -> Result<HashMap<u32, DescriptorBinding>, TransformError> {
+        let mut allocation = HashMap::new();
+
+        let descriptor_sets = new_variables
+            .iter()
+            .map(|variable| variable.original.descriptor_set)
+            .collect::<HashSet<_>>();
+        for descriptor_set in descriptor_sets {
+            let mut bindings = variables
+                .iter()
+                .filter(|variable| variable.location.descriptor_set == descriptor_set)
+                .map(|variable| (variable.id, variable.location.binding))
+                .chain(
+                    new_variables
+                        .iter()
+                        .filter(|variable| variable.original.descriptor_set == descriptor_set)
+                        .map(|variable| (variable.id, variable.original.binding.saturating_add(1))),
+                )
+                .collect::<Vec<_>>();
+            // We can assume that our new variables will have a greater instruction ID than the
+            // original variables. They take the binding they were given, and push the original
+            // variable already there up.
+            bindings.sort_by_key(|&(id, binding)| (binding, std::cmp::Reverse(id)));
+
+            let mut prev_binding = -1;
+            let mut increment = 0;
+            for (id, binding) in bindings {
+                if binding as i32 == prev_binding {
+                    increment += 1;
+                }
+                allocation.insert(
+                    id,
+                    DescriptorBinding {
+                        descriptor_set,
+                        binding: binding.saturating_add(increment),
+                    },
+                );
+                prev_binding = binding as i32;
+            }
+        }
+
+        Ok(allocation)
+    }
+}
+
+/// New variables are bound after the highest binding of the descriptor set they were split off
+/// of, existing bindings are left untouched.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AppendBindings;
+
+impl BindingAllocator for AppendBindings {
+    fn allocate(
+        &mut self,
+        variables: &[BoundVariable],
+        new_variables: &[NewVariable],
+    ) -> Result<HashMap<u32, DescriptorBinding>, TransformError> {
+        let mut next_bindings = HashMap::new();
+        for variable in variables.iter() {
+            let next_binding = next_bindings
+                .entry(variable.location.descriptor_set)
+                .or_insert(0);
+            *next_binding = (*next_binding).max(variable.location.binding as u64 + 1);
+        }
+
+        let mut allocation = HashMap::new();
+        for variable in new_variables.iter() {
+            let descriptor_set = variable.original.descriptor_set;
+            let next_binding = next_bindings.entry(descriptor_set).or_insert(0);
+            let binding =
+                u32::try_from(*next_binding).map_err(|_| TransformError::BindingAllocation {
+                    id: variable.id,
+                    location: variable.original,
+                    reason: "binding overflows",
+                })?;
+            *next_binding += 1;
+            allocation.insert(
+                variable.id,
+                DescriptorBinding {
+                    descriptor_set,
+                    binding,
+                },
+            );
+        }
+
+        Ok(allocation)
+    }
+}
+
+/// New variables are bound to a descriptor set of their own, after any binding already in it,
+/// existing bindings are left untouched.
+/// When splitting combined image samplers, this places every new sampler into one set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExtraDescriptorSet {
+    /// Defaults to the set after the highest descriptor set in use.
+    pub descriptor_set: Option<u32>,
+}
+
+impl BindingAllocator for ExtraDescriptorSet {
+    fn allocate(
+        &mut self,
+        variables: &[BoundVariable],
+        new_variables: &[NewVariable],
+    ) -> Result<HashMap<u32, DescriptorBinding>, TransformError> {
+        let mut allocation = HashMap::new();
+        let Some(first_variable) = new_variables.first() else {
+            return Ok(allocation);
+        };
+
+        let descriptor_set = match self.descriptor_set {
+            Some(descriptor_set) => descriptor_set,
+            None => variables
+                .iter()
+                .map(|variable| variable.location.descriptor_set)
+                .max()
+                .map_or(Some(0), |descriptor_set| descriptor_set.checked_add(1))
+                .ok_or(TransformError::BindingAllocation {
+                    id: first_variable.id,
+                    location: first_variable.original,
+                    reason: "descriptor set overflows",
+                })?,
+        };
+
+        // - Running more than one transform with the same set fills it up
+        let first_binding = variables
+            .iter()
+            .filter(|variable| variable.location.descriptor_set == descriptor_set)
+            .map(|variable| variable.location.binding as u64 + 1)
+            .max()
+            .unwrap_or(0);
+        for (binding, variable) in (first_binding..).zip(new_variables.iter()) {
+            let binding =
+                u32::try_from(binding).map_err(|_| TransformError::BindingAllocation {
+                    id: variable.id,
+                    location: variable.original,
+                    reason: "binding overflows",
+                })?;
+            allocation.insert(
+                variable.id,
+                DescriptorBinding {
+                    descriptor_set,
+                    binding,
+                },
+            );
+        }
+
+        Ok(allocation)
+    }
+}
+
+/// New variables are bound where the table says, existing bindings are left untouched.
+/// Entries are keyed by where the variable that was split was bound before the transform, and
+/// the kind of split.
+#[derive(Debug, Clone, Default)]
+pub struct ExplicitBindings {
+    pub bindings: HashMap<(DescriptorBinding, CorrectionType), DescriptorBinding>,
+}
+
+impl BindingAllocator for ExplicitBindings {
+    fn allocate(
+        &mut self,
+        _variables: &[BoundVariable],
+        new_variables: &[NewVariable],
+    ) -> Result<HashMap<u32, DescriptorBinding>, TransformError> {
+        new_variables
+            .iter()
+            .map(|variable| {
+                self.bindings
+                    .get(&(variable.original, variable.correction_type))
+                    .map(|&location| (variable.id, location))
+                    .ok_or(TransformError::BindingAllocation {
+                        id: variable.id,
+                        location: variable.original,
+                        reason: "no explicit binding was given",
+                    })
+            })
+            .collect()
+    }
+}
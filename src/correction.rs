@@ -1,24 +1,323 @@
 use super::*;
 
 #[repr(u16)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum CorrectionType {
     SplitCombined = 0,
     SplitDrefRegular = 1,
     SplitDrefComparison = 2,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CorrectionBinding {
     pub corrections: Vec<CorrectionType>,
+    /// Where the original variable is bound after the transform, followed by where the variable
+    /// created by each of `corrections` is bound.
+    pub locations: Vec<DescriptorBinding>,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CorrectionSet {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_sorted"))]
     pub bindings: HashMap<u32, CorrectionBinding>,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CorrectionMap {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_sorted"))]
     pub sets: HashMap<u32, CorrectionSet>,
+    /// One record per bound variable of the original module, sorted by original binding.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub resources: Vec<CorrectionResource>,
+    /// The resources used by each entry point of the module, in module order.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub entry_points: Vec<CorrectionEntryPoint>,
+}
+
+/// What a bound variable holds, looking through arrays.
+/// Storage images are textures as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ResourceKind {
+    Texture,
+    Sampler,
+    CombinedImageSampler,
+    Other,
+}
+
+/// Mirrors WebGPU's `GPUTextureViewDimension`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TextureViewDimension {
+    #[cfg_attr(feature = "serde", serde(rename = "1d"))]
+    D1,
+    #[cfg_attr(feature = "serde", serde(rename = "2d"))]
+    D2,
+    #[cfg_attr(feature = "serde", serde(rename = "2d-array"))]
+    D2Array,
+    #[cfg_attr(feature = "serde", serde(rename = "cube"))]
+    Cube,
+    #[cfg_attr(feature = "serde", serde(rename = "cube-array"))]
+    CubeArray,
+    #[cfg_attr(feature = "serde", serde(rename = "3d"))]
+    D3,
+}
+
+/// Mirrors WebGPU's `GPUTextureSampleType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum TextureSampleType {
+    /// Sampled through a sampler.
+    Float,
+    /// Only ever fetched from or queried.
+    UnfilterableFloat,
+    Depth,
+    Sint,
+    Uint,
+}
+
+/// Mirrors WebGPU's `GPUSamplerBindingType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum SamplerBindingType {
+    Filtering,
+    /// Only ever combined with textures that cannot be filtered.
+    NonFiltering,
+    /// Used for depth comparisons.
+    Comparison,
+}
+
+/// Mirrors WebGPU's `GPUTextureBindingLayout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextureBindingInfo {
+    pub view_dimension: TextureViewDimension,
+    pub multisampled: bool,
+    pub sample_type: TextureSampleType,
+}
+
+/// The execution model of an entry point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExecutionModel {
+    #[cfg_attr(feature = "serde", serde(rename = "vertex"))]
+    Vertex,
+    #[cfg_attr(feature = "serde", serde(rename = "fragment"))]
+    Fragment,
+    #[cfg_attr(feature = "serde", serde(rename = "compute"))]
+    GLCompute,
+    /// Any other SPIR-V execution model, by its number.
+    #[cfg_attr(feature = "serde", serde(rename = "other"))]
+    Other(u32),
+}
+
+impl ExecutionModel {
+    pub fn from_spirv(execution_model: u32) -> Self {
+        match execution_model {
+            SPV_EXECUTION_MODEL_VERTEX => ExecutionModel::Vertex,
+            SPV_EXECUTION_MODEL_FRAGMENT => ExecutionModel::Fragment,
+            SPV_EXECUTION_MODEL_GL_COMPUTE => ExecutionModel::GLCompute,
+            other => ExecutionModel::Other(other),
+        }
+    }
+
+    pub fn to_spirv(self) -> u32 {
+        match self {
+            ExecutionModel::Vertex => SPV_EXECUTION_MODEL_VERTEX,
+            ExecutionModel::Fragment => SPV_EXECUTION_MODEL_FRAGMENT,
+            ExecutionModel::GLCompute => SPV_EXECUTION_MODEL_GL_COMPUTE,
+            ExecutionModel::Other(other) => other,
+        }
+    }
+
+    /// The WebGPU stage, [ShaderStages::NONE] for execution models WebGPU does not have.
+    pub fn stage(self) -> ShaderStages {
+        match self {
+            ExecutionModel::Vertex => ShaderStages::VERTEX,
+            ExecutionModel::Fragment => ShaderStages::FRAGMENT,
+            ExecutionModel::GLCompute => ShaderStages::COMPUTE,
+            ExecutionModel::Other(_) => ShaderStages::NONE,
+        }
+    }
+}
+
+/// Mirrors WebGPU's `GPUShaderStageFlags`, stages are combined with `|`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct ShaderStages(pub u32);
+
+impl ShaderStages {
+    pub const NONE: ShaderStages = ShaderStages(0);
+    pub const VERTEX: ShaderStages = ShaderStages(1);
+    pub const FRAGMENT: ShaderStages = ShaderStages(2);
+    pub const COMPUTE: ShaderStages = ShaderStages(4);
+
+    pub fn contains(self, other: ShaderStages) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for ShaderStages {
+    type Output = ShaderStages;
+
+    fn bitor(self, other: ShaderStages) -> ShaderStages {
+        ShaderStages(self.0 | other.0)
+    }
+}
+
+impl std::ops::BitOrAssign for ShaderStages {
+    fn bitor_assign(&mut self, other: ShaderStages) {
+        self.0 |= other.0;
+    }
+}
+
+/// A variable bound after the transform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CorrectionVariable {
+    pub id: u32,
+    pub location: DescriptorBinding,
+    pub kind: ResourceKind,
+    /// Set for sampled textures and combined image samplers whose dimension WebGPU supports.
+    pub texture: Option<TextureBindingInfo>,
+    /// Set for samplers and combined image samplers.
+    pub sampler: Option<SamplerBindingType>,
+    /// The split that created this variable, `None` for the original variable.
+    pub correction: Option<CorrectionType>,
+    /// The stages of the entry points that use this variable, or the stages given to
+    /// [pipeline_transform].
+    pub visibility: ShaderStages,
+}
+
+/// Where a variable bound before the transform, and every variable split off of it, ended up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CorrectionResource {
+    pub original_id: u32,
+    pub original: DescriptorBinding,
+    /// The original variable, followed by the variables split off of it in the same order as
+    /// [CorrectionBinding::corrections].
+    pub variables: Vec<CorrectionVariable>,
+}
+
+impl CorrectionResource {
+    /// The texture part, the first variable that holds a texture.
+    pub fn texture(&self) -> Option<&CorrectionVariable> {
+        self.variables
+            .iter()
+            .find(|variable| variable.kind == ResourceKind::Texture)
+    }
+
+    /// The sampler part, the first variable that holds a sampler.
+    pub fn sampler(&self) -> Option<&CorrectionVariable> {
+        self.variables
+            .iter()
+            .find(|variable| variable.kind == ResourceKind::Sampler)
+    }
+}
+
+/// The resources one entry point uses after the transform, enough for the bind group layouts of
+/// a pipeline built from only this entry point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CorrectionEntryPoint {
+    pub name: String,
+    pub execution_model: ExecutionModel,
+    /// The resources of [CorrectionMap::resources] this entry point uses, holding only the
+    /// variables it uses. Their visibility is the stage of this entry point.
+    pub resources: Vec<CorrectionResource>,
+}
+
+impl CorrectionMap {
+    /// The resources used by the entry point called `name` with `execution_model`.
+    pub fn entry_point(
+        &self,
+        name: &str,
+        execution_model: ExecutionModel,
+    ) -> Option<&CorrectionEntryPoint> {
+        self.entry_points.iter().find(|entry_point| {
+            entry_point.name == name && entry_point.execution_model == execution_model
+        })
+    }
+
+    /// Add the corrections of another module that shares the same bindings, as done by
+    /// [pipeline_transform].
+    /// Resources are matched by their original binding, and variables by their binding and
+    /// correction. The stages of matching variables are combined, ids are kept from `self`.
+    /// Entry points are matched by their name and execution model.
+    pub fn merge(&mut self, other: &CorrectionMap) {
+        for (&set_num, other_set) in other.sets.iter() {
+            let set = self.sets.entry(set_num).or_default();
+            for (&binding_num, other_binding) in other_set.bindings.iter() {
+                let Some(binding) = set.bindings.get_mut(&binding_num) else {
+                    set.bindings.insert(binding_num, other_binding.clone());
+                    continue;
+                };
+                for (correction_idx, &correction) in other_binding.corrections.iter().enumerate() {
+                    let location = other_binding.locations.get(correction_idx + 1);
+                    let exists = binding
+                        .corrections
+                        .iter()
+                        .enumerate()
+                        .any(|(idx, &existing)| {
+                            existing == correction && binding.locations.get(idx + 1) == location
+                        });
+                    if !exists {
+                        binding.corrections.push(correction);
+                        binding.locations.extend(location);
+                    }
+                }
+            }
+        }
+
+        for other_resource in other.resources.iter() {
+            let Some(resource) = self
+                .resources
+                .iter_mut()
+                .find(|resource| resource.original == other_resource.original)
+            else {
+                self.resources.push(other_resource.clone());
+                continue;
+            };
+            for other_variable in other_resource.variables.iter() {
+                match resource.variables.iter_mut().find(|variable| {
+                    variable.location == other_variable.location
+                        && variable.correction == other_variable.correction
+                }) {
+                    Some(variable) => variable.visibility |= other_variable.visibility,
+                    None => resource.variables.push(*other_variable),
+                }
+            }
+        }
+        self.resources.sort_by_key(|resource| resource.original);
+
+        // - Entry points already in `self` are kept as they are
+        for other_entry_point in other.entry_points.iter() {
+            if self
+                .entry_point(&other_entry_point.name, other_entry_point.execution_model)
+                .is_none()
+            {
+                self.entry_points.push(other_entry_point.clone());
+            }
+        }
+    }
+}
+
+// Hash maps are written out in key order, so the same correction always serializes the same way.
+#[cfg(feature = "serde")]
+fn serialize_sorted<S, V>(map: &HashMap<u32, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    V: serde::Serialize,
+{
+    serializer.collect_map(map.iter().collect::<std::collections::BTreeMap<_, _>>())
 }
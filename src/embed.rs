@@ -0,0 +1,346 @@
+use super::*;
+
+// Every embedded correction map is a run of OpStrings, each starting with this, followed by the
+// format version, the index of the part, the number of parts and a part of the encoded map as hex
+// words.
+// OpString is used over OpModuleProcessed since it is also available in SPIR-V 1.0.
+const EMBED_PREFIX: &str = "spirv-webgpu-transform corrections ";
+const EMBED_VERSION: u32 = 1;
+// An instruction has at most 65535 words, so a large map is split over several OpStrings
+const EMBED_PART_WORDS: usize = 16384;
+
+/// Store `corrections` inside of `spv`, so that it can later be recovered with
+/// [read_embedded_corrections].
+/// The map is kept in unreferenced `OpString`s, which drivers and translators ignore.
+/// A map that was embedded before is replaced.
+pub fn embed_corrections(
+    spv: &[u32],
+    corrections: &CorrectionMap,
+) -> Result<Vec<u32>, TransformError> {
+    let mut module = Module::parse(spv)?;
+
+    let mut words = vec![];
+    encode_corrections(corrections, &mut words);
+    let parts = words.chunks(EMBED_PART_WORDS).collect::<Vec<_>>();
+    // - An empty map still needs its own part
+    let part_count = parts.len().max(1);
+    let payloads = (0..part_count).map(|part| {
+        format!(
+            "{}{} {} {} {}",
+            EMBED_PREFIX,
+            EMBED_VERSION,
+            part,
+            part_count,
+            parts
+                .get(part)
+                .into_iter()
+                .flat_map(|words| words.iter())
+                .map(|word| format!("{:08x}", word))
+                .collect::<String>()
+        )
+    });
+
+    // - Reuse the ids of a previous map, there must only ever be one
+    let mut ids = vec![];
+    module.instructions.retain(|instruction| {
+        if embedded_payload(instruction).is_some() {
+            ids.push(instruction.id());
+            false
+        } else {
+            true
+        }
+    });
+    let mut ids = ids.into_iter();
+
+    // - OpString must come before any other debug instruction in SPIR-V 1.0
+    let insert_idx = Layout::new(&module)
+        .previous_idx(LayoutSection::Debug, &[])
+        .map_or(0, |idx| idx + 1);
+    let mut strings = vec![];
+    for payload in payloads {
+        let id = ids.next().unwrap_or_else(|| {
+            let id = module.instruction_bound();
+            module.set_instruction_bound(id + 1);
+            id
+        });
+        strings.push(Instruction::new(
+            SPV_INSTRUCTION_OP_STRING,
+            None,
+            Some(id),
+            encode_string(&payload),
+        ));
+    }
+    module.instructions.splice(insert_idx..insert_idx, strings);
+
+    module.assemble()
+}
+
+/// Recover the correction map stored by [embed_corrections].
+/// Returns `None` if `spv` is not a module or does not hold a correction map.
+pub fn read_embedded_corrections(spv: &[u32]) -> Option<CorrectionMap> {
+    let module = Module::parse(spv).ok()?;
+
+    // - Every part must be there exactly once
+    let mut parts = vec![];
+    for payload in module.instructions.iter().filter_map(embedded_payload) {
+        let mut fields = payload.splitn(4, ' ');
+        let version = fields.next()?.parse::<u32>().ok()?;
+        let part = fields.next()?.parse::<usize>().ok()?;
+        let part_count = fields.next()?.parse::<usize>().ok()?;
+        let hex = fields.next()?;
+        if version != EMBED_VERSION || hex.len() % 8 != 0 {
+            return None;
+        }
+        parts.push((part, part_count, hex.to_owned()));
+    }
+    parts.sort_by_key(|&(part, _, _)| part);
+    let part_count = parts.first()?.1;
+    if parts.len() != part_count
+        || parts
+            .iter()
+            .enumerate()
+            .any(|(idx, &(part, count, _))| part != idx || count != part_count)
+    {
+        return None;
+    }
+
+    let mut words = vec![];
+    for (_, _, hex) in parts.iter() {
+        for idx in (0..hex.len()).step_by(8) {
+            words.push(u32::from_str_radix(hex.get(idx..idx + 8)?, 16).ok()?);
+        }
+    }
+
+    let mut words = words.into_iter();
+    let corrections = decode_corrections(&mut words)?;
+    // - Trailing words mean the payload was not written by us
+    words.next().is_none().then_some(corrections)
+}
+
+// What follows the prefix, if `instruction` is an embedded correction map.
+fn embedded_payload(instruction: &Instruction) -> Option<String> {
+    if instruction.opcode != SPV_INSTRUCTION_OP_STRING {
+        return None;
+    }
+    decode_string(&instruction.operands)?
+        .strip_prefix(EMBED_PREFIX)
+        .map(String::from)
+}
+
+// A nul terminated literal string, padded to a whole word.
+fn encode_string(string: &str) -> Vec<u32> {
+    let mut bytes = string.as_bytes().to_vec();
+    bytes.push(0);
+    bytes.resize(bytes.len().div_ceil(4) * 4, 0);
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+// Maps are written in key order, optional values as a presence flag followed by the value.
+fn encode_corrections(corrections: &CorrectionMap, out: &mut Vec<u32>) {
+    let mut sets = corrections.sets.iter().collect::<Vec<_>>();
+    sets.sort_by_key(|&(&set_num, _)| set_num);
+    out.push(sets.len() as u32);
+    for (&set_num, set) in sets {
+        let mut bindings = set.bindings.iter().collect::<Vec<_>>();
+        bindings.sort_by_key(|&(&binding_num, _)| binding_num);
+        out.extend([set_num, bindings.len() as u32]);
+        for (&binding_num, binding) in bindings {
+            out.extend([binding_num, binding.corrections.len() as u32]);
+            out.extend(binding.corrections.iter().map(|&ty| ty as u32));
+            out.push(binding.locations.len() as u32);
+            for location in binding.locations.iter() {
+                encode_location(location, out);
+            }
+        }
+    }
+
+    encode_resources(&corrections.resources, out);
+
+    out.push(corrections.entry_points.len() as u32);
+    for entry_point in corrections.entry_points.iter() {
+        let name = encode_string(&entry_point.name);
+        out.push(name.len() as u32);
+        out.extend(name);
+        out.push(entry_point.execution_model.to_spirv());
+        encode_resources(&entry_point.resources, out);
+    }
+}
+
+fn encode_resources(resources: &[CorrectionResource], out: &mut Vec<u32>) {
+    out.push(resources.len() as u32);
+    for resource in resources.iter() {
+        out.push(resource.original_id);
+        encode_location(&resource.original, out);
+        out.push(resource.variables.len() as u32);
+        for variable in resource.variables.iter() {
+            out.push(variable.id);
+            encode_location(&variable.location, out);
+            out.push(variable.kind as u32);
+            match variable.texture {
+                Some(texture) => out.extend([
+                    1,
+                    texture.view_dimension as u32,
+                    texture.multisampled as u32,
+                    texture.sample_type as u32,
+                ]),
+                None => out.push(0),
+            }
+            match variable.sampler {
+                Some(sampler) => out.extend([1, sampler as u32]),
+                None => out.push(0),
+            }
+            match variable.correction {
+                Some(correction) => out.extend([1, correction as u32]),
+                None => out.push(0),
+            }
+            out.push(variable.visibility.0);
+        }
+    }
+}
+
+fn encode_location(location: &DescriptorBinding, out: &mut Vec<u32>) {
+    out.extend([location.descriptor_set, location.binding]);
+}
+
+fn decode_corrections(words: &mut impl Iterator<Item = u32>) -> Option<CorrectionMap> {
+    let mut corrections = CorrectionMap::default();
+
+    for _ in 0..words.next()? {
+        let set_num = words.next()?;
+        let mut set = CorrectionSet::default();
+        for _ in 0..words.next()? {
+            let binding_num = words.next()?;
+            let mut binding = CorrectionBinding::default();
+            for _ in 0..words.next()? {
+                binding
+                    .corrections
+                    .push(decode_correction_type(words.next()?)?);
+            }
+            for _ in 0..words.next()? {
+                binding.locations.push(decode_location(words)?);
+            }
+            set.bindings.insert(binding_num, binding);
+        }
+        corrections.sets.insert(set_num, set);
+    }
+
+    corrections.resources = decode_resources(words)?;
+
+    for _ in 0..words.next()? {
+        let name_length = words.next()? as usize;
+        let name = words.by_ref().take(name_length).collect::<Vec<_>>();
+        if name.len() != name_length {
+            return None;
+        }
+        corrections.entry_points.push(CorrectionEntryPoint {
+            name: decode_string(&name)?,
+            execution_model: ExecutionModel::from_spirv(words.next()?),
+            resources: decode_resources(words)?,
+        });
+    }
+
+    Some(corrections)
+}
+
+fn decode_resources(words: &mut impl Iterator<Item = u32>) -> Option<Vec<CorrectionResource>> {
+    let mut resources = vec![];
+    for _ in 0..words.next()? {
+        let original_id = words.next()?;
+        let original = decode_location(words)?;
+        let mut variables = vec![];
+        for _ in 0..words.next()? {
+            let id = words.next()?;
+            let location = decode_location(words)?;
+            let kind = match words.next()? {
+                0 => ResourceKind::Texture,
+                1 => ResourceKind::Sampler,
+                2 => ResourceKind::CombinedImageSampler,
+                3 => ResourceKind::Other,
+                _ => return None,
+            };
+            let texture = match words.next()? {
+                0 => None,
+                1 => Some(TextureBindingInfo {
+                    view_dimension: match words.next()? {
+                        0 => TextureViewDimension::D1,
+                        1 => TextureViewDimension::D2,
+                        2 => TextureViewDimension::D2Array,
+                        3 => TextureViewDimension::Cube,
+                        4 => TextureViewDimension::CubeArray,
+                        5 => TextureViewDimension::D3,
+                        _ => return None,
+                    },
+                    multisampled: decode_bool(words.next()?)?,
+                    sample_type: match words.next()? {
+                        0 => TextureSampleType::Float,
+                        1 => TextureSampleType::UnfilterableFloat,
+                        2 => TextureSampleType::Depth,
+                        3 => TextureSampleType::Sint,
+                        4 => TextureSampleType::Uint,
+                        _ => return None,
+                    },
+                }),
+                _ => return None,
+            };
+            let sampler = match words.next()? {
+                0 => None,
+                1 => Some(match words.next()? {
+                    0 => SamplerBindingType::Filtering,
+                    1 => SamplerBindingType::NonFiltering,
+                    2 => SamplerBindingType::Comparison,
+                    _ => return None,
+                }),
+                _ => return None,
+            };
+            let correction = match words.next()? {
+                0 => None,
+                1 => Some(decode_correction_type(words.next()?)?),
+                _ => return None,
+            };
+            let visibility = ShaderStages(words.next()?);
+            variables.push(CorrectionVariable {
+                id,
+                location,
+                kind,
+                texture,
+                sampler,
+                correction,
+                visibility,
+            });
+        }
+        resources.push(CorrectionResource {
+            original_id,
+            original,
+            variables,
+        });
+    }
+
+    Some(resources)
+}
+
+fn decode_location(words: &mut impl Iterator<Item = u32>) -> Option<DescriptorBinding> {
+    Some(DescriptorBinding {
+        descriptor_set: words.next()?,
+        binding: words.next()?,
+    })
+}
+
+fn decode_correction_type(word: u32) -> Option<CorrectionType> {
+    match word {
+        0 => Some(CorrectionType::SplitCombined),
+        1 => Some(CorrectionType::SplitDrefRegular),
+        2 => Some(CorrectionType::SplitDrefComparison),
+        _ => None,
+    }
+}
+
+fn decode_bool(word: u32) -> Option<bool> {
+    match word {
+        0 => Some(false),
+        1 => Some(true),
+        _ => None,
+    }
+}
@@ -0,0 +1,142 @@
+use super::*;
+
+use std::fmt;
+
+/// Everything that can go wrong while transforming a module.
+/// Instruction errors carry the word offset (counted from the start of the module, header
+/// included) and the opcode of the offending instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransformError {
+    /// The header word at `word_offset` is invalid, `found` is `None` when the module is too short.
+    BadHeader {
+        word_offset: usize,
+        found: Option<u32>,
+    },
+    /// The instruction has a word count of zero or runs past the end of the module.
+    TruncatedInstruction { word_offset: usize, opcode: u16 },
+    /// The instruction is shorter than its opcode requires.
+    MissingOperand { word_offset: usize, opcode: u16 },
+    /// The instruction references a type id that was never declared.
+    MissingType {
+        word_offset: usize,
+        opcode: u16,
+        type_id: u32,
+    },
+    /// The module is valid SPIR-V, but uses a construct we do not know how to transform.
+    UnsupportedPattern {
+        word_offset: usize,
+        opcode: u16,
+        reason: &'static str,
+    },
+    /// A resource has a `Binding` decoration without a `DescriptorSet` decoration. Run
+    /// [DefaultDescriptorSetPass] first to place it in a default set.
+    BindingWithoutDescriptorSet { id: u32, binding: u32 },
+    /// The [BindingAllocator] could not bind the new variable `id`, which was split off of the
+    /// variable bound at `location`.
+    BindingAllocation {
+        id: u32,
+        location: DescriptorBinding,
+        reason: &'static str,
+    },
+    /// Growing the instruction would exceed the maximum word count of 65535.
+    WordCountOverflow { word_offset: usize, opcode: u16 },
+    /// The input given to [u8_slice_to_u32_vec] is not a whole number of words.
+    UnalignedByteCount { byte_count: usize },
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransformError::BadHeader {
+                word_offset,
+                found: Some(found),
+            } => write!(f, "bad spirv header word {}: {:#010x}", word_offset, found),
+            TransformError::BadHeader {
+                word_offset,
+                found: None,
+            } => write!(f, "spirv header is truncated at word {}", word_offset),
+            TransformError::TruncatedInstruction {
+                word_offset,
+                opcode,
+            } => write!(
+                f,
+                "instruction (opcode {}) at word {} is truncated",
+                opcode, word_offset
+            ),
+            TransformError::MissingOperand {
+                word_offset,
+                opcode,
+            } => write!(
+                f,
+                "instruction (opcode {}) at word {} is missing operands",
+                opcode, word_offset
+            ),
+            TransformError::MissingType {
+                word_offset,
+                opcode,
+                type_id,
+            } => write!(
+                f,
+                "instruction (opcode {}) at word {} references undeclared type %{}",
+                opcode, word_offset, type_id
+            ),
+            TransformError::UnsupportedPattern {
+                word_offset,
+                opcode,
+                reason,
+            } => write!(
+                f,
+                "instruction (opcode {}) at word {} is not supported: {}",
+                opcode, word_offset, reason
+            ),
+            TransformError::BindingWithoutDescriptorSet { id, binding } => write!(
+                f,
+                "%{} has Binding {} but no DescriptorSet decoration",
+                id, binding
+            ),
+            TransformError::BindingAllocation {
+                id,
+                location,
+                reason,
+            } => write!(
+                f,
+                "%{} (split off of set {} binding {}) could not be given a binding: {}",
+                id, location.descriptor_set, location.binding, reason
+            ),
+            TransformError::WordCountOverflow {
+                word_offset,
+                opcode,
+            } => write!(
+                f,
+                "instruction (opcode {}) at word {} would exceed the maximum word count",
+                opcode, word_offset
+            ),
+            TransformError::UnalignedByteCount { byte_count } => write!(
+                f,
+                "spirv is {} bytes long, which is not a multiple of 4",
+                byte_count
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+impl TransformError {
+    // Helpers for building errors from an instruction index into `module`.
+    pub(crate) fn missing_type(module: &Module, idx: usize, type_id: u32) -> Self {
+        TransformError::MissingType {
+            word_offset: module.word_offset(idx),
+            opcode: module.instructions[idx].opcode,
+            type_id,
+        }
+    }
+
+    pub(crate) fn unsupported(module: &Module, idx: usize, reason: &'static str) -> Self {
+        TransformError::UnsupportedPattern {
+            word_offset: module.word_offset(idx),
+            opcode: module.instructions[idx].opcode,
+            reason,
+        }
+    }
+}
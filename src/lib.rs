@@ -1,10 +1,17 @@
 use std::collections::{HashMap, HashSet};
 
+mod binding;
 mod correction;
+mod embed;
+mod error;
+mod module;
+mod pass;
+mod pipeline;
 mod splitcombined;
 mod splitdref;
 mod spv;
 mod util;
+mod webgpu;
 
 #[cfg(test)]
 mod test;
@@ -12,39 +19,48 @@ mod test;
 use spv::*;
 use util::*;
 
+pub use binding::*;
 pub use correction::*;
+pub use embed::*;
+pub use error::*;
+pub use module::*;
+pub use pass::*;
+pub use pipeline::*;
 pub use splitcombined::*;
 pub use splitdref::*;
+pub use webgpu::*;
 
 #[derive(Debug, Clone)]
 struct InstructionInsert {
-    previous_spv_idx: usize,
-    instruction: Vec<u32>,
+    previous_idx: usize,
+    instructions: Vec<Instruction>,
 }
 
 #[derive(Debug, Clone)]
-struct WordInsert {
+struct OperandInsert {
     idx: usize,
-    word: u32,
-    head_idx: usize,
+    operand_idx: usize,
+    operand: u32,
 }
 
 /// Helper to convert a `&[u8]` into a `Vec<u32>`.
-pub fn u8_slice_to_u32_vec(vec: &[u8]) -> Vec<u32> {
-    assert_eq!(
-        vec.len() % 4,
-        0,
-        "Input slice length must be a multiple of 4."
-    );
-
-    vec.chunks_exact(4)
+/// Fails with [TransformError::UnalignedByteCount] if `vec` is not a whole number of words.
+pub fn u8_slice_to_u32_vec(vec: &[u8]) -> Result<Vec<u32>, TransformError> {
+    if !vec.len().is_multiple_of(4) {
+        return Err(TransformError::UnalignedByteCount {
+            byte_count: vec.len(),
+        });
+    }
+
+    Ok(vec
+        .chunks_exact(4)
         .map(|chunk| {
             (chunk[0] as u32)
                 | ((chunk[1] as u32) << 8)
                 | ((chunk[2] as u32) << 16)
                 | ((chunk[3] as u32) << 24)
         })
-        .collect::<Vec<_>>()
+        .collect::<Vec<_>>())
 }
 
 /// Helper to convert a `&[u32]` into a `Vec<u8>`.
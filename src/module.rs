@@ -0,0 +1,241 @@
+use super::*;
+
+/// A single parsed instruction.
+/// The result type and result id are split from the remaining operands based on the opcode.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Instruction {
+    pub opcode: u16,
+    pub result_type: Option<u32>,
+    pub result_id: Option<u32>,
+    pub operands: Vec<u32>,
+}
+
+impl Instruction {
+    pub fn new(
+        opcode: u16,
+        result_type: Option<u32>,
+        result_id: Option<u32>,
+        operands: Vec<u32>,
+    ) -> Self {
+        Instruction {
+            opcode,
+            result_type,
+            result_id,
+            operands,
+        }
+    }
+
+    /// An `OpNop`, used to white out instructions before they are pruned.
+    pub fn nop() -> Self {
+        Instruction::new(SPV_INSTRUCTION_OP_NOP, None, None, vec![])
+    }
+
+    /// The result id, or `0` (never a valid id) if this instruction has none.
+    pub fn id(&self) -> u32 {
+        self.result_id.unwrap_or(0)
+    }
+
+    /// The result type id, or `0` (never a valid id) if this instruction has none.
+    pub fn type_id(&self) -> u32 {
+        self.result_type.unwrap_or(0)
+    }
+
+    pub fn word_count(&self) -> usize {
+        1 + self.result_type.is_some() as usize
+            + self.result_id.is_some() as usize
+            + self.operands.len()
+    }
+
+    // Expects `words` to be exactly one instruction.
+    fn from_words(words: &[u32]) -> Self {
+        let opcode = loword(words[0]);
+        let (has_result, has_result_type) = spv_has_result_and_type(opcode);
+
+        let mut words = words[1..].iter().copied();
+        let result_type = has_result_type.then(|| words.next()).flatten();
+        let result_id = has_result.then(|| words.next()).flatten();
+
+        Instruction::new(opcode, result_type, result_id, words.collect())
+    }
+
+    fn write_words(&self, out: &mut Vec<u32>) -> Option<()> {
+        let word_count = u16::try_from(self.word_count()).ok()?;
+        out.push(encode_word(word_count, self.opcode));
+        out.extend(self.result_type);
+        out.extend(self.result_id);
+        out.extend_from_slice(&self.operands);
+        Some(())
+    }
+}
+
+/// A parsed SPIR-V module.
+/// Parse with [Module::parse], edit the instructions in place, then [Module::assemble] back to
+/// words.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Module {
+    pub header: [u32; SPV_HEADER_LENGTH],
+    pub instructions: Vec<Instruction>,
+}
+
+// Upper bound for ids in a module, as specified by the SPIR-V universal limits.
+// Keeping the bound small also means our new ids can never overflow.
+const SPV_MAX_INSTRUCTION_BOUND: u32 = 0x3FFFFF;
+
+// The minimum word count of each instruction we read operands from.
+// Instructions we never inspect are only checked for their word count being in bounds.
+fn minimum_word_count(words: &[u32], instruction: u16) -> u16 {
+    let (has_result, has_result_type) = spv_has_result_and_type(instruction);
+    let minimum = match instruction {
+        SPV_INSTRUCTION_OP_TYPE_VOID | SPV_INSTRUCTION_OP_TYPE_SAMPLER => 2,
+        // Binding and DescriptorSet also carry a literal
+        SPV_INSTRUCTION_OP_DECORATE
+            if matches!(
+                words.get(2),
+                Some(&SPV_DECORATION_BINDING | &SPV_DECORATION_DESCRIPTOR_SET)
+            ) =>
+        {
+            4
+        }
+        SPV_INSTRUCTION_OP_TYPE_SAMPLED_IMAGE
+        | SPV_INSTRUCTION_OP_TYPE_RUNTIME_ARRAY
+        | SPV_INSTRUCTION_OP_TYPE_FUNCTION
+        | SPV_INSTRUCTION_OP_FUNCTION_PARAMTER
+        | SPV_INSTRUCTION_OP_DECORATE => 3,
+        SPV_INSTRUCTION_OP_TYPE_ARRAY
+        | SPV_INSTRUCTION_OP_TYPE_POINTER
+        | SPV_INSTRUCTION_OP_VARIABLE
+        | SPV_INSTRUCTION_OP_LOAD
+        | SPV_INSTRUCTION_OP_ACCESS_CHAIN
+        | SPV_INSTRUCTION_OP_IN_BOUNDS_ACCESS_CHAIN
+        | SPV_INSTRUCTION_OP_FUNCTION_CALL
+        | SPV_INSTRUCTION_OP_COPY_OBJECT
+        | SPV_INSTRUCTION_OP_IMAGE => 4,
+        SPV_INSTRUCTION_OP_FUNCTION
+        | SPV_INSTRUCTION_OP_SAMPLED_IMAGE
+        | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_IMPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_EXPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_PROJ_IMPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_PROJ_EXPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_GATHER
+        | SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_IMPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_EXPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SPARSE_GATHER => 5,
+        SPV_INSTRUCTION_OP_IMAGE_SAMPLE_DREF_IMPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_DREF_EXPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_PROJ_DREF_IMPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_PROJ_DREF_EXPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_DREF_GATHER
+        | SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_DREF_IMPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_DREF_EXPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SPARSE_DREF_GATHER
+        | SPV_INSTRUCTION_OP_SELECT => 6,
+        SPV_INSTRUCTION_OP_TYPE_IMAGE => 9,
+        _ => 1,
+    };
+    minimum.max(1 + has_result as u16 + has_result_type as u16)
+}
+
+impl Module {
+    /// Parse and validate the header and instruction stream of `spv`.
+    pub fn parse(spv: &[u32]) -> Result<Self, TransformError> {
+        if spv.len() < SPV_HEADER_LENGTH {
+            return Err(TransformError::BadHeader {
+                word_offset: spv.len(),
+                found: None,
+            });
+        }
+
+        let magic_number = spv[SPV_HEADER_MAGIC_NUM_OFFSET];
+        if magic_number != SPV_HEADER_MAGIC {
+            return Err(TransformError::BadHeader {
+                word_offset: SPV_HEADER_MAGIC_NUM_OFFSET,
+                found: Some(magic_number),
+            });
+        }
+
+        let instruction_bound = spv[SPV_HEADER_INSTRUCTION_BOUND_OFFSET];
+        if instruction_bound > SPV_MAX_INSTRUCTION_BOUND {
+            return Err(TransformError::BadHeader {
+                word_offset: SPV_HEADER_INSTRUCTION_BOUND_OFFSET,
+                found: Some(instruction_bound),
+            });
+        }
+
+        let mut header = [0; SPV_HEADER_LENGTH];
+        header.copy_from_slice(&spv[..SPV_HEADER_LENGTH]);
+
+        let mut instructions = vec![];
+        let mut spv_idx = SPV_HEADER_LENGTH;
+        while spv_idx < spv.len() {
+            let op = spv[spv_idx];
+            let word_count = hiword(op);
+            let instruction = loword(op);
+
+            if word_count == 0 || spv_idx + word_count as usize > spv.len() {
+                return Err(TransformError::TruncatedInstruction {
+                    word_offset: spv_idx,
+                    opcode: instruction,
+                });
+            }
+
+            let words = &spv[spv_idx..spv_idx + word_count as usize];
+            if word_count < minimum_word_count(words, instruction) {
+                return Err(TransformError::MissingOperand {
+                    word_offset: spv_idx,
+                    opcode: instruction,
+                });
+            }
+
+            instructions.push(Instruction::from_words(words));
+            spv_idx += word_count as usize;
+        }
+
+        Ok(Module {
+            header,
+            instructions,
+        })
+    }
+
+    /// Write the module back out as words.
+    pub fn assemble(&self) -> Result<Vec<u32>, TransformError> {
+        let mut spv = self.header.to_vec();
+        for instruction in self.instructions.iter() {
+            let word_offset = spv.len();
+            instruction
+                .write_words(&mut spv)
+                .ok_or(TransformError::WordCountOverflow {
+                    word_offset,
+                    opcode: instruction.opcode,
+                })?;
+        }
+        Ok(spv)
+    }
+
+    pub fn instruction_bound(&self) -> u32 {
+        self.header[SPV_HEADER_INSTRUCTION_BOUND_OFFSET]
+    }
+
+    pub fn set_instruction_bound(&mut self, instruction_bound: u32) {
+        self.header[SPV_HEADER_INSTRUCTION_BOUND_OFFSET] = instruction_bound;
+    }
+
+    /// Map each result id to the index of the instruction defining it.
+    pub fn definitions(&self) -> HashMap<u32, usize> {
+        self.instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, instruction)| instruction.result_id.map(|id| (id, idx)))
+            .collect()
+    }
+
+    /// The word offset of an instruction from the start of the module, header included.
+    pub fn word_offset(&self, instruction_idx: usize) -> usize {
+        SPV_HEADER_LENGTH
+            + self
+                .instructions
+                .iter()
+                .take(instruction_idx)
+                .map(Instruction::word_count)
+                .sum::<usize>()
+    }
+}
@@ -0,0 +1,113 @@
+use super::*;
+
+/// A rewrite of a parsed [Module] that a [PassManager] can chain with other passes.
+pub trait Pass {
+    /// A short name for the pass, such as `"split-combined"`.
+    fn name(&self) -> &str;
+
+    /// Rewrite `module` in place, and record anything the user needs to know about in
+    /// `corrections`.
+    /// `instruction_bound` is shared by every pass, new ids are taken from it and it must be
+    /// raised past every id handed out. The header of `module` is only updated once every pass
+    /// has run.
+    /// Instructions are removed by replacing them with [Instruction::nop], which keeps the indices
+    /// of every other instruction stable. They are pruned after the last pass at the latest.
+    fn run(
+        &mut self,
+        module: &mut Module,
+        instruction_bound: &mut u32,
+        corrections: &mut Option<CorrectionMap>,
+    ) -> Result<(), TransformError>;
+}
+
+/// Runs [Pass]es one after the other on a single parse of a module.
+#[derive(Default)]
+pub struct PassManager<'a> {
+    passes: Vec<Box<dyn Pass + 'a>>,
+}
+
+impl<'a> PassManager<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `pass` after every pass that was added before it.
+    pub fn add(&mut self, pass: impl Pass + 'a) -> &mut Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// The names of the passes, in the order they run.
+    pub fn names(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    /// Run every pass on `spv`, then remove the instructions they whited out and write the new
+    /// header.
+    /// `corrections` is left untouched if any pass fails.
+    pub fn run(
+        &mut self,
+        spv: &[u32],
+        corrections: &mut Option<CorrectionMap>,
+    ) -> Result<Vec<u32>, TransformError> {
+        let mut module = Module::parse(spv)?;
+        let mut instruction_bound = module.instruction_bound();
+        let mut new_corrections = corrections.clone();
+
+        for pass in self.passes.iter_mut() {
+            pass.run(&mut module, &mut instruction_bound, &mut new_corrections)?;
+        }
+
+        prune_noops(&mut module);
+        let out_spv = fuse_final(module, instruction_bound)?;
+        *corrections = new_corrections;
+        Ok(out_spv)
+    }
+}
+
+/// Replace every `OpDecorationGroup` with explicit decorations on the targets of the group, so
+/// that bindings shared through `OpGroupDecorate` are seen like any other binding.
+/// [combimgsampsplitter], [drefsplitter] and [webgpu_transform] run this first.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlattenDecorationGroupsPass;
+
+impl Pass for FlattenDecorationGroupsPass {
+    fn name(&self) -> &str {
+        "flatten-decoration-groups"
+    }
+
+    fn run(
+        &mut self,
+        module: &mut Module,
+        _: &mut u32,
+        _: &mut Option<CorrectionMap>,
+    ) -> Result<(), TransformError> {
+        util::flatten_decoration_groups(module);
+        Ok(())
+    }
+}
+
+/// Place every variable with a `Binding` decoration but no `DescriptorSet` decoration, as found in
+/// `GL_ARB_gl_spirv` modules, in `descriptor_set` by adding the missing decoration.
+/// The splitters need both decorations, [combimgsampsplitter], [drefsplitter] and
+/// [webgpu_transform] run this first.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultDescriptorSetPass {
+    pub descriptor_set: u32,
+}
+
+impl Pass for DefaultDescriptorSetPass {
+    fn name(&self) -> &str {
+        "default-descriptor-set"
+    }
+
+    fn run(
+        &mut self,
+        module: &mut Module,
+        _: &mut u32,
+        _: &mut Option<CorrectionMap>,
+    ) -> Result<(), TransformError> {
+        util::add_missing_descriptor_sets(module, self.descriptor_set);
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+use super::*;
+
+use std::collections::BTreeSet;
+
+/// One shader module of a pipeline.
+#[derive(Debug, Clone)]
+pub struct PipelineStage {
+    /// The stages the variables of this module are visible to.
+    pub stage: ShaderStages,
+    pub spv: Vec<u32>,
+    /// The corrections of earlier transforms of this module, updated along with `spv`.
+    pub corrections: Option<CorrectionMap>,
+}
+
+/// A transform that binds new variables with a [BindingAllocator], such as
+/// [combimgsampsplitter_with_allocator] and [drefsplitter_with_allocator].
+pub type AllocatingTransform = fn(
+    &[u32],
+    &mut Option<CorrectionMap>,
+    &mut dyn BindingAllocator,
+) -> Result<Vec<u32>, TransformError>;
+
+/// Run `transforms` one after the other on every module of a pipeline, so that they agree on one
+/// binding plan.
+/// For each transform, `allocator` is asked once for the bindings of all modules together, as
+/// though they were one module, then every module is rewritten to that plan, including modules
+/// that had nothing to split. The next transform starts from the rewritten modules, just like
+/// [webgpu_transform] chains its passes.
+/// Variables without a `DescriptorSet` decoration are placed in `default_descriptor_set` first,
+/// see [DefaultDescriptorSetPass].
+/// Returns the corrections of every stage merged with [CorrectionMap::merge]. Nothing is changed
+/// if any module fails.
+pub fn pipeline_transform(
+    stages: &mut [PipelineStage],
+    transforms: &[AllocatingTransform],
+    default_descriptor_set: u32,
+    allocator: &mut dyn BindingAllocator,
+) -> Result<CorrectionMap, TransformError> {
+    // 1. Place every variable in a descriptor set, the transforms would otherwise use set 0
+    let mut new_stages = stages.to_vec();
+    for stage in new_stages.iter_mut() {
+        let mut module = Module::parse(&stage.spv)?;
+        util::flatten_decoration_groups(&mut module);
+        util::add_missing_descriptor_sets(&mut module, default_descriptor_set);
+        stage.spv = module.assemble()?;
+    }
+
+    // 2. Transform the whole pipeline at once
+    for &transform in transforms.iter() {
+        new_stages = pipeline_transform_once(&new_stages, transform, allocator)?;
+    }
+
+    // 3. Record which stages see every variable
+    let mut merged_corrections = CorrectionMap::default();
+    for stage in new_stages.iter_mut() {
+        if let Some(corrections) = stage.corrections.as_mut() {
+            for variable in corrections
+                .resources
+                .iter_mut()
+                .flat_map(|resource| resource.variables.iter_mut())
+            {
+                variable.visibility = stage.stage;
+            }
+            merged_corrections.merge(corrections);
+        }
+    }
+
+    stages.clone_from_slice(&new_stages);
+    Ok(merged_corrections)
+}
+
+// Run a single transform on every module with one shared binding plan.
+fn pipeline_transform_once(
+    stages: &[PipelineStage],
+    transform: AllocatingTransform,
+    allocator: &mut dyn BindingAllocator,
+) -> Result<Vec<PipelineStage>, TransformError> {
+    // 1. Find what every module would split
+    let mut locations = BTreeSet::new();
+    let mut recorder = RecordNewVariables::default();
+    for stage in stages.iter() {
+        locations.extend(
+            util::bound_variables(&Module::parse(&stage.spv)?)?
+                .into_iter()
+                .map(|variable| variable.location),
+        );
+        transform(&stage.spv, &mut stage.corrections.clone(), &mut recorder)?;
+    }
+
+    // 2. Allocate everything at once
+    let mut planned = plan_bindings(locations, recorder.new_variables, allocator)?;
+
+    // 3. Rewrite every module to the plan
+    let mut new_stages = vec![];
+    for stage in stages.iter() {
+        let mut corrections = stage.corrections.clone();
+        planned.used = false;
+        let mut spv = transform(&stage.spv, &mut corrections, &mut planned)?;
+        // - Transforms skip modules with nothing to split, their bindings still need to move
+        if !planned.used {
+            let mut module = Module::parse(&spv)?;
+            if corrections.is_none() {
+                corrections = Some(util::initial_corrections(util::bound_variables(&module)?));
+            }
+            util::correct_decorate(CorrectDecorateIn {
+                new_module: &mut module,
+                new_variables: &[],
+                allocator: &mut planned,
+                corrections: &mut corrections,
+            })?;
+            spv = module.assemble()?;
+        }
+        new_stages.push(PipelineStage {
+            stage: stage.stage,
+            spv,
+            corrections,
+        });
+    }
+    Ok(new_stages)
+}
+
+// Remembers the new variables of a transform, and binds them anywhere that is free
+#[derive(Default)]
+struct RecordNewVariables {
+    new_variables: BTreeSet<(DescriptorBinding, CorrectionType)>,
+}
+
+impl BindingAllocator for RecordNewVariables {
+    fn allocate(
+        &mut self,
+        variables: &[BoundVariable],
+        new_variables: &[NewVariable],
+    ) -> Result<HashMap<u32, DescriptorBinding>, TransformError> {
+        self.new_variables.extend(
+            new_variables
+                .iter()
+                .map(|variable| (variable.original, variable.correction_type)),
+        );
+        AppendBindings.allocate(variables, new_variables)
+    }
+}
+
+// The shared plan, keyed by where variables were bound before the transform
+struct PlannedBindings {
+    locations: HashMap<DescriptorBinding, DescriptorBinding>,
+    new_locations: HashMap<(DescriptorBinding, CorrectionType), DescriptorBinding>,
+    used: bool,
+}
+
+impl BindingAllocator for PlannedBindings {
+    fn allocate(
+        &mut self,
+        variables: &[BoundVariable],
+        new_variables: &[NewVariable],
+    ) -> Result<HashMap<u32, DescriptorBinding>, TransformError> {
+        self.used = true;
+        let mut allocation = HashMap::new();
+        for variable in variables.iter() {
+            if let Some(&location) = self.locations.get(&variable.location) {
+                allocation.insert(variable.id, location);
+            }
+        }
+        for variable in new_variables.iter() {
+            let &location = self
+                .new_locations
+                .get(&(variable.original, variable.correction_type))
+                .ok_or(TransformError::BindingAllocation {
+                    id: variable.id,
+                    location: variable.original,
+                    reason: "it is not part of the pipeline's binding plan",
+                })?;
+            allocation.insert(variable.id, location);
+        }
+        Ok(allocation)
+    }
+}
+
+// Allocate as though every module was one, with made up ids in binding order.
+fn plan_bindings(
+    mut locations: BTreeSet<DescriptorBinding>,
+    new_variables: BTreeSet<(DescriptorBinding, CorrectionType)>,
+    allocator: &mut dyn BindingAllocator,
+) -> Result<PlannedBindings, TransformError> {
+    locations.extend(new_variables.iter().map(|&(original, _)| original));
+    let location_ids = locations
+        .iter()
+        .enumerate()
+        .map(|(idx, &location)| (location, idx as u32 + 1))
+        .collect::<HashMap<_, _>>();
+
+    let variables = locations
+        .iter()
+        .map(|location| BoundVariable {
+            id: location_ids[location],
+            location: *location,
+        })
+        .collect::<Vec<_>>();
+    // - New variables get greater ids than the variables they were split off of, just like in a
+    //   single module
+    let new_variables = new_variables
+        .iter()
+        .enumerate()
+        .map(|(idx, &(original, correction_type))| NewVariable {
+            id: (locations.len() + idx) as u32 + 1,
+            original_id: location_ids[&original],
+            original,
+            correction_type,
+        })
+        .collect::<Vec<_>>();
+
+    let mut allocation = allocator.allocate(&variables, &new_variables)?;
+    for variable in variables.iter() {
+        allocation.entry(variable.id).or_insert(variable.location);
+    }
+
+    // - No two variables of the pipeline may share a binding
+    let mut bound_ids = HashMap::new();
+    for variable in variables.iter() {
+        bound_ids.insert(allocation[&variable.id], variable.id);
+    }
+    let mut new_locations = HashMap::new();
+    for variable in new_variables.iter() {
+        let Some(&location) = allocation.get(&variable.id) else {
+            return Err(TransformError::BindingAllocation {
+                id: variable.id,
+                location: variable.original,
+                reason: "the binding allocator did not bind it",
+            });
+        };
+        if bound_ids.insert(location, variable.id).is_some() {
+            return Err(TransformError::BindingAllocation {
+                id: variable.id,
+                location: variable.original,
+                reason: "its binding is already in use",
+            });
+        }
+        new_locations.insert((variable.original, variable.correction_type), location);
+    }
+
+    Ok(PlannedBindings {
+        locations: variables
+            .iter()
+            .map(|variable| (variable.location, allocation[&variable.id]))
+            .collect(),
+        new_locations,
+        used: false,
+    })
+}
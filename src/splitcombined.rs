@@ -1,17 +1,31 @@
 use super::*;
 
+mod access_chain;
+mod dead_only;
+mod fetch_only;
+mod forward;
 mod function_call;
 mod function_parameter;
 mod load;
+mod recombine;
+mod type_array;
 mod type_function;
 mod type_pointer;
+mod value_parameter;
 mod variable;
 
+use access_chain::*;
+use dead_only::*;
+use fetch_only::*;
+use forward::*;
 use function_call::*;
 use function_parameter::*;
 use load::*;
+use recombine::*;
+use type_array::*;
 use type_function::*;
 use type_pointer::*;
+use value_parameter::*;
 use variable::*;
 
 /// Perform the operation on a `Vec<u32>`.
@@ -19,205 +33,379 @@ use variable::*;
 pub fn combimgsampsplitter(
     in_spv: &[u32],
     corrections: &mut Option<CorrectionMap>,
-) -> Result<Vec<u32>, ()> {
-    let spv = in_spv.to_owned();
+) -> Result<Vec<u32>, TransformError> {
+    combimgsampsplitter_with_allocator(in_spv, corrections, &mut ShiftBindings)
+}
+
+/// Same as [combimgsampsplitter], but new variables are bound by `allocator` instead of [ShiftBindings].
+pub fn combimgsampsplitter_with_allocator(
+    in_spv: &[u32],
+    corrections: &mut Option<CorrectionMap>,
+    allocator: &mut dyn BindingAllocator,
+) -> Result<Vec<u32>, TransformError> {
+    PassManager::new()
+        .add(FlattenDecorationGroupsPass)
+        .add(DefaultDescriptorSetPass::default())
+        .add(SplitCombinedPass { allocator })
+        .run(in_spv, corrections)
+}
 
-    let mut instruction_bound = spv[SPV_HEADER_INSTRUCTION_BOUND_OFFSET];
-    let magic_number = spv[SPV_HEADER_MAGIC_NUM_OFFSET];
+/// [combimgsampsplitter] as a [Pass].
+#[derive(Debug, Clone, Default)]
+pub struct SplitCombinedPass<A = ShiftBindings> {
+    pub allocator: A,
+}
+
+impl<A: BindingAllocator> Pass for SplitCombinedPass<A> {
+    fn name(&self) -> &str {
+        "split-combined"
+    }
+
+    fn run(
+        &mut self,
+        module: &mut Module,
+        instruction_bound: &mut u32,
+        corrections: &mut Option<CorrectionMap>,
+    ) -> Result<(), TransformError> {
+        split_combined(module, instruction_bound, corrections, &mut self.allocator)
+    }
+}
 
-    let spv_header = spv[0..SPV_HEADER_LENGTH].to_owned();
+// `out_module` is left untouched if there is nothing to split.
+fn split_combined(
+    out_module: &mut Module,
+    out_instruction_bound: &mut u32,
+    corrections: &mut Option<CorrectionMap>,
+    allocator: &mut dyn BindingAllocator,
+) -> Result<(), TransformError> {
+    let module = out_module.clone();
 
-    assert_eq!(magic_number, SPV_HEADER_MAGIC);
+    let mut instruction_bound = *out_instruction_bound;
 
     let mut instruction_inserts = vec![];
-    let mut word_inserts = vec![];
+    let mut operand_inserts = vec![];
 
-    let spv = spv.into_iter().skip(SPV_HEADER_LENGTH).collect::<Vec<_>>();
-    let mut new_spv = spv.clone();
+    let mut new_module = module.clone();
 
     let mut op_type_sampler_idx = None;
-    let mut first_op_deocrate_idx = None;
-    let mut first_op_type_void_idx = None;
 
     let mut op_type_image_idxs = vec![];
     let mut op_type_sampled_image_idxs = vec![];
+    let mut op_type_array_idxs = vec![];
     let mut op_type_pointer_idxs = vec![];
     let mut op_variables_idxs = vec![];
     let mut op_loads_idxs = vec![];
+    let mut op_access_chain_idxs = vec![];
     let mut op_decorate_idxs = vec![];
+    let mut non_uniform_decorate_idxs = HashMap::new();
     let mut op_type_function_idxs = vec![];
     let mut op_function_parameter_idxs = vec![];
     let mut op_function_call_idxs = vec![];
 
     // 1. Find locations instructions we need
-    let mut spv_idx = 0;
-    while spv_idx < spv.len() {
-        let op = spv[spv_idx];
-        let word_count = hiword(op);
-        let instruction = loword(op);
-
-        match instruction {
-            SPV_INSTRUCTION_OP_TYPE_VOID => {
-                first_op_type_void_idx = Some(spv_idx);
-            }
+    for (idx, instruction) in module.instructions.iter().enumerate() {
+        match instruction.opcode {
             SPV_INSTRUCTION_OP_TYPE_SAMPLER => {
-                op_type_sampler_idx = Some(spv_idx);
-                new_spv[spv_idx] = encode_word(word_count, SPV_INSTRUCTION_OP_NOP);
+                op_type_sampler_idx = Some(idx);
+                new_module.instructions[idx] = Instruction::nop();
             }
             SPV_INSTRUCTION_OP_TYPE_IMAGE => {
-                op_type_image_idxs.push(spv_idx);
+                op_type_image_idxs.push(idx);
             }
-            SPV_INSTRUCTION_OP_TYPE_SAMPLED_IMAGE => op_type_sampled_image_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_TYPE_POINTER => {
-                if spv[spv_idx + 2] == SPV_STORAGE_CLASS_UNIFORM_CONSTANT {
-                    op_type_pointer_idxs.push(spv_idx);
-                }
+            SPV_INSTRUCTION_OP_TYPE_SAMPLED_IMAGE => op_type_sampled_image_idxs.push(idx),
+            SPV_INSTRUCTION_OP_TYPE_ARRAY | SPV_INSTRUCTION_OP_TYPE_RUNTIME_ARRAY => {
+                op_type_array_idxs.push(idx)
+            }
+            SPV_INSTRUCTION_OP_TYPE_POINTER
+                if instruction.operands[0] == SPV_STORAGE_CLASS_UNIFORM_CONSTANT =>
+            {
+                op_type_pointer_idxs.push(idx);
+            }
+            SPV_INSTRUCTION_OP_VARIABLE => op_variables_idxs.push(idx),
+            SPV_INSTRUCTION_OP_LOAD => op_loads_idxs.push(idx),
+            SPV_INSTRUCTION_OP_ACCESS_CHAIN | SPV_INSTRUCTION_OP_IN_BOUNDS_ACCESS_CHAIN => {
+                op_access_chain_idxs.push(idx)
             }
-            SPV_INSTRUCTION_OP_VARIABLE => op_variables_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_LOAD => op_loads_idxs.push(spv_idx),
             SPV_INSTRUCTION_OP_DECORATE => {
-                op_decorate_idxs.push(spv_idx);
-                first_op_deocrate_idx.get_or_insert(spv_idx);
+                op_decorate_idxs.push(idx);
+                if instruction.operands[1] == SPV_DECORATION_NON_UNIFORM {
+                    non_uniform_decorate_idxs.insert(instruction.operands[0], idx);
+                }
             }
-            SPV_INSTRUCTION_OP_TYPE_FUNCTION => op_type_function_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_FUNCTION_PARAMTER => op_function_parameter_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_FUNCTION_CALL => op_function_call_idxs.push(spv_idx),
+            SPV_INSTRUCTION_OP_TYPE_FUNCTION => op_type_function_idxs.push(idx),
+            SPV_INSTRUCTION_OP_FUNCTION_PARAMTER => op_function_parameter_idxs.push(idx),
+            SPV_INSTRUCTION_OP_FUNCTION_CALL => op_function_call_idxs.push(idx),
 
             _ => {}
         }
-
-        spv_idx += word_count as usize;
     }
+    let underlying_image_ids = op_type_sampled_image_idxs
+        .iter()
+        .map(|&ts_idx| {
+            let ts = &module.instructions[ts_idx];
+            (ts.id(), ts.operands[0])
+        })
+        .collect::<HashMap<_, _>>();
 
     // 2. Insert OpTypeSampler and respective OpTypePointer if neccessary
 
     // - If there has been no OpTypeImage, there will be nothing to do
     if op_type_image_idxs.is_empty() {
-        return Ok(in_spv.to_vec());
+        return Ok(());
     };
 
     let op_type_sampler_res_id = if let Some(idx) = op_type_sampler_idx {
-        spv[idx + 1]
+        module.instructions[idx].id()
     } else {
         let op_type_sampler_res_id = instruction_bound;
         instruction_bound += 1;
         op_type_sampler_res_id
     };
 
+    // - OpTypeSampler has no dependencies, so it can go at the very start of the types section
+    let layout = Layout::new(&module);
+    let Some(types_start_idx) = layout.previous_idx(LayoutSection::Global, &[]) else {
+        return Err(TransformError::unsupported(
+            &module,
+            op_type_image_idxs[0],
+            "module does not declare OpMemoryModel",
+        ));
+    };
+
     let op_type_pointer_sampler_res_id = instruction_bound;
     instruction_bound += 1;
     instruction_inserts.push(InstructionInsert {
-        // Let's avoid trouble and just insert after OpTypeVoid.
-        // previous_spv_idx: op_type_image_idx,
-        previous_spv_idx: first_op_type_void_idx.unwrap(),
-        instruction: vec![
-            encode_word(2, SPV_INSTRUCTION_OP_TYPE_SAMPLER),
-            op_type_sampler_res_id,
-            encode_word(4, SPV_INSTRUCTION_OP_TYPE_POINTER),
-            op_type_pointer_sampler_res_id,
-            SPV_STORAGE_CLASS_UNIFORM_CONSTANT,
-            op_type_sampler_res_id,
+        previous_idx: types_start_idx,
+        instructions: vec![
+            Instruction::new(
+                SPV_INSTRUCTION_OP_TYPE_SAMPLER,
+                None,
+                Some(op_type_sampler_res_id),
+                vec![],
+            ),
+            Instruction::new(
+                SPV_INSTRUCTION_OP_TYPE_POINTER,
+                None,
+                Some(op_type_pointer_sampler_res_id),
+                vec![SPV_STORAGE_CLASS_UNIFORM_CONSTANT, op_type_sampler_res_id],
+            ),
         ],
     });
 
-    // 3. OpTypePointer
+    // 3. OpTypeArray
+    let ta_res = type_array(TypeArrayIn {
+        module: &module,
+        new_module: &mut new_module,
+        instruction_bound: &mut instruction_bound,
+        instruction_inserts: &mut instruction_inserts,
+        op_type_sampler_res_id,
+        op_type_array_idxs: &op_type_array_idxs,
+        underlying_image_ids: &underlying_image_ids,
+    });
+
+    // 4. OpTypePointer
     let tp_res = type_pointer(TypePointerIn {
-        spv: &spv,
-        new_spv: &mut new_spv,
+        module: &module,
+        new_module: &mut new_module,
+        instruction_bound: &mut instruction_bound,
+        instruction_inserts: &mut instruction_inserts,
 
+        op_type_pointer_sampler_res_id,
         op_type_pointer_idxs: &op_type_pointer_idxs,
-        op_type_sampled_image_idxs: &op_type_sampled_image_idxs,
+        underlying_image_ids: &underlying_image_ids,
+        ta_res: &ta_res,
     });
 
-    // 4. OpVariable
+    // 5. Find combimgs that never reach a sampling operation
+    let fetch_only_v_res_ids = fetch_only_variables(FetchOnlyVariablesIn {
+        module: &module,
+        op_variables_idxs: &op_variables_idxs,
+        op_access_chain_idxs: &op_access_chain_idxs,
+        op_loads_idxs: &op_loads_idxs,
+        op_function_call_idxs: &op_function_call_idxs,
+        underlying_image_ids: &underlying_image_ids,
+    });
+    // - Combimgs that only dead functions load keep their combined type and get no sampler
+    let graph = CallGraph::new(&module)?;
+    let dead_only_v_res_ids = dead_only_variables(DeadOnlyVariablesIn {
+        module: &module,
+        graph: &graph,
+        op_variables_idxs: &op_variables_idxs,
+        op_type_pointer_idxs: &op_type_pointer_idxs,
+        underlying_image_ids: &underlying_image_ids,
+        fetch_only_v_res_ids: &fetch_only_v_res_ids,
+    });
+    dead_only(DeadOnlyIn {
+        module: &module,
+        new_module: &mut new_module,
+        instruction_bound: &mut instruction_bound,
+        instruction_inserts: &mut instruction_inserts,
+        op_variables_idxs: &op_variables_idxs,
+        op_type_pointer_idxs: &op_type_pointer_idxs,
+        dead_only_v_res_ids: &dead_only_v_res_ids,
+    });
+
+    // 6. OpVariable
     let v_res = variable(VariableIn {
-        spv: &spv,
+        module: &module,
         instruction_bound: &mut instruction_bound,
         instruction_inserts: &mut instruction_inserts,
-        op_type_pointer_sampler_res_id,
         op_variables_idxs: &op_variables_idxs,
+        fetch_only_v_res_ids: &fetch_only_v_res_ids,
+        dead_only_v_res_ids: &dead_only_v_res_ids,
         tp_res: &tp_res,
     });
 
-    // 5. OpTypeFunction
+    // 7. OpTypeFunction
     type_function(TypeFunctionIn {
-        spv: &spv,
-        word_inserts: &mut word_inserts,
-        op_type_pointer_sampler_res_id,
+        module: &module,
+        new_module: &mut new_module,
+        operand_inserts: &mut operand_inserts,
+        op_type_sampler_res_id,
         op_type_function_idxs: &op_type_function_idxs,
+        underlying_image_ids: &underlying_image_ids,
         tp_res: &tp_res,
     });
 
-    // 6. OpFunctionParameter
+    // 8. OpFunctionParameter
     let parameter_res = function_parameter(FunctionParameterIn {
-        spv: &spv,
+        module: &module,
         instruction_bound: &mut instruction_bound,
         instruction_inserts: &mut instruction_inserts,
-        op_type_pointer_sampler_res_id,
         op_function_parameter_idxs: &op_function_parameter_idxs,
         tp_res: &tp_res,
     });
 
-    // 7. OpFunctionCall
-    function_call(FunctionCallIn {
-        spv: &spv,
-        word_inserts: &mut word_inserts,
-        op_function_call_idxs: &op_function_call_idxs,
+    // 9. OpFunctionParameter passed by value
+    let vp_res = value_parameter(ValueParameterIn {
+        module: &module,
+        new_module: &mut new_module,
+        instruction_bound: &mut instruction_bound,
+        instruction_inserts: &mut instruction_inserts,
+        op_type_sampler_res_id,
+        op_function_parameter_idxs: &op_function_parameter_idxs,
+        underlying_image_ids: &underlying_image_ids,
+    });
+
+    // 10. OpAccessChain
+    let ac_res = access_chain(AccessChainIn {
+        module: &module,
+        instruction_bound: &mut instruction_bound,
+        instruction_inserts: &mut instruction_inserts,
+        op_access_chain_idxs: &op_access_chain_idxs,
+        non_uniform_decorate_idxs: &non_uniform_decorate_idxs,
+        tp_res: &tp_res,
+        v_res: &v_res,
+        parameter_res: &parameter_res,
+    });
+
+    // 11. OpLoad
+    let l_res = load(LoadIn {
+        module: &module,
+        new_module: &mut new_module,
+        instruction_bound: &mut instruction_bound,
+        instruction_inserts: &mut instruction_inserts,
+        op_type_sampler_res_id,
+        op_loads_idxs: &op_loads_idxs,
+        non_uniform_decorate_idxs: &non_uniform_decorate_idxs,
         v_res: &v_res,
+        ac_res: &ac_res,
         parameter_res: &parameter_res,
     });
 
-    // 8. OpLoad
-    load(LoadIn {
-        spv: &spv,
-        new_spv: &mut new_spv,
+    // 12. OpCopyObject, OpPhi and OpSelect
+    let values = l_res.into_iter().chain(vp_res).collect::<Vec<_>>();
+    let f_res = forward(ForwardIn {
+        module: &module,
+        new_module: &mut new_module,
         instruction_bound: &mut instruction_bound,
         instruction_inserts: &mut instruction_inserts,
         op_type_sampler_res_id,
+        non_uniform_decorate_idxs: &non_uniform_decorate_idxs,
+        values: &values,
+    })?;
+
+    // 13. OpLoad and OpImage of combimgs that never reach a sampling operation
+    fetch_only(FetchOnlyIn {
+        module: &module,
+        new_module: &mut new_module,
+        op_access_chain_idxs: &op_access_chain_idxs,
         op_loads_idxs: &op_loads_idxs,
+        underlying_image_ids: &underlying_image_ids,
+        fetch_only_v_res_ids: &fetch_only_v_res_ids,
+    });
+
+    // 14. OpFunctionCall
+    let values = values.into_iter().chain(f_res).collect::<Vec<_>>();
+    function_call(FunctionCallIn {
+        module: &module,
+        new_module: &mut new_module,
+        operand_inserts: &mut operand_inserts,
+        op_function_call_idxs: &op_function_call_idxs,
+        underlying_image_ids: &underlying_image_ids,
+        functions: &util::find_functions(&module)?,
         v_res: &v_res,
+        ac_res: &ac_res,
         parameter_res: &parameter_res,
+        values: &values,
+    })?;
+
+    // 15. OpSampledImage for the remaining users of split values
+    recombine(RecombineIn {
+        module: &module,
+        new_module: &mut new_module,
+        instruction_bound: &mut instruction_bound,
+        instruction_inserts: &mut instruction_inserts,
+        non_uniform_decorate_idxs: &non_uniform_decorate_idxs,
+        values: &values,
     });
 
-    // 9. OpDecorate
-    let DecorateOut {
-        descriptor_sets_to_correct,
-    } = util::decorate(DecorateIn {
-        spv: &spv,
+    // 16. OpDecorate
+    let affected_variables = v_res
+        .iter()
+        .map(
+            |VariableOut {
+                 v_res_id,
+                 new_sampler_v_res_id,
+                 ..
+             }| {
+                util::DecorationVariable {
+                    original_res_id: *v_res_id,
+                    new_res_id: *new_sampler_v_res_id,
+                    correction_type: CorrectionType::SplitCombined,
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+    let DecorateOut { new_variables } = util::decorate(DecorateIn {
+        module: &module,
         instruction_inserts: &mut instruction_inserts,
-        first_op_deocrate_idx,
+        layout: &layout,
         op_decorate_idxs: &op_decorate_idxs,
-        affected_variables: &v_res
-            .iter()
-            .map(
-                |VariableOut {
-                     v_res_id,
-                     new_sampler_v_res_id,
-                     ..
-                 }| {
-                    util::DecorationVariable {
-                        original_res_id: *v_res_id,
-                        new_res_id: *new_sampler_v_res_id,
-                        correction_type: CorrectionType::SplitCombined,
-                    }
-                },
-            )
-            .collect::<Vec<_>>(),
+        affected_variables: &affected_variables,
         corrections,
+    })?;
+
+    // 17. OpEntryPoint
+    util::entry_point_interface(EntryPointInterfaceIn {
+        module: &module,
+        operand_inserts: &mut operand_inserts,
+        affected_variables: &affected_variables,
     });
 
-    // 10. Insert New Instructions
-    insert_new_instructions(&spv, &mut new_spv, &word_inserts, &instruction_inserts);
+    // 18. Insert New Instructions
+    insert_new_instructions(&mut new_module, &operand_inserts, &instruction_inserts);
 
-    // 11. Correct OpDecorate Bindings
+    // 19. Correct OpDecorate Bindings
     util::correct_decorate(CorrectDecorateIn {
-        new_spv: &mut new_spv,
-        descriptor_sets_to_correct,
-    });
-
-    // 12. Remove Instructions that have been Whited Out.
-    prune_noops(&mut new_spv);
+        new_module: &mut new_module,
+        new_variables: &new_variables,
+        allocator,
+        corrections,
+    })?;
 
-    // 13. Write New Header and New Code
-    Ok(fuse_final(spv_header, new_spv, instruction_bound))
+    // - Whited out instructions are removed and the header is written by the PassManager
+    *out_module = new_module;
+    *out_instruction_bound = instruction_bound;
+    Ok(())
 }
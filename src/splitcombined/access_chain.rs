@@ -0,0 +1,96 @@
+use super::*;
+
+pub struct AccessChainIn<'a> {
+    pub module: &'a Module,
+    pub instruction_bound: &'a mut u32,
+    pub instruction_inserts: &'a mut Vec<InstructionInsert>,
+
+    pub op_access_chain_idxs: &'a [usize],
+    pub non_uniform_decorate_idxs: &'a HashMap<u32, usize>,
+
+    pub tp_res: &'a [TypePointerOut],
+    pub v_res: &'a [VariableOut],
+    pub parameter_res: &'a [FunctionParameterOut],
+}
+
+pub fn access_chain(ac_in: AccessChainIn) -> Vec<VariableOut> {
+    let mut ac_res: Vec<VariableOut> = vec![];
+
+    let AccessChainIn {
+        module,
+        instruction_bound,
+        instruction_inserts,
+        op_access_chain_idxs,
+        non_uniform_decorate_idxs,
+        tp_res,
+        v_res,
+        parameter_res,
+    } = ac_in;
+
+    let pointer_types = tp_res
+        .iter()
+        .map(|tp| (tp.tp_res_id, tp))
+        .collect::<HashMap<_, _>>();
+    // - Pointers that have a parallel sampler pointer, chains are added as they are found
+    let mut sampler_pointer_ids = v_res
+        .iter()
+        .map(|v| (v.v_res_id, v.new_sampler_v_res_id))
+        .collect::<HashMap<_, _>>();
+    let sampler_parameter_ids = parameter_res
+        .iter()
+        .map(|p| (p.image_parameter_res_id, p.sampler_parameter_res_id))
+        .collect::<HashMap<_, _>>();
+
+    // - Access chains may index into the result of a previous access chain
+    for &ac_idx in op_access_chain_idxs.iter() {
+        let ac = &module.instructions[ac_idx];
+        let base_id = ac.operands[0];
+
+        // - Find all OpAccessChains that produce one of our pointers
+        let Some(&TypePointerOut {
+            sampler_tp_res_id,
+            underlying_image_id,
+            ..
+        }) = pointer_types.get(&ac.type_id()).copied()
+        else {
+            continue;
+        };
+
+        // - Find the sampler that parallels the base of the chain
+        let Some(&sampler_base_id) = sampler_pointer_ids
+            .get(&base_id)
+            .or(sampler_parameter_ids.get(&base_id))
+        else {
+            continue;
+        };
+
+        // - Walk the same indices into the sampler array
+        let new_sampler_ac_res_id = *instruction_bound;
+        *instruction_bound += 1;
+        let mut operands = ac.operands.clone();
+        operands[0] = sampler_base_id;
+        instruction_inserts.push(InstructionInsert {
+            previous_idx: ac_idx,
+            instructions: vec![Instruction::new(
+                ac.opcode,
+                Some(sampler_tp_res_id),
+                Some(new_sampler_ac_res_id),
+                operands,
+            )],
+        });
+
+        // - Indexing the sampler array must stay just as non-uniform
+        if let Some(&d_idx) = non_uniform_decorate_idxs.get(&ac.id()) {
+            instruction_inserts.push(util::decorate_non_uniform(d_idx, &[new_sampler_ac_res_id]));
+        }
+
+        sampler_pointer_ids.insert(ac.id(), new_sampler_ac_res_id);
+        ac_res.push(VariableOut {
+            v_res_id: ac.id(),
+            new_sampler_v_res_id: new_sampler_ac_res_id,
+            underlying_image_id,
+        });
+    }
+
+    ac_res
+}
@@ -0,0 +1,157 @@
+use super::*;
+
+pub struct DeadOnlyVariablesIn<'a> {
+    pub module: &'a Module,
+    pub graph: &'a CallGraph,
+
+    pub op_variables_idxs: &'a [usize],
+    pub op_type_pointer_idxs: &'a [usize],
+    // OpTypeSampledImage id -> the OpTypeImage it combines
+    pub underlying_image_ids: &'a HashMap<u32, u32>,
+
+    pub fetch_only_v_res_ids: &'a HashSet<u32>,
+}
+
+// Find the combimg variables that are only loaded by functions no entry point reaches. Nothing
+// binds them, so they are left combined, without a sampler binding.
+pub fn dead_only_variables(dov_in: DeadOnlyVariablesIn) -> HashSet<u32> {
+    let DeadOnlyVariablesIn {
+        module,
+        graph,
+        op_variables_idxs,
+        op_type_pointer_idxs,
+        underlying_image_ids,
+        fetch_only_v_res_ids,
+    } = dov_in;
+
+    let sampled_image_pointer_ids = op_type_pointer_idxs
+        .iter()
+        .map(|&tp_idx| &module.instructions[tp_idx])
+        .filter(|tp| underlying_image_ids.contains_key(&tp.operands[1]))
+        .map(|tp| tp.id())
+        .collect::<HashSet<_>>();
+
+    // - Every use of a value in a function body
+    let first_function_idx = module
+        .instructions
+        .iter()
+        .position(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_FUNCTION)
+        .unwrap_or(module.instructions.len());
+    let mut users: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (idx, instruction) in module
+        .instructions
+        .iter()
+        .enumerate()
+        .skip(first_function_idx)
+    {
+        for (operand_idx, &operand) in instruction.operands.iter().enumerate() {
+            if !spv_is_literal_operand(instruction.opcode, operand_idx) {
+                users.entry(operand).or_default().push(idx);
+            }
+        }
+    }
+
+    let trace = ValueTrace::new(module);
+    op_variables_idxs
+        .iter()
+        .map(|&v_idx| &module.instructions[v_idx])
+        .filter(|v| {
+            v.operands[0] == SPV_STORAGE_CLASS_UNIFORM_CONSTANT
+                && sampled_image_pointer_ids.contains(&v.type_id())
+                && !fetch_only_v_res_ids.contains(&v.id())
+        })
+        .filter(|v| {
+            // - Only loaded directly, and only by dead functions
+            let v_users = users.get(&v.id()).map_or(&[][..], |users| users.as_slice());
+            let is_dead_load = |idx: usize| {
+                let user = &module.instructions[idx];
+                user.opcode == SPV_INSTRUCTION_OP_LOAD
+                    && user.operands[0] == v.id()
+                    && graph
+                        .function_of(idx)
+                        .is_some_and(|function| !graph.is_live(function))
+            };
+            if v_users.is_empty() || !v_users.iter().all(|&idx| is_dead_load(idx)) {
+                return false;
+            }
+
+            // - The loaded values must not reach a function call, or be forwarded together with
+            //   values from elsewhere
+            let l_res_ids = v_users
+                .iter()
+                .map(|&idx| module.instructions[idx].id())
+                .collect::<Vec<_>>();
+            let forwarded_ids = trace.forwards(&l_res_ids);
+            let value_ids = forwarded_ids
+                .iter()
+                .copied()
+                .chain(l_res_ids)
+                .collect::<HashSet<_>>();
+            let is_called = value_ids
+                .iter()
+                .flat_map(|id| users.get(id).into_iter().flatten())
+                .any(|&idx| module.instructions[idx].opcode == SPV_INSTRUCTION_OP_FUNCTION_CALL);
+            let is_mixed = forwarded_ids
+                .iter()
+                .any(|id| !trace.sources(*id).iter().all(|id| value_ids.contains(id)));
+            !is_called && !is_mixed
+        })
+        .map(|v| v.id())
+        .collect()
+}
+
+pub struct DeadOnlyIn<'a> {
+    pub module: &'a Module,
+    pub new_module: &'a mut Module,
+    pub instruction_bound: &'a mut u32,
+    pub instruction_inserts: &'a mut Vec<InstructionInsert>,
+
+    pub op_variables_idxs: &'a [usize],
+    pub op_type_pointer_idxs: &'a [usize],
+
+    pub dead_only_v_res_ids: &'a HashSet<u32>,
+}
+
+pub fn dead_only(do_in: DeadOnlyIn) {
+    let DeadOnlyIn {
+        module,
+        new_module,
+        instruction_bound,
+        instruction_inserts,
+        op_variables_idxs,
+        op_type_pointer_idxs,
+        dead_only_v_res_ids,
+    } = do_in;
+
+    // - The shared OpTypePointer now points to the underlying image, so the variables get their
+    //   own pointer to the combimg, one per pointer type
+    let tp_idxs = op_type_pointer_idxs
+        .iter()
+        .map(|&tp_idx| (module.instructions[tp_idx].id(), tp_idx))
+        .collect::<HashMap<_, _>>();
+    let mut combined_tp_res_ids = HashMap::new();
+    for &v_idx in op_variables_idxs.iter() {
+        let v = &module.instructions[v_idx];
+        if !dead_only_v_res_ids.contains(&v.id()) {
+            continue;
+        }
+        let Some(&tp_idx) = tp_idxs.get(&v.type_id()) else {
+            continue;
+        };
+        let combined_tp_res_id = *combined_tp_res_ids.entry(tp_idx).or_insert_with(|| {
+            let combined_tp_res_id = *instruction_bound;
+            *instruction_bound += 1;
+            instruction_inserts.push(InstructionInsert {
+                previous_idx: tp_idx,
+                instructions: vec![Instruction::new(
+                    SPV_INSTRUCTION_OP_TYPE_POINTER,
+                    None,
+                    Some(combined_tp_res_id),
+                    module.instructions[tp_idx].operands.clone(),
+                )],
+            });
+            combined_tp_res_id
+        });
+        new_module.instructions[v_idx].result_type = Some(combined_tp_res_id);
+    }
+}
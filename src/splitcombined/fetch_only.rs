@@ -0,0 +1,217 @@
+use super::*;
+
+pub struct FetchOnlyVariablesIn<'a> {
+    pub module: &'a Module,
+
+    pub op_variables_idxs: &'a [usize],
+    pub op_access_chain_idxs: &'a [usize],
+    pub op_loads_idxs: &'a [usize],
+    pub op_function_call_idxs: &'a [usize],
+    // OpTypeSampledImage id -> the OpTypeImage it combines
+    pub underlying_image_ids: &'a HashMap<u32, u32>,
+}
+
+// Find the variables whose combimgs only ever reach OpImage, as is the case for `texelFetch`,
+// `textureSize` and `textureQueryLevels`. These only need the image, so we do not create a
+// sampler binding for them.
+pub fn fetch_only_variables(fov_in: FetchOnlyVariablesIn) -> HashSet<u32> {
+    let FetchOnlyVariablesIn {
+        module,
+        op_variables_idxs,
+        op_access_chain_idxs,
+        op_loads_idxs,
+        op_function_call_idxs,
+        underlying_image_ids,
+    } = fov_in;
+
+    // - Pointers into each variable, directly or through an OpAccessChain
+    let mut pointer_variable_ids = op_variables_idxs
+        .iter()
+        .map(|&v_idx| &module.instructions[v_idx])
+        .filter(|v| v.operands[0] == SPV_STORAGE_CLASS_UNIFORM_CONSTANT)
+        .map(|v| (v.id(), v.id()))
+        .collect::<HashMap<_, _>>();
+    // - Access chains may index into the result of a previous access chain
+    for &ac_idx in op_access_chain_idxs.iter() {
+        let ac = &module.instructions[ac_idx];
+        if let Some(&v_res_id) = pointer_variable_ids.get(&ac.operands[0]) {
+            pointer_variable_ids.insert(ac.id(), v_res_id);
+        }
+    }
+
+    let mut sampled_v_res_ids = HashSet::new();
+
+    // - Pointers passed to a function keep their sampler, the parameter is shared by every caller
+    for &fc_idx in op_function_call_idxs.iter() {
+        for argument_id in module.instructions[fc_idx].operands.iter().skip(1) {
+            if let Some(&v_res_id) = pointer_variable_ids.get(argument_id) {
+                sampled_v_res_ids.insert(v_res_id);
+            }
+        }
+    }
+
+    // - Every use of a value in a function body
+    let first_function_idx = module
+        .instructions
+        .iter()
+        .position(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_FUNCTION)
+        .unwrap_or(module.instructions.len());
+    let mut users: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (idx, instruction) in module
+        .instructions
+        .iter()
+        .enumerate()
+        .skip(first_function_idx)
+    {
+        for (operand_idx, &operand) in instruction.operands.iter().enumerate() {
+            if !spv_is_literal_operand(instruction.opcode, operand_idx) {
+                users.entry(operand).or_default().push(idx);
+            }
+        }
+    }
+
+    // - Find the variable behind each loaded value, including forwarded ones
+    let trace = ValueTrace::new(module);
+    let mut value_variable_ids: HashMap<u32, Vec<u32>> = HashMap::new();
+    for &l_idx in op_loads_idxs.iter() {
+        let l = &module.instructions[l_idx];
+        let Some(&v_res_id) = pointer_variable_ids.get(&l.operands[0]) else {
+            continue;
+        };
+        // - Loading a whole array is not something we can rewrite
+        if !underlying_image_ids.contains_key(&l.type_id()) {
+            sampled_v_res_ids.insert(v_res_id);
+            continue;
+        }
+        for id in std::iter::once(l.id()).chain(trace.forwards(&[l.id()])) {
+            value_variable_ids.entry(id).or_default().push(v_res_id);
+        }
+    }
+
+    // - Anything other than OpImage, or forwarding to another value, needs the sampler
+    for (&id, v_res_ids) in value_variable_ids.iter() {
+        let is_sampled = users.get(&id).into_iter().flatten().any(|&idx| {
+            let user = &module.instructions[idx];
+            let is_forward = value_variable_ids.contains_key(&user.id())
+                && forwarded_operand_idxs(user)
+                    .iter()
+                    .any(|&operand_idx| user.operands[operand_idx] == id);
+            user.opcode != SPV_INSTRUCTION_OP_IMAGE && !is_forward
+        });
+        if is_sampled {
+            sampled_v_res_ids.extend(v_res_ids.iter().copied());
+        }
+    }
+
+    // - Variables that are forwarded through the same OpPhi or OpSelect have to agree, otherwise
+    //   only one side of it would have a sampler
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for v_res_ids in value_variable_ids.values() {
+            if v_res_ids.iter().any(|id| sampled_v_res_ids.contains(id)) {
+                for &v_res_id in v_res_ids.iter() {
+                    changed |= sampled_v_res_ids.insert(v_res_id);
+                }
+            }
+        }
+    }
+
+    pointer_variable_ids
+        .into_iter()
+        .filter_map(|(id, v_res_id)| (id == v_res_id).then_some(id))
+        .filter(|id| !sampled_v_res_ids.contains(id))
+        .collect()
+}
+
+pub struct FetchOnlyIn<'a> {
+    pub module: &'a Module,
+    pub new_module: &'a mut Module,
+
+    pub op_access_chain_idxs: &'a [usize],
+    pub op_loads_idxs: &'a [usize],
+    // OpTypeSampledImage id -> the OpTypeImage it combines
+    pub underlying_image_ids: &'a HashMap<u32, u32>,
+
+    pub fetch_only_v_res_ids: &'a HashSet<u32>,
+}
+
+pub fn fetch_only(fo_in: FetchOnlyIn) {
+    let FetchOnlyIn {
+        module,
+        new_module,
+        op_access_chain_idxs,
+        op_loads_idxs,
+        underlying_image_ids,
+        fetch_only_v_res_ids,
+    } = fo_in;
+
+    let mut pointer_ids = fetch_only_v_res_ids.clone();
+    for &ac_idx in op_access_chain_idxs.iter() {
+        let ac = &module.instructions[ac_idx];
+        if pointer_ids.contains(&ac.operands[0]) {
+            pointer_ids.insert(ac.id());
+        }
+    }
+
+    // - Loads, and the values they are forwarded to, now load the underlying image
+    let l_res_ids = op_loads_idxs
+        .iter()
+        .map(|&l_idx| &module.instructions[l_idx])
+        .filter(|l| pointer_ids.contains(&l.operands[0]))
+        .map(|l| l.id())
+        .collect::<Vec<_>>();
+    let trace = ValueTrace::new(module);
+    let value_ids = trace
+        .forwards(&l_res_ids)
+        .into_iter()
+        .chain(l_res_ids)
+        .collect::<HashSet<_>>();
+
+    // - OpImage of an image is not allowed, its users take the image directly instead
+    let mut image_substitutions = HashMap::new();
+    for (idx, instruction) in module.instructions.iter().enumerate() {
+        if value_ids.contains(&instruction.id()) {
+            if let Some(&underlying_image_id) = underlying_image_ids.get(&instruction.type_id()) {
+                new_module.instructions[idx].result_type = Some(underlying_image_id);
+            }
+        } else if instruction.opcode == SPV_INSTRUCTION_OP_IMAGE
+            && value_ids.contains(&instruction.operands[0])
+        {
+            image_substitutions.insert(instruction.id(), instruction.operands[0]);
+            new_module.instructions[idx] = Instruction::nop();
+        }
+    }
+    if image_substitutions.is_empty() {
+        return;
+    }
+
+    let mut in_function = false;
+    for instruction in new_module.instructions.iter_mut() {
+        // - Names and decorations of the removed OpImage would dangle
+        if matches!(
+            instruction.opcode,
+            SPV_INSTRUCTION_OP_NAME | SPV_INSTRUCTION_OP_DECORATE
+        ) && instruction
+            .operands
+            .first()
+            .is_some_and(|id| image_substitutions.contains_key(id))
+        {
+            *instruction = Instruction::nop();
+            continue;
+        }
+        // - Only function bodies can use the removed OpImage, and literals are only known there
+        in_function |= instruction.opcode == SPV_INSTRUCTION_OP_FUNCTION;
+        if !in_function {
+            continue;
+        }
+        let opcode = instruction.opcode;
+        for (operand_idx, operand) in instruction.operands.iter_mut().enumerate() {
+            if !spv_is_literal_operand(opcode, operand_idx)
+                && let Some(&image_id) = image_substitutions.get(operand)
+            {
+                *operand = image_id;
+            }
+        }
+    }
+}
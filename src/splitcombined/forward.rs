@@ -0,0 +1,135 @@
+use super::*;
+
+pub struct ForwardIn<'a> {
+    pub module: &'a Module,
+    pub new_module: &'a mut Module,
+    pub instruction_bound: &'a mut u32,
+    pub instruction_inserts: &'a mut Vec<InstructionInsert>,
+
+    pub op_type_sampler_res_id: u32,
+    pub non_uniform_decorate_idxs: &'a HashMap<u32, usize>,
+
+    // Loads and value parameters that have been split
+    pub values: &'a [SplitValueOut],
+}
+
+pub fn forward(f_in: ForwardIn) -> Result<Vec<SplitValueOut>, TransformError> {
+    let mut f_res = vec![];
+
+    let ForwardIn {
+        module,
+        new_module,
+        instruction_bound,
+        instruction_inserts,
+        op_type_sampler_res_id,
+        non_uniform_decorate_idxs,
+        values,
+    } = f_in;
+
+    // - Find all OpCopyObject, OpPhi and OpSelect that our split values flow into
+    let trace = ValueTrace::new(module);
+    let forward_ids = trace.forwards(
+        &values
+            .iter()
+            .map(|SplitValueOut { res_id, .. }| *res_id)
+            .collect::<Vec<_>>(),
+    );
+
+    // - Give every one of them an image and a sampler result up front, phis in loops may
+    //   forward values that are defined further down
+    let mut split_ids = values
+        .iter()
+        .map(
+            |&SplitValueOut {
+                 res_id,
+                 image_res_id,
+                 sampler_res_id,
+                 ..
+             }| (res_id, (image_res_id, sampler_res_id)),
+        )
+        .collect::<HashMap<_, _>>();
+    let mut f_idxs = vec![];
+    for (f_idx, instruction) in module.instructions.iter().enumerate() {
+        if forward_ids.contains(&instruction.id()) {
+            split_ids.insert(
+                instruction.id(),
+                (*instruction_bound, *instruction_bound + 1),
+            );
+            *instruction_bound += 2;
+            f_idxs.push(f_idx);
+        }
+    }
+
+    let underlying_image_ids = values
+        .iter()
+        .map(
+            |&SplitValueOut {
+                 res_id,
+                 underlying_image_id,
+                 ..
+             }| (res_id, underlying_image_id),
+        )
+        .collect::<HashMap<_, _>>();
+    for f_idx in f_idxs {
+        let instruction = &module.instructions[f_idx];
+        let f_res_id = instruction.id();
+        let (image_f_res_id, sampler_f_res_id) = split_ids[&f_res_id];
+
+        // - Forwarded values can only be split if every one of them is split
+        let Some(underlying_image_id) = trace
+            .roots(f_res_id)
+            .into_iter()
+            .map(|root_id| underlying_image_ids.get(&root_id).copied())
+            .reduce(|a, b| a.filter(|&a| Some(a) == b))
+            .flatten()
+        else {
+            return Err(TransformError::unsupported(
+                module,
+                f_idx,
+                "forwarded value mixes combined image samplers with other values",
+            ));
+        };
+
+        // - The original instruction forwards the image, a new one forwards the sampler
+        let mut image_forward = instruction.clone();
+        image_forward.result_type = Some(underlying_image_id);
+        image_forward.result_id = Some(image_f_res_id);
+        let mut sampler_forward = instruction.clone();
+        sampler_forward.result_type = Some(op_type_sampler_res_id);
+        sampler_forward.result_id = Some(sampler_f_res_id);
+        for operand_idx in forwarded_operand_idxs(instruction) {
+            // - Only reachable by a forwarding instruction that forwards itself
+            let Some(&(image_id, sampler_id)) = split_ids.get(&instruction.operands[operand_idx])
+            else {
+                return Err(TransformError::unsupported(
+                    module,
+                    f_idx,
+                    "forwarded value does not originate from any value",
+                ));
+            };
+            image_forward.operands[operand_idx] = image_id;
+            sampler_forward.operands[operand_idx] = sampler_id;
+        }
+        new_module.instructions[f_idx] = image_forward;
+        instruction_inserts.push(InstructionInsert {
+            previous_idx: f_idx,
+            instructions: vec![sampler_forward],
+        });
+
+        if let Some(&d_idx) = non_uniform_decorate_idxs.get(&f_res_id) {
+            instruction_inserts.push(util::decorate_non_uniform(
+                d_idx,
+                &[image_f_res_id, sampler_f_res_id],
+            ));
+        }
+
+        f_res.push(SplitValueOut {
+            res_id: f_res_id,
+            image_res_id: image_f_res_id,
+            sampler_res_id: sampler_f_res_id,
+            underlying_image_id,
+        });
+    }
+
+    Ok(f_res)
+}
@@ -1,57 +1,138 @@
 use super::*;
 
 pub struct FunctionCallIn<'a> {
-    pub spv: &'a [u32],
-    pub word_inserts: &'a mut Vec<WordInsert>,
+    pub module: &'a Module,
+    pub new_module: &'a mut Module,
+    pub operand_inserts: &'a mut Vec<OperandInsert>,
 
     pub op_function_call_idxs: &'a [usize],
+    // OpTypeSampledImage id -> the OpTypeImage it combines
+    pub underlying_image_ids: &'a HashMap<u32, u32>,
+    pub functions: &'a [FunctionInfo],
 
     pub v_res: &'a [VariableOut],
+    pub ac_res: &'a [VariableOut],
     pub parameter_res: &'a [FunctionParameterOut],
+    // Loads, value parameters and forwarded values that have been split
+    pub values: &'a [SplitValueOut],
 }
 
-pub fn function_call(fc_in: FunctionCallIn) {
+pub fn function_call(fc_in: FunctionCallIn) -> Result<(), TransformError> {
     let FunctionCallIn {
-        spv,
-        word_inserts,
+        module,
+        new_module,
+        operand_inserts,
         op_function_call_idxs,
+        underlying_image_ids,
+        functions,
         v_res,
+        ac_res,
         parameter_res,
+        values,
     } = fc_in;
 
-    op_function_call_idxs.iter().for_each(|&fc_idx| {
-        parameter_res
-            // - Handle use of nested function calls
-            .iter()
-            .map(
-                |FunctionParameterOut {
-                     image_parameter_res_id,
-                     sampler_parameter_res_id,
-                     ..
-                 }| { (image_parameter_res_id, sampler_parameter_res_id) },
-            )
-            // - Handle use of uniform variables
-            .chain(v_res.iter().map(
-                |VariableOut {
-                     v_res_id: image_id,
-                     new_sampler_v_res_id: sampler_id,
-                     ..
-                 }| (image_id, sampler_id),
-            ))
-            .for_each(|(&image_id, &sampler_id)| {
-                let word_count = hiword(spv[fc_idx]);
-                for (i, param) in spv[fc_idx + 4..fc_idx + word_count as usize]
+    let definitions = module.definitions();
+    let split_ids = values
+        .iter()
+        .map(
+            |&SplitValueOut {
+                 res_id,
+                 image_res_id,
+                 sampler_res_id,
+                 ..
+             }| (res_id, (image_res_id, sampler_res_id)),
+        )
+        .collect::<HashMap<_, _>>();
+    let parameter_type_ids = functions
+        .iter()
+        .map(|function| {
+            (
+                module.instructions[function.function_idx].id(),
+                function
+                    .parameter_idxs
                     .iter()
-                    .enumerate()
-                {
-                    if *param == image_id {
-                        word_inserts.push(WordInsert {
-                            idx: fc_idx + 4 + i,
-                            word: sampler_id,
-                            head_idx: fc_idx,
-                        })
-                    }
-                }
+                    .map(|&fp_idx| module.instructions[fp_idx].type_id())
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    let sampler_ids = parameter_res
+        .iter()
+        // - Handle use of nested function calls
+        .map(
+            |&FunctionParameterOut {
+                 image_parameter_res_id,
+                 sampler_parameter_res_id,
+                 ..
+             }| (image_parameter_res_id, sampler_parameter_res_id),
+        )
+        // - Handle use of uniform variables and elements of uniform arrays
+        .chain(v_res.iter().chain(ac_res.iter()).map(
+            |&VariableOut {
+                 v_res_id: image_id,
+                 new_sampler_v_res_id: sampler_id,
+                 ..
+             }| (image_id, sampler_id),
+        ))
+        .collect::<HashMap<_, _>>();
+
+    for &fc_idx in op_function_call_idxs.iter() {
+        // Skip the function id
+        for (i, param) in module.instructions[fc_idx]
+            .operands
+            .iter()
+            .enumerate()
+            .skip(1)
+        {
+            if let Some(&sampler_id) = sampler_ids.get(param) {
+                operand_inserts.push(OperandInsert {
+                    idx: fc_idx,
+                    operand_idx: i,
+                    operand: sampler_id,
+                })
+            }
+        }
+
+        // - Combimgs passed by value are passed as an image and a sampler
+        let fc = &module.instructions[fc_idx];
+        let Some(parameter_type_ids) = parameter_type_ids.get(&fc.operands[0]) else {
+            continue;
+        };
+        // Skip the function id
+        for ((i, argument_id), parameter_type_id) in fc
+            .operands
+            .iter()
+            .enumerate()
+            .skip(1)
+            .zip(parameter_type_ids)
+        {
+            if !underlying_image_ids.contains_key(parameter_type_id) {
+                continue;
+            }
+            // - Arguments are either split by us, or already combined from an image and a sampler
+            let split = split_ids.get(argument_id).copied().or_else(|| {
+                definitions
+                    .get(argument_id)
+                    .map(|&idx| &module.instructions[idx])
+                    .filter(|si| si.opcode == SPV_INSTRUCTION_OP_SAMPLED_IMAGE)
+                    .map(|si| (si.operands[0], si.operands[1]))
             });
-    });
+            let Some((image_id, sampler_id)) = split else {
+                return Err(TransformError::unsupported(
+                    module,
+                    fc_idx,
+                    "combined image sampler argument cannot be split",
+                ));
+            };
+            new_module.instructions[fc_idx].operands[i] = image_id;
+            operand_inserts.push(OperandInsert {
+                idx: fc_idx,
+                operand_idx: i,
+                operand: sampler_id,
+            });
+        }
+    }
+
+    Ok(())
 }
@@ -1,92 +1,113 @@
 use super::*;
 
 pub struct LoadIn<'a> {
-    pub spv: &'a [u32],
-    pub new_spv: &'a mut [u32],
+    pub module: &'a Module,
+    pub new_module: &'a mut Module,
     pub instruction_bound: &'a mut u32,
     pub instruction_inserts: &'a mut Vec<InstructionInsert>,
 
     pub op_type_sampler_res_id: u32,
     pub op_loads_idxs: &'a [usize],
+    pub non_uniform_decorate_idxs: &'a HashMap<u32, usize>,
 
     pub v_res: &'a [VariableOut],
+    pub ac_res: &'a [VariableOut],
     pub parameter_res: &'a [FunctionParameterOut],
 }
 
-pub fn load(l_in: LoadIn) {
+// A combined image sampler value that has been split into an image and a sampler value.
+// `res_id` is recombined from them with an OpSampledImage for its remaining users, see
+// `recombine`.
+pub struct SplitValueOut {
+    pub res_id: u32,
+    pub image_res_id: u32,
+    pub sampler_res_id: u32,
+    pub underlying_image_id: u32,
+}
+
+pub fn load(l_in: LoadIn) -> Vec<SplitValueOut> {
+    let mut l_res = vec![];
+
     let LoadIn {
-        spv,
-        new_spv,
+        module,
+        new_module,
         instruction_bound,
         instruction_inserts,
         op_type_sampler_res_id,
         op_loads_idxs,
+        non_uniform_decorate_idxs,
         v_res,
+        ac_res,
         parameter_res,
     } = l_in;
 
-    op_loads_idxs
+    let pointer_ids = v_res
         .iter()
-        .filter_map(|&l_idx| {
-            // - Find all OpLoads that ref our v_res_ids
-            v_res.iter().find_map(
-                |&VariableOut {
-                     v_res_id,
-                     new_sampler_v_res_id,
-                     underlying_image_id,
-                     ..
-                 }| {
-                    (v_res_id == spv[l_idx + 3]).then_some((
-                        l_idx,
-                        new_sampler_v_res_id,
-                        underlying_image_id,
-                    ))
-                },
+        .chain(ac_res.iter())
+        .map(|v| (v.v_res_id, (v.new_sampler_v_res_id, v.underlying_image_id)))
+        .collect::<HashMap<_, _>>();
+    let parameter_ids = parameter_res
+        .iter()
+        .map(|p| {
+            (
+                p.image_parameter_res_id,
+                (p.sampler_parameter_res_id, p.underlying_image_id),
             )
         })
-        .chain(op_loads_idxs.iter().filter_map(|&l_idx| {
-            // - Find all OpLoads that ref our parameter_res_ids
-            parameter_res.iter().find_map(
-                |FunctionParameterOut {
-                     image_parameter_res_id,
-                     sampler_parameter_res_id,
-                     underlying_image_id,
-                     ..
-                 }| {
-                    (*image_parameter_res_id == spv[l_idx + 3]).then_some((
-                        l_idx,
-                        *sampler_parameter_res_id,
-                        *underlying_image_id,
-                    ))
-                },
-            )
-        }))
+        .collect::<HashMap<_, _>>();
+    let split_load = |l_idx: usize, ids: &HashMap<u32, (u32, u32)>| {
+        let &(sampler_v_res_id, underlying_image_id) =
+            ids.get(&module.instructions[l_idx].operands[0])?;
+        Some((l_idx, sampler_v_res_id, underlying_image_id))
+    };
+
+    op_loads_idxs
+        .iter()
+        // - Find all OpLoads that ref our v_res_ids, directly or through an OpAccessChain
+        .filter_map(|&l_idx| split_load(l_idx, &pointer_ids))
+        // - Find all OpLoads that ref our parameter_res_ids
+        .chain(
+            op_loads_idxs
+                .iter()
+                .filter_map(|&l_idx| split_load(l_idx, &parameter_ids)),
+        )
         .for_each(|(l_idx, sampler_v_res_id, underlying_image_id)| {
-            // - Insert OpLoads and OpSampledImage to replace combimgsamp
+            // - Insert OpLoads to replace combimgsamp
             let image_op_load_res_id = *instruction_bound;
             *instruction_bound += 1;
 
-            let image_original_res_id = spv[l_idx + 2];
-            let original_combined_res_id = new_spv[l_idx + 1];
-
-            new_spv[l_idx + 1] = underlying_image_id;
-            new_spv[l_idx + 2] = image_op_load_res_id;
+            let image_original_res_id = module.instructions[l_idx].id();
+            new_module.instructions[l_idx].result_type = Some(underlying_image_id);
+            new_module.instructions[l_idx].result_id = Some(image_op_load_res_id);
 
             let sampler_op_load_res_id = *instruction_bound;
             *instruction_bound += 1;
             instruction_inserts.push(InstructionInsert {
-                previous_spv_idx: l_idx,
-                instruction: vec![
-                    encode_word(4, SPV_INSTRUCTION_OP_LOAD),
-                    op_type_sampler_res_id,
-                    sampler_op_load_res_id,
-                    sampler_v_res_id,
-                    encode_word(5, SPV_INSTRUCTION_OP_SAMPLED_IMAGE),
-                    original_combined_res_id,
-                    image_original_res_id,
-                    image_op_load_res_id,
-                    sampler_op_load_res_id,
-                ],
+                previous_idx: l_idx,
+                instructions: vec![Instruction::new(
+                    SPV_INSTRUCTION_OP_LOAD,
+                    Some(op_type_sampler_res_id),
+                    Some(sampler_op_load_res_id),
+                    vec![sampler_v_res_id],
+                )],
+            });
+
+            // - The original result id will belong to an OpSampledImage, so both new loads need
+            // their own NonUniform decoration
+            if let Some(&d_idx) = non_uniform_decorate_idxs.get(&image_original_res_id) {
+                instruction_inserts.push(util::decorate_non_uniform(
+                    d_idx,
+                    &[image_op_load_res_id, sampler_op_load_res_id],
+                ));
+            }
+
+            l_res.push(SplitValueOut {
+                res_id: image_original_res_id,
+                image_res_id: image_op_load_res_id,
+                sampler_res_id: sampler_op_load_res_id,
+                underlying_image_id,
             });
         });
+
+    l_res
 }
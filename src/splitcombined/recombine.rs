@@ -0,0 +1,99 @@
+use super::*;
+
+pub struct RecombineIn<'a> {
+    pub module: &'a Module,
+    pub new_module: &'a mut Module,
+    pub instruction_bound: &'a mut u32,
+    pub instruction_inserts: &'a mut Vec<InstructionInsert>,
+
+    pub non_uniform_decorate_idxs: &'a HashMap<u32, usize>,
+
+    // Loads, value parameters and forwarded values that have been split
+    pub values: &'a [SplitValueOut],
+}
+
+// An OpSampledImage must be in the same block as its users, but split values may be used in any
+// block they dominate. Recombine the image and the sampler right before every remaining user,
+// the first user keeps the original result id.
+pub fn recombine(r_in: RecombineIn) {
+    let RecombineIn {
+        module,
+        new_module,
+        instruction_bound,
+        instruction_inserts,
+        non_uniform_decorate_idxs,
+        values,
+    } = r_in;
+
+    if values.is_empty() {
+        return;
+    }
+
+    let definitions = module.definitions();
+    let split_values = values
+        .iter()
+        .map(|value| (value.res_id, value))
+        .collect::<HashMap<_, _>>();
+
+    // - Debug and annotation instructions come before the first function, they are not users
+    let first_function_idx = module
+        .instructions
+        .iter()
+        .position(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_FUNCTION)
+        .unwrap_or(module.instructions.len());
+
+    // - Forwarding instructions and function calls have already been rewritten to use the image
+    //   and the sampler, whoever still uses the original result id needs it recombined
+    let mut recombined_ids = HashSet::new();
+    for idx in first_function_idx..new_module.instructions.len() {
+        for operand_idx in 0..new_module.instructions[idx].operands.len() {
+            let instruction = &new_module.instructions[idx];
+            if spv_is_literal_operand(instruction.opcode, operand_idx) {
+                continue;
+            }
+            let Some(value) = split_values.get(&instruction.operands[operand_idx]) else {
+                continue;
+            };
+
+            let sampled_image_res_id = if recombined_ids.insert(value.res_id) {
+                value.res_id
+            } else {
+                let sampled_image_res_id = *instruction_bound;
+                *instruction_bound += 1;
+                if let Some(&d_idx) = non_uniform_decorate_idxs.get(&value.res_id) {
+                    instruction_inserts
+                        .push(util::decorate_non_uniform(d_idx, &[sampled_image_res_id]));
+                }
+                sampled_image_res_id
+            };
+            new_module.instructions[idx].operands[operand_idx] = sampled_image_res_id;
+
+            // - Users are never the first instruction of a block, that is always an OpLabel
+            debug_assert_ne!(module.instructions[idx].opcode, SPV_INSTRUCTION_OP_LABEL);
+            instruction_inserts.push(InstructionInsert {
+                previous_idx: idx - 1,
+                instructions: vec![Instruction::new(
+                    SPV_INSTRUCTION_OP_SAMPLED_IMAGE,
+                    Some(module.instructions[definitions[&value.res_id]].type_id()),
+                    Some(sampled_image_res_id),
+                    vec![value.image_res_id, value.sampler_res_id],
+                )],
+            });
+        }
+    }
+
+    // - Values without users are gone, and so are their names and decorations
+    for instruction in new_module.instructions[..first_function_idx].iter_mut() {
+        if matches!(
+            instruction.opcode,
+            SPV_INSTRUCTION_OP_NAME
+                | SPV_INSTRUCTION_OP_DECORATE
+                | SPV_INSTRUCTION_OP_DECORATE_ID
+                | SPV_INSTRUCTION_OP_DECORATE_STRING
+        ) && instruction.operands.first().is_some_and(|target_id| {
+            split_values.contains_key(target_id) && !recombined_ids.contains(target_id)
+        }) {
+            *instruction = Instruction::nop();
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use super::*;
+
+pub struct TypeArrayIn<'a> {
+    pub module: &'a Module,
+    pub new_module: &'a mut Module,
+    pub instruction_bound: &'a mut u32,
+    pub instruction_inserts: &'a mut Vec<InstructionInsert>,
+
+    pub op_type_sampler_res_id: u32,
+    pub op_type_array_idxs: &'a [usize],
+    // OpTypeSampledImage id -> the OpTypeImage it combines
+    pub underlying_image_ids: &'a HashMap<u32, u32>,
+}
+
+pub struct TypeArrayOut {
+    pub ta_res_id: u32,
+    pub sampler_ta_res_id: u32,
+}
+
+pub fn type_array(ta_in: TypeArrayIn) -> Vec<TypeArrayOut> {
+    let mut ta_res: Vec<TypeArrayOut> = vec![];
+
+    let TypeArrayIn {
+        module,
+        new_module,
+        instruction_bound,
+        instruction_inserts,
+        op_type_sampler_res_id,
+        op_type_array_idxs,
+        underlying_image_ids,
+    } = ta_in;
+
+    let mut sampler_array_ids = HashMap::new();
+
+    // - Both OpTypeArray and OpTypeRuntimeArray keep their element type as the first operand
+    // - Types are declared before use, so arrays of arrays come after their element array
+    for &ta_idx in op_type_array_idxs.iter() {
+        let ta = &module.instructions[ta_idx];
+        let element_id = ta.operands[0];
+
+        let sampler_element_id =
+            if let Some(&underlying_image_id) = underlying_image_ids.get(&element_id) {
+                // - Change the combined image sampler element to the underlying image type
+                new_module.instructions[ta_idx].operands[0] = underlying_image_id;
+                op_type_sampler_res_id
+            } else if let Some(&sampler_ta_res_id) = sampler_array_ids.get(&element_id) {
+                sampler_ta_res_id
+            } else {
+                continue;
+            };
+
+        // - Declare a matching array of samplers right after the original array
+        let sampler_ta_res_id = *instruction_bound;
+        *instruction_bound += 1;
+        let mut operands = ta.operands.clone();
+        operands[0] = sampler_element_id;
+        instruction_inserts.push(InstructionInsert {
+            previous_idx: ta_idx,
+            instructions: vec![Instruction::new(
+                ta.opcode,
+                None,
+                Some(sampler_ta_res_id),
+                operands,
+            )],
+        });
+
+        sampler_array_ids.insert(ta.id(), sampler_ta_res_id);
+        ta_res.push(TypeArrayOut {
+            ta_res_id: ta.id(),
+            sampler_ta_res_id,
+        });
+    }
+
+    ta_res
+}
@@ -1,40 +1,60 @@
 use super::*;
 
 pub struct TypeFunctionIn<'a> {
-    pub spv: &'a [u32],
-    pub word_inserts: &'a mut Vec<WordInsert>,
+    pub module: &'a Module,
+    pub new_module: &'a mut Module,
+    pub operand_inserts: &'a mut Vec<OperandInsert>,
 
-    pub op_type_pointer_sampler_res_id: u32,
+    pub op_type_sampler_res_id: u32,
     pub op_type_function_idxs: &'a [usize],
+    // OpTypeSampledImage id -> the OpTypeImage it combines
+    pub underlying_image_ids: &'a HashMap<u32, u32>,
 
     pub tp_res: &'a [TypePointerOut],
 }
 
 pub fn type_function(tf_in: TypeFunctionIn) {
     let TypeFunctionIn {
-        spv,
-        word_inserts,
-        op_type_pointer_sampler_res_id,
+        module,
+        new_module,
+        operand_inserts,
+        op_type_sampler_res_id,
         op_type_function_idxs,
+        underlying_image_ids,
         tp_res,
     } = tf_in;
 
+    let sampler_pointer_types = tp_res
+        .iter()
+        .map(|tp| (tp.tp_res_id, tp.sampler_tp_res_id))
+        .collect::<HashMap<_, _>>();
+
     op_type_function_idxs.iter().for_each(|&tf_idx| {
-        // - Append a sampler OpTypePointer to OpTypeFunction instruction when an combimg OpTypePointer is found.
-        tp_res.iter().for_each(|&TypePointerOut { tp_res_id, .. }| {
-            let word_count = hiword(spv[tf_idx]);
-            for (i, ty) in spv[tf_idx + 3..tf_idx + word_count as usize]
-                .iter()
-                .enumerate()
-            {
-                if *ty == tp_res_id {
-                    word_inserts.push(WordInsert {
-                        idx: tf_idx + 3 + i,
-                        word: op_type_pointer_sampler_res_id,
-                        head_idx: tf_idx,
-                    })
-                }
+        // Skip the return type
+        for (i, ty) in module.instructions[tf_idx]
+            .operands
+            .iter()
+            .enumerate()
+            .skip(1)
+        {
+            // - Append a sampler OpTypePointer to OpTypeFunction instruction when an combimg
+            //   OpTypePointer is found.
+            if let Some(&sampler_tp_res_id) = sampler_pointer_types.get(ty) {
+                operand_inserts.push(OperandInsert {
+                    idx: tf_idx,
+                    operand_idx: i,
+                    operand: sampler_tp_res_id,
+                })
+            }
+            // - Combimgs passed by value become an image and an OpTypeSampler
+            else if let Some(&underlying_image_id) = underlying_image_ids.get(ty) {
+                new_module.instructions[tf_idx].operands[i] = underlying_image_id;
+                operand_inserts.push(OperandInsert {
+                    idx: tf_idx,
+                    operand_idx: i,
+                    operand: op_type_sampler_res_id,
+                })
             }
-        })
+        }
     });
 }
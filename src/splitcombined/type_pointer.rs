@@ -1,13 +1,23 @@
+use super::*;
+
 pub struct TypePointerIn<'a> {
-    pub spv: &'a [u32],
-    pub new_spv: &'a mut [u32],
+    pub module: &'a Module,
+    pub new_module: &'a mut Module,
+    pub instruction_bound: &'a mut u32,
+    pub instruction_inserts: &'a mut Vec<InstructionInsert>,
 
+    pub op_type_pointer_sampler_res_id: u32,
     pub op_type_pointer_idxs: &'a [usize],
-    pub op_type_sampled_image_idxs: &'a [usize],
+    // OpTypeSampledImage id -> the OpTypeImage it combines
+    pub underlying_image_ids: &'a HashMap<u32, u32>,
+
+    pub ta_res: &'a [TypeArrayOut],
 }
 
 pub struct TypePointerOut {
     pub tp_res_id: u32,
+    pub sampler_tp_res_id: u32,
+    // For arrays, this is the array type, which now holds the underlying images
     pub underlying_image_id: u32,
 }
 
@@ -15,31 +25,60 @@ pub fn type_pointer(tp_in: TypePointerIn) -> Vec<TypePointerOut> {
     let mut tp_res = vec![];
 
     let TypePointerIn {
-        spv,
-        new_spv,
+        module,
+        new_module,
+        instruction_bound,
+        instruction_inserts,
+        op_type_pointer_sampler_res_id,
         op_type_pointer_idxs,
-        op_type_sampled_image_idxs,
+        underlying_image_ids,
+        ta_res,
     } = tp_in;
 
-    op_type_pointer_idxs
+    let sampler_array_ids = ta_res
         .iter()
-        .filter_map(|&tp_spv_idx| {
-            // - Find OpTypePointers that ref OpTypeSampledImage
-            op_type_sampled_image_idxs.iter().find_map(|&ts_spv_idx| {
-                (spv[tp_spv_idx + 3] == spv[ts_spv_idx + 1])
-                    .then_some((tp_spv_idx, spv[ts_spv_idx + 2]))
-            })
-        })
-        .for_each(|(tp_spv_idx, underlying_image_id)| {
+        .map(|ta| (ta.ta_res_id, ta.sampler_ta_res_id))
+        .collect::<HashMap<_, _>>();
+
+    op_type_pointer_idxs.iter().for_each(|&tp_idx| {
+        let tp = &module.instructions[tp_idx];
+        let pointee_id = tp.operands[1];
+
+        // - Find OpTypePointers that ref OpTypeSampledImage
+        if let Some(&underlying_image_id) = underlying_image_ids.get(&pointee_id) {
             // - Change combined image sampler type to underlying image type
-            new_spv[tp_spv_idx + 3] = underlying_image_id;
+            new_module.instructions[tp_idx].operands[1] = underlying_image_id;
 
             // - Save the OpTypePointer res id for later
             tp_res.push(TypePointerOut {
-                tp_res_id: spv[tp_spv_idx + 1],
+                tp_res_id: tp.id(),
+                sampler_tp_res_id: op_type_pointer_sampler_res_id,
                 underlying_image_id,
             });
-        });
+        }
+        // - Find OpTypePointers that ref an array of OpTypeSampledImage
+        else if let Some(&sampler_ta_res_id) = sampler_array_ids.get(&pointee_id) {
+            // - The array itself was changed in place, so we only need a pointer to the sampler
+            // array
+            let sampler_tp_res_id = *instruction_bound;
+            *instruction_bound += 1;
+            instruction_inserts.push(InstructionInsert {
+                previous_idx: tp_idx,
+                instructions: vec![Instruction::new(
+                    SPV_INSTRUCTION_OP_TYPE_POINTER,
+                    None,
+                    Some(sampler_tp_res_id),
+                    vec![SPV_STORAGE_CLASS_UNIFORM_CONSTANT, sampler_ta_res_id],
+                )],
+            });
+
+            tp_res.push(TypePointerOut {
+                tp_res_id: tp.id(),
+                sampler_tp_res_id,
+                underlying_image_id: pointee_id,
+            });
+        }
+    });
 
     tp_res
 }
@@ -0,0 +1,67 @@
+use super::*;
+
+pub struct ValueParameterIn<'a> {
+    pub module: &'a Module,
+    pub new_module: &'a mut Module,
+    pub instruction_bound: &'a mut u32,
+    pub instruction_inserts: &'a mut Vec<InstructionInsert>,
+
+    pub op_type_sampler_res_id: u32,
+    pub op_function_parameter_idxs: &'a [usize],
+    // OpTypeSampledImage id -> the OpTypeImage it combines
+    pub underlying_image_ids: &'a HashMap<u32, u32>,
+}
+
+pub fn value_parameter(vp_in: ValueParameterIn) -> Vec<SplitValueOut> {
+    let mut vp_res = vec![];
+
+    let ValueParameterIn {
+        module,
+        new_module,
+        instruction_bound,
+        instruction_inserts,
+        op_type_sampler_res_id,
+        op_function_parameter_idxs,
+        underlying_image_ids,
+    } = vp_in;
+
+    op_function_parameter_idxs
+        .iter()
+        .filter_map(|&fp_idx| {
+            // - Find all OpFunctionParameters that take a combimg by value
+            let fp = &module.instructions[fp_idx];
+            underlying_image_ids
+                .get(&fp.type_id())
+                .map(|&underlying_image_id| (fp_idx, underlying_image_id))
+        })
+        .for_each(|(fp_idx, underlying_image_id)| {
+            let fp = &module.instructions[fp_idx];
+
+            // - The original parameter takes the image, a new one after it takes the sampler
+            let image_parameter_res_id = *instruction_bound;
+            *instruction_bound += 1;
+            let sampler_parameter_res_id = *instruction_bound;
+            *instruction_bound += 1;
+
+            new_module.instructions[fp_idx].result_type = Some(underlying_image_id);
+            new_module.instructions[fp_idx].result_id = Some(image_parameter_res_id);
+            instruction_inserts.push(InstructionInsert {
+                previous_idx: fp_idx,
+                instructions: vec![Instruction::new(
+                    SPV_INSTRUCTION_OP_FUNCTION_PARAMTER,
+                    Some(op_type_sampler_res_id),
+                    Some(sampler_parameter_res_id),
+                    vec![],
+                )],
+            });
+
+            vp_res.push(SplitValueOut {
+                res_id: fp.id(),
+                image_res_id: image_parameter_res_id,
+                sampler_res_id: sampler_parameter_res_id,
+                underlying_image_id,
+            });
+        });
+
+    vp_res
+}
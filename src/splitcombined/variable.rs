@@ -1,12 +1,13 @@
 use super::*;
 
 pub struct VariableIn<'a> {
-    pub spv: &'a [u32],
+    pub module: &'a Module,
     pub instruction_bound: &'a mut u32,
     pub instruction_inserts: &'a mut Vec<InstructionInsert>,
 
-    pub op_type_pointer_sampler_res_id: u32,
     pub op_variables_idxs: &'a [usize],
+    pub fetch_only_v_res_ids: &'a HashSet<u32>,
+    pub dead_only_v_res_ids: &'a HashSet<u32>,
 
     pub tp_res: &'a [TypePointerOut],
 }
@@ -21,51 +22,54 @@ pub fn variable(v_in: VariableIn) -> Vec<VariableOut> {
     let mut v_res = vec![];
 
     let VariableIn {
-        spv,
+        module,
         instruction_bound,
         instruction_inserts,
-        op_type_pointer_sampler_res_id,
         op_variables_idxs,
+        fetch_only_v_res_ids,
+        dead_only_v_res_ids,
         tp_res,
     } = v_in;
 
+    let pointer_types = tp_res
+        .iter()
+        .map(|tp| (tp.tp_res_id, tp))
+        .collect::<HashMap<_, _>>();
+
     op_variables_idxs
         .iter()
+        .filter(|&&v_idx| {
+            let v_res_id = module.instructions[v_idx].id();
+            !fetch_only_v_res_ids.contains(&v_res_id) && !dead_only_v_res_ids.contains(&v_res_id)
+        })
         .filter_map(|&v_idx| {
             // - Find all OpVariables that ref our tp_spv_idxs
-            tp_res.iter().find_map(
-                |&TypePointerOut {
-                     tp_res_id,
-                     underlying_image_id,
-                 }| {
-                    (tp_res_id == spv[v_idx + 1]).then_some((
-                        v_idx,
-                        spv[v_idx + 2],
-                        underlying_image_id,
-                    ))
-                },
-            )
+            let v = &module.instructions[v_idx];
+            let tp = pointer_types.get(&v.type_id())?;
+            Some((v_idx, v.id(), tp.sampler_tp_res_id, tp.underlying_image_id))
         })
-        .for_each(|(v_idx, v_res_id, underlying_image_id)| {
-            // - Inject OpVariable for new sampler
-            let new_sampler_v_res_id = *instruction_bound;
-            *instruction_bound += 1;
-            instruction_inserts.push(InstructionInsert {
-                previous_spv_idx: v_idx,
-                instruction: vec![
-                    encode_word(4, SPV_INSTRUCTION_OP_VARIABLE),
-                    op_type_pointer_sampler_res_id,
+        .for_each(
+            |(v_idx, v_res_id, sampler_tp_res_id, underlying_image_id)| {
+                // - Inject OpVariable for new sampler
+                let new_sampler_v_res_id = *instruction_bound;
+                *instruction_bound += 1;
+                instruction_inserts.push(InstructionInsert {
+                    previous_idx: v_idx,
+                    instructions: vec![Instruction::new(
+                        SPV_INSTRUCTION_OP_VARIABLE,
+                        Some(sampler_tp_res_id),
+                        Some(new_sampler_v_res_id),
+                        vec![SPV_STORAGE_CLASS_UNIFORM_CONSTANT],
+                    )],
+                });
+                // - Save the OpVariable res id for later
+                v_res.push(VariableOut {
+                    v_res_id,
                     new_sampler_v_res_id,
-                    SPV_STORAGE_CLASS_UNIFORM_CONSTANT,
-                ],
-            });
-            // - Save the OpVariable res id for later
-            v_res.push(VariableOut {
-                v_res_id,
-                new_sampler_v_res_id,
-                underlying_image_id,
-            });
-        });
+                    underlying_image_id,
+                });
+            },
+        );
 
     v_res
 }
@@ -1,760 +1,745 @@
 use super::*;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum OperationVariant {
-    Regular,
-    Dref,
+mod shared_load;
+
+use shared_load::*;
+
+#[derive(Debug, Clone, Copy)]
+struct ComplementType {
+    // The variant that keeps the original type
+    primary: OperationVariant,
+    complement_tp_id: u32,
+    complement_ti_id: u32,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum LoadType {
-    Variable,
-    FunctionArgument,
+// A function specialized for the variables it is called with
+#[derive(Debug, Clone)]
+struct FunctionInstance {
+    function_id: u32,
+    // The variable that replaces each parameter, if any
+    bound_variable_ids: Vec<Option<u32>>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum MixState {
-    Mixed,
-    PotentiallyMixed,
+/// Perform the operation on a `Vec<u32>`.
+/// Use [u8_slice_to_u32_vec] to convert a `&[u8]` into a `Vec<u32>`
+pub fn drefsplitter(
+    in_spv: &[u32],
+    corrections: &mut Option<CorrectionMap>,
+) -> Result<Vec<u32>, TransformError> {
+    drefsplitter_with_allocator(in_spv, corrections, &mut ShiftBindings)
 }
 
-trait IsIndexOrId {}
-impl IsIndexOrId for u32 {}
-impl IsIndexOrId for usize {}
+/// Same as [drefsplitter], but new variables are bound by `allocator` instead of [ShiftBindings].
+pub fn drefsplitter_with_allocator(
+    in_spv: &[u32],
+    corrections: &mut Option<CorrectionMap>,
+    allocator: &mut dyn BindingAllocator,
+) -> Result<Vec<u32>, TransformError> {
+    PassManager::new()
+        .add(FlattenDecorationGroupsPass)
+        .add(DefaultDescriptorSetPass::default())
+        .add(SplitDrefPass { allocator })
+        .run(in_spv, corrections)
+}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum PatchObjectType<T: IsIndexOrId> {
-    Sampler(T),
-    Image(T),
+/// [drefsplitter] as a [Pass].
+#[derive(Debug, Clone, Default)]
+pub struct SplitDrefPass<A = ShiftBindings> {
+    pub allocator: A,
 }
 
-impl<T> PatchObjectType<T>
-where
-    T: IsIndexOrId,
-{
-    fn next<N: IsIndexOrId>(self, next_id: N) -> PatchObjectType<N> {
-        match self {
-            PatchObjectType::Sampler(_) => PatchObjectType::Sampler(next_id),
-            PatchObjectType::Image(_) => PatchObjectType::Image(next_id),
-        }
+impl<A: BindingAllocator> Pass for SplitDrefPass<A> {
+    fn name(&self) -> &str {
+        "split-dref"
     }
 
-    fn inner(self) -> T {
-        match self {
-            PatchObjectType::Sampler(v) => v,
-            PatchObjectType::Image(v) => v,
-        }
+    fn run(
+        &mut self,
+        module: &mut Module,
+        instruction_bound: &mut u32,
+        corrections: &mut Option<CorrectionMap>,
+    ) -> Result<(), TransformError> {
+        split_dref(module, instruction_bound, corrections, &mut self.allocator)
     }
 }
 
-/// Perform the operation on a `Vec<u32>`.
-/// Use [u8_slice_to_u32_vec] to convert a `&[u8]` into a `Vec<u32>`
-pub fn drefsplitter(
-    in_spv: &[u32],
+// `out_module` is left untouched if there is nothing to split.
+fn split_dref(
+    out_module: &mut Module,
+    out_instruction_bound: &mut u32,
     corrections: &mut Option<CorrectionMap>,
-) -> Result<Vec<u32>, ()> {
-    let spv = in_spv.to_owned();
-
-    let mut instruction_bound = spv[SPV_HEADER_INSTRUCTION_BOUND_OFFSET];
-    let magic_number = spv[SPV_HEADER_MAGIC_NUM_OFFSET];
-
-    let spv_header = spv[0..SPV_HEADER_LENGTH].to_owned();
+    allocator: &mut dyn BindingAllocator,
+) -> Result<(), TransformError> {
+    let mut instruction_bound = *out_instruction_bound;
 
-    assert_eq!(magic_number, SPV_HEADER_MAGIC);
+    // 1. Give every OpLoad and OpSampledImage that reaches both operation types its own copy
+    let module = shared_load(out_module, &mut instruction_bound);
 
     let mut instruction_inserts: Vec<InstructionInsert> = vec![];
-    let mut word_inserts: Vec<WordInsert> = vec![];
+    let mut operand_inserts = vec![];
 
-    let spv = spv.into_iter().skip(SPV_HEADER_LENGTH).collect::<Vec<_>>();
-    let mut new_spv = spv.clone();
+    let mut new_module = module.clone();
 
-    // 1. Find locations instructions we need
+    // 2. Find locations instructions we need
     let mut op_dref_operation_idxs = vec![];
     let mut op_sampled_operation_idxs = vec![];
     let mut op_sampled_image_idxs = vec![];
     let mut op_load_idxs = vec![];
     let mut op_variable_idxs = vec![];
     let mut op_decorate_idxs = vec![];
+    let mut op_name_idxs = vec![];
     let mut op_type_image_idxs = vec![];
     let mut op_type_pointer_idxs = vec![];
     let mut op_type_function_idxs = vec![];
-    let mut op_function_idxs = vec![];
-    let mut op_function_call_idxs = vec![];
-    let mut op_function_parameter_idxs = vec![];
-
-    let mut first_op_type_sampler_id = None;
-    let mut first_op_type_pointer_sampler_id = None;
-
-    let mut spv_idx = 0;
-    while spv_idx < spv.len() {
-        let op = spv[spv_idx];
-        let word_count = hiword(op);
-        let instruction = loword(op);
-
-        match instruction {
-            SPV_INSTRUCTION_OP_SAMPLED_IMAGE => op_sampled_image_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_LOAD => op_load_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_IMAGE_SAMPLE_DREF_IMPLICIT_LOD
-            | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_DREF_EXPLICIT_LOD
-            | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_PROJ_DREF_IMPLICIT_LOD
-            | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_PROJ_DREF_EXPLICIT_LOD
-            | SPV_INSTRUCTION_OP_IMAGE_DREF_GATHER
-            | SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_DREF_IMPLICIT_LOD
-            | SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_DREF_EXPLICIT_LOD
-            | SPV_INSTRUCTION_OP_IMAGE_SPARSE_DREF_GATHER => op_dref_operation_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_IMAGE_SAMPLE_IMPLICIT_LOD
-            | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_EXPLICIT_LOD
-            | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_PROJ_IMPLICIT_LOD
-            | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_PROJ_EXPLICIT_LOD
-            | SPV_INSTRUCTION_OP_IMAGE_GATHER
-            | SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_IMPLICIT_LOD
-            | SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_EXPLICIT_LOD
-            | SPV_INSTRUCTION_OP_IMAGE_SPARSE_GATHER => op_sampled_operation_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_VARIABLE => op_variable_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_DECORATE => op_decorate_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_TYPE_IMAGE => op_type_image_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_TYPE_SAMPLER => {
-                first_op_type_sampler_id.get_or_insert(spv[spv_idx + 1]);
-            }
-            SPV_INSTRUCTION_OP_TYPE_POINTER => {
-                if first_op_type_sampler_id == Some(spv[spv_idx + 3])
-                    && spv[spv_idx + 2] == SPV_STORAGE_CLASS_UNIFORM_CONSTANT
-                {
-                    first_op_type_pointer_sampler_id = Some(spv[spv_idx + 1]);
-                }
-                op_type_pointer_idxs.push(spv_idx)
-            }
-            SPV_INSTRUCTION_OP_TYPE_FUNCTION => op_type_function_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_FUNCTION => op_function_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_FUNCTION_CALL => op_function_call_idxs.push(spv_idx),
-            SPV_INSTRUCTION_OP_FUNCTION_PARAMTER => op_function_parameter_idxs.push(spv_idx),
+
+    let mut has_op_type_sampler = false;
+
+    for (idx, instruction) in module.instructions.iter().enumerate() {
+        match operation_variant(instruction.opcode) {
+            Some(OperationVariant::Regular) => op_sampled_operation_idxs.push(idx),
+            Some(OperationVariant::Dref) => op_dref_operation_idxs.push(idx),
+            None => {}
+        }
+        match instruction.opcode {
+            SPV_INSTRUCTION_OP_SAMPLED_IMAGE => op_sampled_image_idxs.push(idx),
+            SPV_INSTRUCTION_OP_LOAD => op_load_idxs.push(idx),
+            SPV_INSTRUCTION_OP_VARIABLE => op_variable_idxs.push(idx),
+            SPV_INSTRUCTION_OP_DECORATE => op_decorate_idxs.push(idx),
+            SPV_INSTRUCTION_OP_NAME => op_name_idxs.push(idx),
+            SPV_INSTRUCTION_OP_TYPE_IMAGE => op_type_image_idxs.push(idx),
+            SPV_INSTRUCTION_OP_TYPE_SAMPLER => has_op_type_sampler = true,
+            SPV_INSTRUCTION_OP_TYPE_POINTER => op_type_pointer_idxs.push(idx),
+            SPV_INSTRUCTION_OP_TYPE_FUNCTION => op_type_function_idxs.push(idx),
             _ => {}
         }
-
-        spv_idx += word_count as usize;
     }
 
-    let first_op_deocrate_idx = op_decorate_idxs.first().copied();
-
     // If there is no OpTypeSampler, either this is invalid, or we do not need to do any patching at all.
-    let (Some(first_op_type_sampler_id), Some(first_op_type_pointer_sampler_id)) =
-        (first_op_type_sampler_id, first_op_type_pointer_sampler_id)
-    else {
-        return Ok(in_spv.to_vec());
-    };
+    if !has_op_type_sampler {
+        return Ok(());
+    }
 
-    // 2. Collect all the loaded sampled images of both operation types
-    // Conveniently, the offset for this value is always +3 for all of these operations
+    // 3. Collect all the loaded sampled images of both operation types
+    // Conveniently, this value is always the first operand for all of these operations.
+    // It may have been forwarded through OpCopyObject, OpPhi and OpSelect on the way.
+    let trace = ValueTrace::new(&module);
     let loaded_sampled_image_ids = op_sampled_operation_idxs
         .iter()
-        .map(|idx| (spv[idx + 3], OperationVariant::Regular))
+        .map(|&idx| (idx, OperationVariant::Regular))
         .chain(
             op_dref_operation_idxs
                 .iter()
-                .map(|idx| (spv[idx + 3], OperationVariant::Dref)),
+                .map(|&idx| (idx, OperationVariant::Dref)),
         )
-        .collect::<Vec<_>>();
+        .flat_map(|(idx, ty)| {
+            trace
+                .roots(module.instructions[idx].operands[0])
+                .into_iter()
+                .map(move |id| (id, ty))
+        })
+        .collect::<HashMap<_, _>>();
 
-    // 3. Backtrace to find the OpSampledImage that resulted in our loaded sampled images
-    let loaded_variable_ids = op_sampled_image_idxs
+    // 4. Backtrace to find the OpSampledImage that resulted in our loaded sampled images
+    let loaded_object_ids = op_sampled_image_idxs
         .iter()
         .filter_map(|idx| {
-            let sampled_result_id = spv[idx + 2];
-            let loaded_image_id = spv[idx + 3];
-            let loaded_sampler_id = spv[idx + 4];
+            let sampled_image = &module.instructions[*idx];
             loaded_sampled_image_ids
+                .get(&sampled_image.id())
+                .map(|&ty| (&sampled_image.operands[..2], ty))
+        })
+        .flat_map(|(loaded_ids, ty)| {
+            loaded_ids
                 .iter()
-                .find_map(|(id, ty)| (*id == sampled_result_id).then_some(ty))
-                .map(|ty| {
-                    [
-                        (PatchObjectType::Image(loaded_image_id), ty),
-                        (PatchObjectType::Sampler(loaded_sampler_id), ty),
-                    ]
-                })
+                .flat_map(|&id| trace.roots(id))
+                .map(move |id| (id, ty))
         })
-        .flatten()
-        .collect::<Vec<_>>();
+        .collect::<HashMap<_, _>>();
 
-    // 4. Backtrack to find the OpLoad that resulted in our loaded images
-    let object_ids = op_load_idxs
+    // 5. Backtrack to find the OpLoad that resulted in our loaded images and samplers
+    // The loaded pointer is either an OpVariable or an OpFunctionParameter
+    let object_loads = op_load_idxs
         .iter()
-        .filter_map(|idx| {
-            let loaded_result_id = spv[idx + 2];
-            let original_image_or_sampler = spv[idx + 3];
-            loaded_variable_ids
-                .iter()
-                .find_map(|(id, ty)| (id.inner() == loaded_result_id).then_some((id, ty)))
-                .map(|(id, ty)| (id.next(original_image_or_sampler), idx, ty))
+        .filter_map(|&idx| {
+            let load = &module.instructions[idx];
+            loaded_object_ids
+                .get(&load.id())
+                .map(|&ty| (idx, load.operands[0], ty))
         })
         .collect::<Vec<_>>();
 
-    // 5. Find the images that mismatch operations
-    let mut mixed_object_ids = HashMap::new();
-    let mut patch_object_id_to_loads = HashMap::new();
-
-    for (id, load_idx, ty) in object_ids.iter().copied() {
-        let entry = mixed_object_ids.entry(id).or_insert((false, false));
+    let load_variants = object_loads
+        .iter()
+        .map(|&(load_idx, _, ty)| (load_idx, ty))
+        .collect::<HashMap<_, _>>();
+
+    // 6. Find the operations that directly reach each image and sampler
+    // Functions that no entry point reaches do not bind anything, so they are left out.
+    let graph = CallGraph::new(&module)?;
+    let is_live = |idx: usize| {
+        graph
+            .function_of(idx)
+            .is_none_or(|function| graph.is_live(function))
+    };
+    let mut usages: HashMap<u32, Usage> = HashMap::new();
+    for &(load_idx, object_id, ty) in object_loads.iter() {
+        if is_live(load_idx) {
+            usages.entry(object_id).or_default().insert(ty);
+        }
+    }
 
-        match ty {
-            OperationVariant::Regular => entry.0 = true,
-            OperationVariant::Dref => {
-                entry.1 = true;
+    // 7. Propagate the usages of function parameters to the arguments of every call
+    // Arguments may be function parameters themselves, so keep going until nothing changes.
+    // This is what catches an image or sampler that is only mixed across different functions.
+    let functions = &graph.functions;
+    let mut call_edges = vec![];
+    for (caller, calls) in graph.calls.iter().enumerate() {
+        for &(call_idx, callee) in calls.iter() {
+            let call = &module.instructions[call_idx];
+            let parameter_idxs = &functions[callee].parameter_idxs;
+            if call.operands.len() != 1 + parameter_idxs.len() {
+                return Err(TransformError::unsupported(
+                    &module,
+                    call_idx,
+                    "OpFunctionCall argument count does not match its OpFunction",
+                ));
+            }
+            if !graph.is_live(caller) {
+                continue;
+            }
+            for (&argument_id, &parameter_idx) in call.operands[1..].iter().zip(parameter_idxs) {
+                call_edges.push((argument_id, module.instructions[parameter_idx].id()));
             }
         }
-        patch_object_id_to_loads
-            .entry(id)
-            .or_insert(vec![])
-            .push((load_idx, ty));
     }
 
-    let mixed_object_ids = mixed_object_ids
-        .into_iter()
-        .filter_map(|(id, (uses_regular, uses_dref))| (uses_regular && uses_dref).then_some(id))
-        .collect::<Vec<_>>();
-
-    // 6. Find the OpVariable of the mismatched images
-    let patch_variable_idxs = op_variable_idxs
-        .iter()
-        .filter_map(|idx: &usize| {
-            let result_id = spv[*idx + 2];
-            mixed_object_ids
-                .iter()
-                .find(|id| id.inner() == result_id)
-                .map(|id| id.next(*idx))
-        })
-        .collect::<Vec<_>>();
-
-    // 7. Find OpFunctionParameter of ~~the mismatched~~ ALL images operations
-    // Later, we can keep the ones that trace to mismatched global variables
-    let patch_function_parameter_idxs = op_function_parameter_idxs
-        .iter()
-        .filter_map(|idx: &usize| {
-            let result_id = spv[*idx + 2];
-            mixed_object_ids
-                .iter()
-                .find_map(|id| {
-                    (id.inner() == result_id).then_some((id.next(*idx), MixState::Mixed))
-                })
-                .or(object_ids.iter().find_map(|(id, _, _)| {
-                    (id.inner() == result_id).then_some((id.next(*idx), MixState::PotentiallyMixed))
-                }))
-        })
-        .collect::<Vec<_>>();
-
-    // 8. Find the OpVariable that eventually reaches OpFunctionCall of our OpFunctions
-    // Because functions may be deeply nested, we'll have to account for other OpFunctionCalls
-    let function_patch_variables_with_calls = patch_function_parameter_idxs
-        .iter()
-        .map(|&(idx, mix_state)| {
-            let mut traced_function_calls = vec![];
-            let entry = get_function_from_parameter(&spv, idx.inner());
-            let variables =
-                trace_function_argument_to_variables(TraceFunctionArgumentToVariablesIn {
-                    spv: &spv,
-                    op_variable_idxs: &op_variable_idxs,
-                    op_function_parameter_idxs: &op_function_parameter_idxs,
-                    op_function_call_idxs: &op_function_call_idxs,
-                    entry,
-                    traced_function_call_idxs: &mut traced_function_calls,
-                });
-            (
-                variables
-                    .into_iter()
-                    .map(|v| idx.next(v))
-                    .collect::<Vec<_>>(),
-                traced_function_calls,
-                mix_state,
-            )
-        })
-        .collect::<Vec<_>>();
-
-    // Filter out PotentiallyMixed parameters that don't relate to any Mixed function parameters or
-    // mixed variables
-    // TODO: This cannot handle mixing between different contexts, see `test_hidden3_dref.frag`
-    let function_patch_variables_with_calls = function_patch_variables_with_calls
-        .iter()
-        .cloned()
-        .filter_map(|(variables, calls, mix_state)| match mix_state {
-            MixState::Mixed => Some((variables, calls)),
-            MixState::PotentiallyMixed => (function_patch_variables_with_calls.iter().any(
-                |(mixed_variables, _, mix_state)| {
-                    *mix_state == MixState::Mixed
-                        && mixed_variables
-                            .iter()
-                            .any(|va| variables.iter().any(|vb| va == vb))
-                },
-            ) || patch_variable_idxs
-                .iter()
-                .any(|idx| variables.iter().any(|va| va == idx)))
-            .then_some((variables, calls)),
-        })
-        .collect::<Vec<_>>();
-
-    let mut patch_variable_idxs = patch_variable_idxs
-        .iter()
-        .copied()
-        .map(|idx| (idx, LoadType::Variable))
-        .collect::<Vec<_>>();
-
-    let mut function_patch_variable_set = HashSet::new();
-    for (variables, _) in function_patch_variables_with_calls.iter() {
-        for variable in variables {
-            if !function_patch_variable_set.contains(variable) {
-                patch_variable_idxs.push((*variable, LoadType::FunctionArgument));
-                function_patch_variable_set.insert(*variable);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(argument_id, parameter_id) in call_edges.iter() {
+            if let Some(&usage) = usages.get(&parameter_id) {
+                changed |= usages.entry(argument_id).or_default().merge(usage);
             }
         }
     }
 
-    // 9. Find OpTypePointer that resulted in OpVariable
-    let patch_variable_idxs = patch_variable_idxs.into_iter().map(|(variable_idx, lty)| {
-        let type_pointer_id = spv[variable_idx.inner() + 1];
-        let maybe_tp_idx = op_type_pointer_idxs.iter().find(|&tp_idx| {
-            let tp_id = spv[tp_idx + 1];
-            type_pointer_id == tp_id
-        });
-        (variable_idx, lty, maybe_tp_idx.copied())
-    });
+    let is_mixed = |id: u32| usages.get(&id).is_some_and(|usage| usage.is_mixed());
 
-    // 10. Find OpTypeImage that resulted in OpTypePointer
+    // 8. Find the OpTypeImage of the mixed variables
     //    We also want to create an complement OpTypeImage (depth=!depth) (without duplicates) and
-    //    a respective OpTypePointer ~~and OpTypeSampledImage pair~~ (also no duplicates).
-    let mut existing_type_pointers_from_type_image = HashMap::new();
-    let mut existing_type_images_from_complement_instruction = HashMap::new();
-
-    let patch_variable_idxs = patch_variable_idxs
-        .map(|(variable_idx, lty, tp_idx)| {
-            match variable_idx {
-                v @ PatchObjectType::Sampler(variable_idx) => {
-                    (
-                        v.next(variable_idx),
-                        lty,
-                        first_op_type_sampler_id,
-                        first_op_type_pointer_sampler_id,
-                        first_op_type_sampler_id,
-                        // From the perspective of a SPIRV sampler variable, this doesn't matter
-                        OperationVariant::Dref,
-                    )
-                }
-                v @ PatchObjectType::Image(variable_idx) => {
-                    let variable_result_id = spv[variable_idx];
-                    let image_type_id = if let Some(tp_idx) = tp_idx {
-                        // type_image_id
-                        spv[tp_idx + 3]
-                    } else if let Some(load_idxs) =
-                        patch_object_id_to_loads.get(&PatchObjectType::Image(variable_result_id))
-                        && let Some(&(load_idx, _)) = load_idxs.first()
-                    {
-                        // We don't have a type pointer, let's find the OpTypeImage via our original OpLoad!
-                        // load_type_result_id
-                        spv[load_idx + 1]
-                    } else {
-                        unreachable!(
-                            "Our OpVariable image id should always point back to a OpLoad id"
-                        );
-                    };
+    //    a respective OpTypePointer (also no duplicates). Samplers are their own complement.
+    let definitions = module.definitions();
+    // New types are inserted after the instruction at this index
+    let mut new_definition_idxs: HashMap<u32, usize> = HashMap::new();
+
+    let mut complement_types: HashMap<u32, ComplementType> = HashMap::new();
+    // - Existing types are reused before new ones are declared, the first declaration wins
+    let mut type_images_from_instruction: HashMap<Vec<u32>, u32> = HashMap::new();
+    for &idx in op_type_image_idxs.iter().rev() {
+        let ti = &module.instructions[idx];
+        type_images_from_instruction.insert(ti.operands.clone(), ti.id());
+    }
+    let mut type_pointers_from_type_image: HashMap<u32, u32> = HashMap::new();
+    for &idx in op_type_pointer_idxs.iter().rev() {
+        let tp = &module.instructions[idx];
+        if tp.operands[0] == SPV_STORAGE_CLASS_UNIFORM_CONSTANT {
+            type_pointers_from_type_image.insert(tp.operands[1], tp.id());
+        }
+    }
 
-                    // Grab the existing type image
-                    let (ti_idx, ti_id) = op_type_image_idxs
-                        .iter()
-                        .find_map(|&ti_idx| {
-                            let result_id = spv[ti_idx + 1];
-                            (result_id == image_type_id).then_some((ti_idx, result_id))
-                        })
-                        .unwrap();
-
-                    // Try to find an type image with the complement properties or (re-)create one
-                    let ti_word_count = hiword(spv[ti_idx]) as usize;
-                    let mut ti_complement = spv[ti_idx + 2..ti_idx + ti_word_count].to_vec();
-                    let complement_ty = match ti_complement[2] {
-                        0 | 2 => {
-                            ti_complement[2] = 1;
-                            OperationVariant::Dref
-                        }
-                        1 => {
-                            ti_complement[2] = 0;
-                            OperationVariant::Regular
-                        }
-                        _ => panic!("depth field on valid spv can only be 0, 1, or 2"),
-                    };
+    for &variable_idx in op_variable_idxs.iter() {
+        let variable = &module.instructions[variable_idx];
+        let tp_id = variable.type_id();
+        if !is_mixed(variable.id()) || complement_types.contains_key(&tp_id) {
+            continue;
+        }
+
+        let Some(&tp_idx) = definitions
+            .get(&tp_id)
+            .filter(|&&idx| module.instructions[idx].opcode == SPV_INSTRUCTION_OP_TYPE_POINTER)
+        else {
+            return Err(TransformError::missing_type(&module, variable_idx, tp_id));
+        };
+        let ti_id = module.instructions[tp_idx].operands[1];
+        let Some(&ti_idx) = definitions.get(&ti_id) else {
+            return Err(TransformError::missing_type(&module, tp_idx, ti_id));
+        };
+        let type_image = &module.instructions[ti_idx];
+
+        let complement_type = match type_image.opcode {
+            SPV_INSTRUCTION_OP_TYPE_SAMPLER => ComplementType {
+                primary: OperationVariant::Regular,
+                complement_tp_id: tp_id,
+                complement_ti_id: ti_id,
+            },
+            SPV_INSTRUCTION_OP_TYPE_IMAGE => {
+                let mut ti_complement = type_image.operands.clone();
+                let primary = match ti_complement[2] {
+                    0 | 2 => {
+                        ti_complement[2] = 1;
+                        OperationVariant::Regular
+                    }
+                    1 => {
+                        ti_complement[2] = 0;
+                        OperationVariant::Dref
+                    }
+                    _ => {
+                        return Err(TransformError::unsupported(
+                            &module,
+                            ti_idx,
+                            "depth field on valid spv can only be 0, 1, or 2",
+                        ));
+                    }
+                };
 
-                    let mut new_instructions = vec![];
-
-                    let complement_ti_id = existing_type_images_from_complement_instruction
-                        .get(&ti_complement)
-                        .copied()
-                        .or(op_type_image_idxs.iter().find_map(|&idx| {
-                            let word_count = hiword(spv[idx]) as usize;
-                            let result_id = spv[idx + 1];
-                            // To have a consistent instruction ordering, we white-out the existing OpTypeImage
-                            if ti_complement == spv[idx + 2..idx + word_count] {
-                                for it in new_spv.iter_mut().skip(idx).take(word_count) {
-                                    *it = encode_word(1, SPV_INSTRUCTION_OP_NOP);
-                                }
-                                Some(result_id)
-                            } else {
-                                None
-                            }
-                        }));
-                    let complement_ti_id = {
-                        let new_type_image_id = complement_ti_id.unwrap_or_else(|| {
-                            instruction_bound += 1;
-                            instruction_bound - 1
+                // Try to find an type image with the complement properties or create one
+                let complement_ti_id =
+                    if let Some(&id) = type_images_from_instruction.get(&ti_complement) {
+                        id
+                    } else {
+                        let new_type_image_id = instruction_bound;
+                        instruction_bound += 1;
+                        instruction_inserts.push(InstructionInsert {
+                            previous_idx: ti_idx,
+                            instructions: vec![Instruction::new(
+                                SPV_INSTRUCTION_OP_TYPE_IMAGE,
+                                None,
+                                Some(new_type_image_id),
+                                ti_complement.clone(),
+                            )],
                         });
-                        if !existing_type_images_from_complement_instruction
-                            .contains_key(&ti_complement)
-                        {
-                            let mut new_instruction = vec![
-                                encode_word(
-                                    (ti_complement.len() + 2) as u16,
-                                    SPV_INSTRUCTION_OP_TYPE_IMAGE,
-                                ),
-                                new_type_image_id,
-                            ];
-                            existing_type_images_from_complement_instruction
-                                .insert(ti_complement.clone(), new_type_image_id);
-                            new_instruction.append(&mut ti_complement);
-                            drop(ti_complement);
-                            new_instructions.append(&mut new_instruction);
-                        }
+                        new_definition_idxs.insert(new_type_image_id, ti_idx);
+                        type_images_from_instruction.insert(ti_complement, new_type_image_id);
                         new_type_image_id
                     };
 
-                    // Try to find a type id for complement type image or create one
-                    let complement_tp_id = existing_type_pointers_from_type_image
-                        .get(&complement_ti_id)
-                        .copied()
-                        .or(op_type_pointer_idxs.iter().find_map(|&idx| {
-                            let result_id = spv[idx + 1];
-                            let type_id = spv[idx + 3];
-                            if type_id == complement_ti_id {
-                                existing_type_pointers_from_type_image
-                                    .insert(complement_ti_id, result_id);
-                                Some(result_id)
-                            } else {
-                                None
-                            }
-                        }))
-                        .unwrap_or_else(|| {
-                            let new_type_pointer_id = instruction_bound;
-                            instruction_bound += 1;
-                            let mut new_instruction = vec![
-                                encode_word(4, SPV_INSTRUCTION_OP_TYPE_POINTER),
-                                new_type_pointer_id,
-                                SPV_STORAGE_CLASS_UNIFORM_CONSTANT,
-                                complement_ti_id,
-                            ];
-                            new_instructions.append(&mut new_instruction);
-                            existing_type_pointers_from_type_image
-                                .insert(complement_ti_id, new_type_pointer_id);
-                            new_type_pointer_id
+                // Try to find a type pointer for the complement type image or create one
+                let complement_tp_id =
+                    if let Some(&id) = type_pointers_from_type_image.get(&complement_ti_id) {
+                        id
+                    } else {
+                        let new_type_pointer_id = instruction_bound;
+                        instruction_bound += 1;
+                        // - An existing complement OpTypeImage may be declared after our pointer
+                        let previous_idx = definitions
+                            .get(&complement_ti_id)
+                            .or(new_definition_idxs.get(&complement_ti_id))
+                            .map_or(tp_idx, |&idx| idx.max(tp_idx));
+                        instruction_inserts.push(InstructionInsert {
+                            previous_idx,
+                            instructions: vec![Instruction::new(
+                                SPV_INSTRUCTION_OP_TYPE_POINTER,
+                                None,
+                                Some(new_type_pointer_id),
+                                vec![SPV_STORAGE_CLASS_UNIFORM_CONSTANT, complement_ti_id],
+                            )],
                         });
+                        new_definition_idxs.insert(new_type_pointer_id, previous_idx);
+                        type_pointers_from_type_image.insert(complement_ti_id, new_type_pointer_id);
+                        new_type_pointer_id
+                    };
 
-                    instruction_inserts.push(InstructionInsert {
-                        previous_spv_idx: ti_idx,
-                        instruction: new_instructions,
-                    });
-
-                    (
-                        v.next(variable_idx),
-                        lty,
-                        ti_id,
-                        complement_tp_id,
-                        complement_ti_id,
-                        complement_ty,
-                    )
+                ComplementType {
+                    primary,
+                    complement_tp_id,
+                    complement_ti_id,
                 }
             }
-        })
-        .collect::<Vec<_>>();
+            _ => {
+                return Err(TransformError::unsupported(
+                    &module,
+                    variable_idx,
+                    "image variable does not point to an OpTypeImage",
+                ));
+            }
+        };
+        complement_types.insert(tp_id, complement_type);
+    }
 
-    // 11. New OpVariable with a new_id, patch old OpLoads, and new depth=1 OpTypeImage.
-    // Map new function arguments to the correct instructions.
-    // NOTE: GENERALLY, with glslc, each OpImage* will get its own OpLoad, so we don't need to
-    // check that its result isn't used for both regular and dref operations!
+    // 9. New OpVariable for every mixed variable
     let mut affected_variables = Vec::new();
+    let mut complement_variables = HashMap::new();
+
+    for &variable_idx in op_variable_idxs.iter() {
+        let variable = &module.instructions[variable_idx];
+        let Some(&complement_type) = complement_types
+            .get(&variable.type_id())
+            .filter(|_| is_mixed(variable.id()))
+        else {
+            continue;
+        };
 
-    // There may be a shared OpTypeFunction but not shared OpFunctionParameter
-    let mut patched_function_types = HashMap::new();
-    let mut patched_function_parameters = HashSet::new();
-
-    // We may patch ourselves a new OpTypeFunction multiple times.
-    // Maps function type id and function index to our new type.
-    let mut defered_new_function_types: HashMap<(u32, usize), InstructionInsert> = HashMap::new();
-
-    for (
-        variable_idx_typed,
-        lty,
-        original_ti_id,
-        complement_tp_id,
-        complement_ti_id,
-        complement_ty,
-    ) in patch_variable_idxs
-    {
-        let variable_idx = variable_idx_typed.inner();
-        // OpVariable
-        let word_count = hiword(spv[variable_idx]);
         let new_variable_id = instruction_bound;
         instruction_bound += 1;
-        let mut new_variable = Vec::new();
-        new_variable.extend_from_slice(&spv[variable_idx..variable_idx + word_count as usize]);
-        new_variable[1] = complement_tp_id;
-        new_variable[2] = new_variable_id;
+        let mut new_variable = variable.clone();
+        new_variable.result_type = Some(complement_type.complement_tp_id);
+        new_variable.result_id = Some(new_variable_id);
+        let previous_idx = definitions
+            .get(&complement_type.complement_tp_id)
+            .or(new_definition_idxs.get(&complement_type.complement_tp_id))
+            .map_or(variable_idx, |&idx| idx.max(variable_idx));
         instruction_inserts.push(InstructionInsert {
-            previous_spv_idx: variable_idx,
-            instruction: new_variable,
+            previous_idx,
+            instructions: vec![new_variable],
         });
+        complement_variables.insert(variable.id(), (new_variable_id, complement_type));
 
         affected_variables.push(util::DecorationVariable {
-            original_res_id: spv[variable_idx + 2],
+            original_res_id: variable.id(),
             new_res_id: new_variable_id,
-            correction_type: match complement_ty {
-                OperationVariant::Regular => CorrectionType::SplitDrefComparison,
-                OperationVariant::Dref => CorrectionType::SplitDrefRegular,
+            correction_type: match complement_type.primary {
+                OperationVariant::Dref => CorrectionType::SplitDrefComparison,
+                OperationVariant::Regular => CorrectionType::SplitDrefRegular,
             },
         });
+    }
+
+    // 10. Specialize the functions that take mixed variables for the variables they are called
+    //     with, callers first.
+    // - A parameter that reaches an image operation is replaced by the variable passed for it, so
+    //   that each OpLoad can pick the variant of that variable it needs. The same function may
+    //   compare in one call path and sample regularly in another.
+    // - Functions are cloned once per distinct set of variables. The first instance is patched
+    //   in place, the others are inserted after it.
+    let is_uniform_constant_variable = |id: u32| {
+        definitions.get(&id).is_some_and(|&idx| {
+            let instruction = &module.instructions[idx];
+            instruction.opcode == SPV_INSTRUCTION_OP_VARIABLE
+                && instruction.operands[0] == SPV_STORAGE_CLASS_UNIFORM_CONSTANT
+        })
+    };
+
+    // - Functions that are passed or reach a mixed image or sampler are specialized, and so are
+    //   the functions a specialized function passes its images and samplers to. Every other
+    //   function is left as it is.
+    let mut specialized = vec![false; functions.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (caller, calls) in graph.calls.iter().enumerate() {
+            for &(call_idx, callee) in calls.iter() {
+                if specialized[callee] {
+                    continue;
+                }
+                let call = &module.instructions[call_idx];
+                if call.operands[1..]
+                    .iter()
+                    .zip(functions[callee].parameter_idxs.iter())
+                    .any(|(&argument_id, &parameter_idx)| {
+                        is_mixed(argument_id)
+                            || is_mixed(module.instructions[parameter_idx].id())
+                            || (specialized[caller] && usages.contains_key(&argument_id))
+                    })
+                {
+                    specialized[callee] = true;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let mut instances: Vec<Vec<FunctionInstance>> = vec![vec![]; functions.len()];
+    // Bound variables -> function id of each instance
+    let mut instance_ids: Vec<HashMap<Vec<Option<u32>>, u32>> =
+        vec![HashMap::new(); functions.len()];
+    let mut type_function_ids: HashMap<Vec<u32>, u32> = HashMap::new();
+    for &idx in op_type_function_idxs.iter().rev() {
+        let tf = &module.instructions[idx];
+        type_function_ids.insert(tf.operands.clone(), tf.id());
+    }
+    // - Names and decorations of each id
+    let mut annotation_idxs: HashMap<u32, Vec<usize>> = HashMap::new();
+    for &idx in op_name_idxs.iter().chain(op_decorate_idxs.iter()) {
+        if let Some(&target_id) = module.instructions[idx].operands.first() {
+            annotation_idxs.entry(target_id).or_default().push(idx);
+        }
+    }
 
-        // OpLoad
-        match lty {
-            LoadType::Variable => {
-                let old_variable_id = spv[variable_idx + 2];
-                if let Some(op_load_idxs) =
-                    patch_object_id_to_loads.get(&variable_idx_typed.next(old_variable_id))
+    for function in graph.callers_first_order(&module)? {
+        let info = &functions[function];
+        let op_function = &module.instructions[info.function_idx];
+        let Some(&type_function_idx) = definitions.get(&op_function.operands[1]).filter(|&&idx| {
+            let type_function = &module.instructions[idx];
+            type_function.opcode == SPV_INSTRUCTION_OP_TYPE_FUNCTION
+                && type_function.operands.len() == 1 + info.parameter_idxs.len()
+        }) else {
+            return Err(TransformError::missing_type(
+                &module,
+                info.function_idx,
+                op_function.operands[1],
+            ));
+        };
+
+        // - Functions that are never called keep all of their parameters
+        if instances[function].is_empty() {
+            instances[function].push(FunctionInstance {
+                function_id: op_function.id(),
+                bound_variable_ids: vec![None; info.parameter_idxs.len()],
+            });
+        }
+
+        let bound_parameter_ids = |bound_variable_ids: &[Option<u32>]| {
+            info.parameter_idxs
+                .iter()
+                .zip(bound_variable_ids)
+                .filter_map(|(&idx, bound)| bound.map(|_| module.instructions[idx].id()))
+                .collect::<HashSet<_>>()
+        };
+
+        for instance_idx in 0..instances[function].len() {
+            let FunctionInstance {
+                function_id,
+                bound_variable_ids,
+            } = instances[function][instance_idx].clone();
+            let bound_parameter_ids = bound_parameter_ids(&bound_variable_ids);
+
+            let (mut body, id_map) = if instance_idx == 0 {
+                // - Names and decorations of removed parameters would dangle
+                for idx in bound_parameter_ids
+                    .iter()
+                    .filter_map(|id| annotation_idxs.get(id))
+                    .flatten()
                 {
-                    for &(op_load_idx, ty) in op_load_idxs {
-                        if **ty == complement_ty {
-                            new_spv[op_load_idx + 1] = complement_ti_id;
-                            new_spv[op_load_idx + 3] = new_variable_id;
-                        } else {
-                            new_spv[op_load_idx + 1] = original_ti_id;
-                            new_spv[op_load_idx + 3] = old_variable_id;
-                        };
+                    new_module.instructions[*idx] = Instruction::nop();
+                }
+                (
+                    module.instructions[info.function_idx..=info.function_end_idx].to_vec(),
+                    HashMap::new(),
+                )
+            } else {
+                let CloneFunctionOut {
+                    mut instructions,
+                    id_map,
+                } = util::clone_function(&module, info, &mut instruction_bound);
+                instructions[0].result_id = Some(function_id);
+
+                // - Decorations such as NonUniform must follow the cloned ids
+                let mut d_idxs = id_map
+                    .keys()
+                    .filter(|id| !bound_parameter_ids.contains(id))
+                    .filter_map(|id| annotation_idxs.get(id))
+                    .flatten()
+                    .copied()
+                    .filter(|&idx| module.instructions[idx].opcode == SPV_INSTRUCTION_OP_DECORATE)
+                    .collect::<Vec<_>>();
+                d_idxs.sort();
+                for d_idx in d_idxs {
+                    let decoration = &module.instructions[d_idx];
+                    let mut new_decoration = decoration.clone();
+                    new_decoration.operands[0] = id_map[&decoration.operands[0]];
+                    instruction_inserts.push(InstructionInsert {
+                        previous_idx: d_idx,
+                        instructions: vec![new_decoration],
+                    });
+                }
+                (instructions, id_map)
+            };
+
+            // OpFunctionParameter
+            let mut substitutions = HashMap::new();
+            let mut type_function_operands =
+                vec![module.instructions[type_function_idx].operands[0]];
+
+            for (&parameter_idx, bound) in info.parameter_idxs.iter().zip(bound_variable_ids) {
+                let parameter = &module.instructions[parameter_idx];
+                match bound {
+                    Some(variable_id) => {
+                        let parameter_id = id_map.get(&parameter.id()).copied();
+                        substitutions.insert(parameter_id.unwrap_or(parameter.id()), variable_id);
+                        body[parameter_idx - info.function_idx] = Instruction::nop();
                     }
+                    None => type_function_operands.push(parameter.type_id()),
                 }
             }
-            LoadType::FunctionArgument => {
-                let mut function_id_and_index_to_new_parameter_id = HashMap::new();
-
-                // Patch function types, definition parameter, and final loads
-                for (variables, calls) in function_patch_variables_with_calls.iter() {
-                    if variables.contains(&variable_idx_typed.next(variable_idx)) {
-                        for &call in calls.iter().rev() {
-                            let function_id = spv[call.call_parameter.function_idx + 2];
-                            let type_function_id = spv[call.call_parameter.function_idx + 4];
-                            if !patched_function_parameters.contains(&(
-                                call.call_parameter.parameter_instruction_idx,
-                                spv[call.call_parameter.function_idx + 2],
-                            )) {
-                                let Some(type_function_idx) =
-                                    op_type_function_idxs.iter().find(|&&idx| {
-                                        let result_id = spv[idx + 1];
-                                        type_function_id == result_id
-                                    })
-                                else {
-                                    panic!(
-                                        "OpTypeFunction does not exist for function {}, type {}",
-                                        function_id, type_function_id
-                                    );
-                                };
-
-                                // To allow multiple patching we can either patch by taking
-                                // an instruction directly from the code, or by patching a
-                                // function we have already began
-                                // We save patching the function's type for later in case of
-                                // duplicate OpTypeFunction
-                                {
-                                    let (new_type_function_id, type_instruction_type_info) =
-                                        if let Some(new_function_type) = defered_new_function_types
-                                            .get_mut(&(
-                                                type_function_id,
-                                                call.call_parameter.function_idx,
-                                            ))
-                                        {
-                                            new_function_type.instruction.insert(
-                                                3 + call.call_parameter.parameter_instruction_idx
-                                                    + 1
-                                                    + 1,
-                                                complement_tp_id,
-                                            );
-                                            new_function_type.instruction[0] = encode_word(
-                                                new_function_type.instruction.len() as u16,
-                                                SPV_INSTRUCTION_OP_TYPE_FUNCTION,
-                                            );
-                                            (
-                                                new_function_type.instruction[1],
-                                                new_function_type.instruction[2..].to_vec(),
-                                            )
-                                        } else {
-                                            let new_function_type_id = instruction_bound;
-                                            instruction_bound += 1;
-
-                                            let word_count = hiword(spv[*type_function_idx]);
-                                            let mut type_function = Vec::new();
-                                            type_function.extend_from_slice(
-                                                &spv[*type_function_idx
-                                                    ..*type_function_idx + word_count as usize],
-                                            );
-                                            type_function[0] = encode_word(
-                                                word_count + 1,
-                                                SPV_INSTRUCTION_OP_TYPE_FUNCTION,
-                                            );
-                                            type_function[1] = new_function_type_id;
-                                            type_function.insert(
-                                                3 + call.call_parameter.parameter_instruction_idx
-                                                    + 1,
-                                                complement_tp_id,
-                                            );
-
-                                            let type_instruction_type_info =
-                                                type_function[2..].to_vec();
-
-                                            defered_new_function_types.insert(
-                                                (
-                                                    type_function_id,
-                                                    call.call_parameter.function_idx,
-                                                ),
-                                                InstructionInsert {
-                                                    previous_spv_idx: *type_function_idx,
-                                                    instruction: type_function,
-                                                },
-                                            );
-                                            (new_function_type_id, type_instruction_type_info)
-                                        };
-                                    let entry = patched_function_types
-                                        .entry(type_instruction_type_info)
-                                        .or_insert((new_type_function_id, vec![]));
-                                    entry
-                                        .1
-                                        .push((type_function_id, call.call_parameter.function_idx));
-                                }
-
-                                // Patch function parameter
-                                let new_parameter_id = instruction_bound;
-                                instruction_bound += 1;
-                                instruction_inserts.push(InstructionInsert {
-                                    previous_spv_idx: call.call_parameter.parameter_idx,
-                                    instruction: vec![
-                                        encode_word(3, SPV_INSTRUCTION_OP_FUNCTION_PARAMTER),
-                                        complement_tp_id,
-                                        new_parameter_id,
-                                    ],
-                                });
-
-                                // Use our new parameters to patch dependent OpLoads
-                                for load_idx in op_load_idxs.iter() {
-                                    let result_id = spv[load_idx + 2];
-                                    let ptr_id = spv[load_idx + 3];
-                                    let parameter_result_id =
-                                        spv[call.call_parameter.parameter_idx + 2];
-
-                                    // TODO: OPT Someone else can come by and rearrange these silly data
-                                    // structures later.
-                                    if ptr_id == parameter_result_id {
-                                        let ty = loaded_variable_ids
-                                            .iter()
-                                            .find_map(|&(id, ty)| {
-                                                (id.inner() == result_id).then_some(ty)
-                                            })
-                                            .unwrap();
-                                        if *ty == complement_ty {
-                                            new_spv[load_idx + 1] = complement_ti_id;
-                                            new_spv[load_idx + 3] = new_parameter_id;
-                                        }
-                                    }
-                                }
-
-                                let function_id = spv[call.function_call_idx + 3];
-                                function_id_and_index_to_new_parameter_id.insert(
-                                    (function_id, call.call_parameter.parameter_instruction_idx),
-                                    new_parameter_id,
-                                );
-                                patched_function_parameters.insert((
-                                    call.call_parameter.parameter_instruction_idx,
-                                    function_id,
-                                ));
-                            }
-                        }
+
+            for instruction in body.iter_mut() {
+                let opcode = instruction.opcode;
+                for (operand_idx, operand) in instruction.operands.iter_mut().enumerate() {
+                    if !spv_is_literal_operand(opcode, operand_idx)
+                        && let Some(&variable_id) = substitutions.get(operand)
+                    {
+                        *operand = variable_id;
                     }
                 }
+            }
+
+            // OpLoad
+            // Loads of the complement variant now come from the complement variable
+            let mut retyped_ids = HashMap::new();
+            for (offset, instruction) in body.iter_mut().enumerate() {
+                if let Some(&ty) = load_variants.get(&(info.function_idx + offset))
+                    && let Some(&(complement_variable_id, complement_type)) =
+                        complement_variables.get(&instruction.operands[0])
+                    && complement_type.primary != ty
+                {
+                    instruction.operands[0] = complement_variable_id;
+                    instruction.result_type = Some(complement_type.complement_ti_id);
+                    retyped_ids.insert(instruction.id(), complement_type.complement_ti_id);
+                }
+            }
 
-                // Patch function calls that call other functions
-                for (variables, calls) in function_patch_variables_with_calls.iter() {
-                    if variables.contains(&variable_idx_typed.next(variable_idx)) {
-                        for &call in calls.iter().rev() {
-                            let function_idx = get_function_index_of_instruction_index(
-                                &spv,
-                                call.function_call_idx,
-                            );
-                            let function_id = spv[function_idx + 2];
-                            if let Some(parameter_word) = function_id_and_index_to_new_parameter_id
-                                .get(&(function_id, call.call_parameter.parameter_instruction_idx))
-                            {
-                                word_inserts.push(WordInsert {
-                                    idx: call.function_call_idx
-                                        + 4
-                                        + call.call_parameter.parameter_instruction_idx,
-                                    word: *parameter_word,
-                                    head_idx: call.function_call_idx,
-                                });
-                            } else {
-                                word_inserts.push(WordInsert {
-                                    idx: call.function_call_idx
-                                        + 4
-                                        + call.call_parameter.parameter_instruction_idx,
-                                    word: new_variable_id,
-                                    head_idx: call.function_call_idx,
-                                });
-                            }
-                        }
+            // OpCopyObject, OpPhi and OpSelect
+            // Forwarded loads take their new type along. Phis in loops may forward values that
+            // are defined further down, so keep going until nothing changes.
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for instruction in body.iter_mut() {
+                    if !is_value_forward(instruction.opcode)
+                        || retyped_ids.contains_key(&instruction.id())
+                    {
+                        continue;
+                    }
+                    if let Some(&ti_id) =
+                        forwarded_operand_idxs(instruction)
+                            .iter()
+                            .find_map(|&operand_idx| {
+                                retyped_ids.get(&instruction.operands[operand_idx])
+                            })
+                    {
+                        instruction.result_type = Some(ti_id);
+                        retyped_ids.insert(instruction.id(), ti_id);
+                        changed = true;
                     }
                 }
             }
-        }
+            for (offset, instruction) in body.iter().enumerate() {
+                if retyped_ids.contains_key(&instruction.id())
+                    && is_value_forward(instruction.opcode)
+                    && forwarded_operand_idxs(instruction)
+                        .iter()
+                        .any(|&operand_idx| {
+                            retyped_ids.get(&instruction.operands[operand_idx])
+                                != Some(&instruction.type_id())
+                        })
+                {
+                    return Err(TransformError::unsupported(
+                        &module,
+                        info.function_idx + offset,
+                        "forwarded images are split into different variants",
+                    ));
+                }
+            }
 
-        // OpSampledImage
-        // NOTE: We did not patch in a new OpSampledImage and OpTypeSampledImage.
-        // Thankfully, it seems that `spirv-val`, `naga`, nor `tint` seem to care.
-    }
+            // OpFunctionCall
+            for &call_idx in info.function_call_idxs.iter() {
+                let body_call = &mut body[call_idx - info.function_idx];
+                let Some(&callee) = graph.function_ids.get(&body_call.operands[0]) else {
+                    continue;
+                };
+
+                let mut arguments = vec![];
+                let mut callee_bound_variable_ids = vec![];
+                for (&argument_id, &parameter_idx) in body_call.operands[1..]
+                    .iter()
+                    .zip(functions[callee].parameter_idxs.iter())
+                {
+                    let parameter_id = module.instructions[parameter_idx].id();
+                    if specialized[callee]
+                        && usages.contains_key(&parameter_id)
+                        && is_uniform_constant_variable(argument_id)
+                    {
+                        callee_bound_variable_ids.push(Some(argument_id));
+                    } else {
+                        arguments.push(argument_id);
+                        callee_bound_variable_ids.push(None);
+                    }
+                }
+
+                let callee_function_id = if let Some(&function_id) =
+                    instance_ids[callee].get(&callee_bound_variable_ids)
+                {
+                    function_id
+                } else {
+                    let callee_function_id = if instances[callee].is_empty() {
+                        module.instructions[functions[callee].function_idx].id()
+                    } else {
+                        instruction_bound += 1;
+                        instruction_bound - 1
+                    };
+                    instance_ids[callee]
+                        .insert(callee_bound_variable_ids.clone(), callee_function_id);
+                    instances[callee].push(FunctionInstance {
+                        function_id: callee_function_id,
+                        bound_variable_ids: callee_bound_variable_ids,
+                    });
+                    callee_function_id
+                };
 
-    // 12. Remove duplicate function types and patch them into OpFunction
-    for (_, (new_type_function_id, functions)) in patched_function_types.into_iter() {
-        for (idx, &(type_function_id, function_idx)) in functions.iter().enumerate() {
-            if idx != 0 {
-                defered_new_function_types.remove(&(type_function_id, function_idx));
+                body_call.operands = std::iter::once(callee_function_id)
+                    .chain(arguments)
+                    .collect();
+            }
+
+            // OpTypeFunction
+            // Reuse an existing function type for the remaining parameters or create one
+            let type_function_id = if let Some(&id) = type_function_ids.get(&type_function_operands)
+            {
+                id
+            } else {
+                let new_type_function_id = instruction_bound;
+                instruction_bound += 1;
+                instruction_inserts.push(InstructionInsert {
+                    previous_idx: type_function_idx,
+                    instructions: vec![Instruction::new(
+                        SPV_INSTRUCTION_OP_TYPE_FUNCTION,
+                        None,
+                        Some(new_type_function_id),
+                        type_function_operands.clone(),
+                    )],
+                });
+                type_function_ids.insert(type_function_operands, new_type_function_id);
+                new_type_function_id
+            };
+            body[0].operands[1] = type_function_id;
+
+            if instance_idx == 0 {
+                for (offset, instruction) in body.into_iter().enumerate() {
+                    new_module.instructions[info.function_idx + offset] = instruction;
+                }
+            } else {
+                instruction_inserts.push(InstructionInsert {
+                    previous_idx: info.function_end_idx,
+                    instructions: body,
+                });
             }
-            new_spv[function_idx + 4] = new_type_function_id;
-        }
-    }
 
-    // We now insert our new function types
-    for (_, new_instruction) in defered_new_function_types {
-        instruction_inserts.push(new_instruction)
+            // OpSampledImage
+            // NOTE: We did not patch in a new OpSampledImage and OpTypeSampledImage.
+            // Thankfully, it seems that `spirv-val`, `naga`, nor `tint` seem to care.
+        }
     }
 
-    // 13. Insert new OpDecorate
-    let DecorateOut {
-        descriptor_sets_to_correct,
-    } = util::decorate(DecorateIn {
-        spv: &spv,
+    // 11. Insert new OpDecorate
+    let DecorateOut { new_variables } = util::decorate(DecorateIn {
+        module: &module,
         instruction_inserts: &mut instruction_inserts,
-        first_op_deocrate_idx,
+        layout: &Layout::new(&module),
         op_decorate_idxs: &op_decorate_idxs,
         affected_variables: &affected_variables,
         corrections,
+    })?;
+
+    // 12. OpEntryPoint
+    util::entry_point_interface(EntryPointInterfaceIn {
+        module: &module,
+        operand_inserts: &mut operand_inserts,
+        affected_variables: &affected_variables,
     });
 
-    // 14. Insert New Instructions
-    insert_new_instructions(&spv, &mut new_spv, &word_inserts, &instruction_inserts);
+    // 13. Insert New Instructions
+    insert_new_instructions(&mut new_module, &operand_inserts, &instruction_inserts);
 
-    // 15. Correct OpDecorate Bindings
+    // 14. Correct OpDecorate Bindings
     util::correct_decorate(CorrectDecorateIn {
-        new_spv: &mut new_spv,
-        descriptor_sets_to_correct,
-    });
-
-    // 16. Remove Instructions that have been Whited Out.
-    prune_noops(&mut new_spv);
+        new_module: &mut new_module,
+        new_variables: &new_variables,
+        allocator,
+        corrections,
+    })?;
 
-    // 17. Write New Header and New Code
-    Ok(fuse_final(spv_header, new_spv, instruction_bound))
+    // - Whited out instructions are removed and the header is written by the PassManager
+    *out_module = new_module;
+    *out_instruction_bound = instruction_bound;
+    Ok(())
 }
@@ -0,0 +1,133 @@
+use super::*;
+
+// glslc gives every image operation its own OpLoad and OpSampledImage, but DXC, slang and
+// spirv-opt happily share one between regular and dref operations, possibly through
+// OpCopyObject, OpPhi and OpSelect.
+// Regular operations keep the original, dref operations get a copy inserted right after it, so
+// that every OpLoad can later be pointed at the variable of its own variant.
+pub fn shared_load(module: &Module, instruction_bound: &mut u32) -> Module {
+    let trace = ValueTrace::new(module);
+
+    // - Find the operations every value reaches, walking back from the operations through
+    //   forwarding instructions, OpSampledImage and OpLoad
+    let mut usages: HashMap<u32, Usage> = HashMap::new();
+    let mut copyable_ids = HashSet::new();
+    let mut sampled_image_operands = vec![];
+    for instruction in module.instructions.iter() {
+        if let Some(variant) = operation_variant(instruction.opcode) {
+            usages
+                .entry(instruction.operands[0])
+                .or_default()
+                .insert(variant);
+        }
+        match instruction.opcode {
+            SPV_INSTRUCTION_OP_LOAD => {
+                copyable_ids.insert(instruction.id());
+            }
+            SPV_INSTRUCTION_OP_SAMPLED_IMAGE => {
+                copyable_ids.insert(instruction.id());
+                for &operand in instruction.operands[..2].iter() {
+                    sampled_image_operands.push((instruction.id(), operand));
+                }
+            }
+            opcode if is_value_forward(opcode) => {
+                copyable_ids.insert(instruction.id());
+            }
+            _ => {}
+        }
+    }
+    let edges = sampled_image_operands
+        .into_iter()
+        .chain(copyable_ids.iter().flat_map(|&id| {
+            trace
+                .sources(id)
+                .iter()
+                .map(move |&source_id| (id, source_id))
+        }))
+        .collect::<Vec<_>>();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(user_id, source_id) in edges.iter() {
+            if let Some(&usage) = usages.get(&user_id) {
+                changed |= usages.entry(source_id).or_default().merge(usage);
+            }
+        }
+    }
+
+    // - In module order for a stable order of new ids
+    let mut dref_copy_ids = HashMap::new();
+    for instruction in module.instructions.iter() {
+        let id = instruction.id();
+        if copyable_ids.contains(&id) && usages.get(&id).is_some_and(|usage| usage.is_mixed()) {
+            dref_copy_ids.insert(id, *instruction_bound);
+            *instruction_bound += 1;
+        }
+    }
+
+    if dref_copy_ids.is_empty() {
+        return module.clone();
+    }
+
+    let mut new_module = module.clone();
+    new_module.instructions.clear();
+    for instruction in module.instructions.iter() {
+        let mut instruction = instruction.clone();
+        let mut copy = None;
+
+        match instruction.opcode {
+            // - Dref operations consume the copy of their sampled image
+            opcode if operation_variant(opcode) == Some(OperationVariant::Dref) => {
+                if let Some(&copy_id) = dref_copy_ids.get(&instruction.operands[0]) {
+                    instruction.operands[0] = copy_id;
+                }
+            }
+            // - Values that reach dref operations consume the copies of their operands
+            opcode if opcode == SPV_INSTRUCTION_OP_SAMPLED_IMAGE || is_value_forward(opcode) => {
+                let operand_idxs = if opcode == SPV_INSTRUCTION_OP_SAMPLED_IMAGE {
+                    vec![0, 1]
+                } else {
+                    forwarded_operand_idxs(&instruction)
+                };
+                let mut dref_instruction = instruction.clone();
+                for operand_idx in operand_idxs {
+                    let operand = &mut dref_instruction.operands[operand_idx];
+                    if let Some(&copy_id) = dref_copy_ids.get(operand) {
+                        *operand = copy_id;
+                    }
+                }
+                if let Some(&copy_id) = dref_copy_ids.get(&instruction.id()) {
+                    dref_instruction.result_id = Some(copy_id);
+                    copy = Some(dref_instruction);
+                } else if usages
+                    .get(&instruction.id())
+                    .is_some_and(|usage| usage.only() == Some(OperationVariant::Dref))
+                {
+                    instruction = dref_instruction;
+                }
+            }
+            SPV_INSTRUCTION_OP_LOAD => {
+                if let Some(&copy_id) = dref_copy_ids.get(&instruction.id()) {
+                    let mut dref_load = instruction.clone();
+                    dref_load.result_id = Some(copy_id);
+                    copy = Some(dref_load);
+                }
+            }
+            // - Decorations such as NonUniform must also apply to the copy
+            SPV_INSTRUCTION_OP_DECORATE => {
+                if let Some(&copy_id) = dref_copy_ids.get(&instruction.operands[0]) {
+                    let mut decoration = instruction.clone();
+                    decoration.operands[0] = copy_id;
+                    copy = Some(decoration);
+                }
+            }
+            _ => {}
+        }
+
+        new_module.instructions.push(instruction);
+        new_module.instructions.extend(copy);
+    }
+
+    new_module
+}
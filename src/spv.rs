@@ -4,19 +4,51 @@ pub const SPV_HEADER_MAGIC_NUM_OFFSET: usize = 0;
 pub const SPV_HEADER_INSTRUCTION_BOUND_OFFSET: usize = 3;
 
 pub const SPV_INSTRUCTION_OP_NOP: u16 = 1;
+pub const SPV_INSTRUCTION_OP_SOURCE_CONTINUED: u16 = 2;
+pub const SPV_INSTRUCTION_OP_SOURCE: u16 = 3;
+pub const SPV_INSTRUCTION_OP_SOURCE_EXTENSION: u16 = 4;
+pub const SPV_INSTRUCTION_OP_NAME: u16 = 5;
+pub const SPV_INSTRUCTION_OP_MEMBER_NAME: u16 = 6;
+pub const SPV_INSTRUCTION_OP_STRING: u16 = 7;
+pub const SPV_INSTRUCTION_OP_EXTENSION: u16 = 10;
+pub const SPV_INSTRUCTION_OP_EXT_INST_IMPORT: u16 = 11;
+pub const SPV_INSTRUCTION_OP_MEMORY_MODEL: u16 = 14;
+pub const SPV_INSTRUCTION_OP_ENTRY_POINT: u16 = 15;
+pub const SPV_INSTRUCTION_OP_EXECUTION_MODE: u16 = 16;
+pub const SPV_INSTRUCTION_OP_CAPABILITY: u16 = 17;
 pub const SPV_INSTRUCTION_OP_TYPE_VOID: u16 = 19;
+pub const SPV_INSTRUCTION_OP_TYPE_INT: u16 = 21;
 pub const SPV_INSTRUCTION_OP_TYPE_IMAGE: u16 = 25;
 pub const SPV_INSTRUCTION_OP_TYPE_SAMPLER: u16 = 26;
 pub const SPV_INSTRUCTION_OP_TYPE_SAMPLED_IMAGE: u16 = 27;
+pub const SPV_INSTRUCTION_OP_TYPE_ARRAY: u16 = 28;
+pub const SPV_INSTRUCTION_OP_TYPE_RUNTIME_ARRAY: u16 = 29;
 pub const SPV_INSTRUCTION_OP_TYPE_POINTER: u16 = 32;
 pub const SPV_INSTRUCTION_OP_TYPE_FUNCTION: u16 = 33;
 pub const SPV_INSTRUCTION_OP_FUNCTION_PARAMTER: u16 = 55;
 pub const SPV_INSTRUCTION_OP_FUNCTION_CALL: u16 = 57;
 pub const SPV_INSTRUCTION_OP_FUNCTION: u16 = 54;
+pub const SPV_INSTRUCTION_OP_FUNCTION_END: u16 = 56;
 pub const SPV_INSTRUCTION_OP_VARIABLE: u16 = 59;
 pub const SPV_INSTRUCTION_OP_LOAD: u16 = 61;
+pub const SPV_INSTRUCTION_OP_ACCESS_CHAIN: u16 = 65;
+pub const SPV_INSTRUCTION_OP_IN_BOUNDS_ACCESS_CHAIN: u16 = 66;
 pub const SPV_INSTRUCTION_OP_DECORATE: u16 = 71;
+pub const SPV_INSTRUCTION_OP_MEMBER_DECORATE: u16 = 72;
+pub const SPV_INSTRUCTION_OP_DECORATION_GROUP: u16 = 73;
+pub const SPV_INSTRUCTION_OP_GROUP_DECORATE: u16 = 74;
+pub const SPV_INSTRUCTION_OP_GROUP_MEMBER_DECORATE: u16 = 75;
+pub const SPV_INSTRUCTION_OP_MODULE_PROCESSED: u16 = 330;
+pub const SPV_INSTRUCTION_OP_EXECUTION_MODE_ID: u16 = 331;
+pub const SPV_INSTRUCTION_OP_DECORATE_ID: u16 = 332;
+pub const SPV_INSTRUCTION_OP_DECORATE_STRING: u16 = 5632;
+pub const SPV_INSTRUCTION_OP_MEMBER_DECORATE_STRING: u16 = 5633;
 pub const SPV_INSTRUCTION_OP_SAMPLED_IMAGE: u16 = 86;
+pub const SPV_INSTRUCTION_OP_COPY_OBJECT: u16 = 83;
+pub const SPV_INSTRUCTION_OP_IMAGE: u16 = 100;
+pub const SPV_INSTRUCTION_OP_SELECT: u16 = 169;
+pub const SPV_INSTRUCTION_OP_PHI: u16 = 245;
+pub const SPV_INSTRUCTION_OP_LABEL: u16 = 248;
 
 pub const SPV_INSTRUCTION_OP_IMAGE_SAMPLE_IMPLICIT_LOD: u16 = 87;
 pub const SPV_INSTRUCTION_OP_IMAGE_SAMPLE_EXPLICIT_LOD: u16 = 88;
@@ -36,6 +68,68 @@ pub const SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_DREF_IMPLICIT_LOD: u16 = 307;
 pub const SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_DREF_EXPLICIT_LOD: u16 = 308;
 pub const SPV_INSTRUCTION_OP_IMAGE_SPARSE_DREF_GATHER: u16 = 315;
 
+pub const SPV_EXECUTION_MODEL_VERTEX: u32 = 0;
+pub const SPV_EXECUTION_MODEL_FRAGMENT: u32 = 4;
+pub const SPV_EXECUTION_MODEL_GL_COMPUTE: u32 = 5;
+
 pub const SPV_STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
 pub const SPV_DECORATION_BINDING: u32 = 33;
 pub const SPV_DECORATION_DESCRIPTOR_SET: u32 = 34;
+pub const SPV_DECORATION_LINKAGE_ATTRIBUTES: u32 = 41;
+pub const SPV_DECORATION_NON_UNIFORM: u32 = 5300;
+
+pub const SPV_LINKAGE_TYPE_EXPORT: u32 = 0;
+
+// Generated from `HasResultAndType` in the unified SPIRV-Headers.
+// Unknown opcodes are assumed to have neither, which is still safe to round trip.
+#[rustfmt::skip]
+pub const fn spv_has_result_and_type(opcode: u16) -> (bool, bool) {
+    match opcode {
+        1 | 12 | 41..=46 | 48..=52 | 54..=55 | 57 | 59..=61 | 65..=70 | 77..=84 | 86..=98
+        | 100..=107 | 109..=124 | 126..=152 | 154..=191 | 194..=205 | 207..=215 | 227
+        | 229..=242 | 245 | 259 | 261..=271 | 274..=279 | 282..=286 | 291..=296 | 299..=300
+        | 303..=316 | 318 | 320..=321 | 323..=326 | 328 | 333..=366 | 400..=403
+        | 4160..=4162 | 4164 | 4166 | 4181 | 4183..=4184 | 4418..=4425 | 4428..=4433 | 4447
+        | 4450..=4455 | 4457 | 4459..=4463 | 4477 | 4479..=4483 | 4500..=4503 | 5000..=5007
+        | 5011..=5012 | 5056 | 5074 | 5078 | 5090 | 5101 | 5110..=5111 | 5252..=5255
+        | 5257..=5258 | 5265 | 5267..=5278 | 5283 | 5289 | 5292..=5293 | 5296 | 5300..=5302
+        | 5334 | 5340 | 5345..=5346 | 5359 | 5361..=5362 | 5366..=5367 | 5369 | 5372..=5379
+        | 5381..=5382 | 5384 | 5390..=5396 | 5398 | 5427..=5439 | 5571..=5575 | 5577 | 5580
+        | 5585..=5598 | 5600..=5601 | 5610..=5611 | 5614..=5615 | 5631 | 5699 | 5713..=5816
+        | 5818..=5819 | 5840..=5843 | 5846..=5882 | 5923..=5934 | 5938 | 5946..=5947 | 5949
+        | 6016..=6032 | 6035 | 6096 | 6116..=6117 | 6145 | 6163 | 6165 | 6237 | 6242
+        | 6401..=6408 | 6426 | 6428 | 6529..=6531 => (true, true),
+        7 | 11 | 19..=38 | 73 | 248 | 322 | 327 | 4163 | 4190 | 4417 | 4456 | 4472 | 5076
+        | 5103..=5104 | 5281 | 5288 | 5341 | 5358 | 5370..=5371 | 5609 | 5700..=5712
+        | 5911..=5913 | 6086 | 6199 => (true, false),
+        _ => (false, false),
+    }
+}
+
+// Derived from the operand kinds in the unified SPIR-V grammar, only for instructions that may
+// appear inside a function body. `operand_idx` counts from the first word after the result id.
+// Ids that are always global (such as memory scopes) may be reported as literals, which is
+// harmless when remapping function local ids.
+#[rustfmt::skip]
+pub const fn spv_is_literal_operand(opcode: u16, operand_idx: usize) -> bool {
+    match opcode {
+        // OpFunction, OpVariable
+        54 | 59 => operand_idx == 0,
+        // OpExtInst, OpArrayLength, OpLifetimeStart, OpLifetimeStop
+        12 | 68 | 256 | 257 => operand_idx == 1,
+        // OpLine, OpLoad, OpCompositeExtract, OpSelectionMerge
+        8 | 61 | 81 | 247 => operand_idx >= 1,
+        // OpStore, OpCopyMemory, OpVectorShuffle, OpCompositeInsert, OpLoopMerge
+        62 | 63 | 79 | 82 | 246 => operand_idx >= 2,
+        // OpCopyMemorySized, OpBranchConditional
+        64 | 250 => operand_idx >= 3,
+        // OpSwitch literal and label pairs
+        251 => operand_idx >= 2 && operand_idx.is_multiple_of(2),
+        // Image operands mask of the sampling, fetch and read instructions
+        87 | 88 | 91 | 92 | 95 | 98 | 305 | 306 | 309 | 310 | 313 | 320 => operand_idx == 2,
+        89 | 90 | 93 | 94 | 96 | 97 | 99 | 307 | 308 | 311 | 312 | 314 | 315 => operand_idx == 3,
+        // Group operation of the group and non-uniform group arithmetic instructions
+        264..=271 | 342 | 349..=362 => operand_idx == 1,
+        _ => false,
+    }
+}
@@ -1,4 +1,28 @@
-use super::{combimgsampsplitter, drefsplitter, u8_slice_to_u32_vec, u32_slice_to_u8_vec};
+use super::spv::{
+    SPV_DECORATION_BINDING, SPV_DECORATION_DESCRIPTOR_SET, SPV_DECORATION_LINKAGE_ATTRIBUTES,
+    SPV_INSTRUCTION_OP_ACCESS_CHAIN, SPV_INSTRUCTION_OP_COPY_OBJECT, SPV_INSTRUCTION_OP_DECORATE,
+    SPV_INSTRUCTION_OP_DECORATION_GROUP, SPV_INSTRUCTION_OP_ENTRY_POINT,
+    SPV_INSTRUCTION_OP_EXTENSION, SPV_INSTRUCTION_OP_FUNCTION, SPV_INSTRUCTION_OP_FUNCTION_CALL,
+    SPV_INSTRUCTION_OP_FUNCTION_PARAMTER, SPV_INSTRUCTION_OP_GROUP_DECORATE,
+    SPV_INSTRUCTION_OP_GROUP_MEMBER_DECORATE, SPV_INSTRUCTION_OP_IN_BOUNDS_ACCESS_CHAIN,
+    SPV_INSTRUCTION_OP_LABEL, SPV_INSTRUCTION_OP_LOAD, SPV_INSTRUCTION_OP_MEMBER_NAME,
+    SPV_INSTRUCTION_OP_NAME, SPV_INSTRUCTION_OP_PHI, SPV_INSTRUCTION_OP_SAMPLED_IMAGE,
+    SPV_INSTRUCTION_OP_SELECT, SPV_INSTRUCTION_OP_SOURCE, SPV_INSTRUCTION_OP_STRING,
+    SPV_INSTRUCTION_OP_TYPE_SAMPLED_IMAGE, SPV_INSTRUCTION_OP_VARIABLE, spv_is_literal_operand,
+};
+use super::{
+    AppendBindings, BindingAllocator, BindingStrategy, CorrectionMap, CorrectionType,
+    DescriptorBinding, ExecutionModel, ExplicitBindings, ExtraDescriptorSet, Instruction, Module,
+    Options, Pass, PassManager, PipelineStage, ResourceKind, SamplerBindingType, ShaderStages,
+    ShiftBindings, SplitCombinedPass, SplitDrefPass, TextureBindingInfo, TextureSampleType,
+    TextureViewDimension, TransformError, combimgsampsplitter, combimgsampsplitter_with_allocator,
+    drefsplitter, drefsplitter_with_allocator, embed_corrections, pipeline_transform,
+    read_embedded_corrections, u8_slice_to_u32_vec, u32_slice_to_u8_vec, webgpu_transform,
+};
+
+use super::util::decode_string;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use naga::{back, front, valid};
 use spirv_tools::val::{self, Validator};
@@ -6,6 +30,8 @@ use spirv_tools::val::{self, Validator};
 const SPV_VALIDATE: u8 = 0b0000001;
 const NAGA_VALIDATE: u8 = 0b0000010;
 const NAGA_CONVERT: u8 = 0b0000100;
+// Optimized modules may still need legalization, e.g. OpPhi of images
+const SPV_BEFORE_LEGALIZATION: u8 = 0b0001000;
 const DO_ALL: u8 = 0xff;
 
 #[macro_export]
@@ -14,7 +40,7 @@ macro_rules! test_with_spv_and_fn {
         #[test]
         fn $NAME() {
             let spv = include_bytes!($SPV);
-            let spv = u8_slice_to_u32_vec(spv);
+            let spv = u8_slice_to_u32_vec(spv).unwrap();
             let out_spv = $FN(&spv, &mut None).unwrap();
             try_spv_to_wgsl(&out_spv, $FLAGS);
         }
@@ -25,7 +51,11 @@ fn try_spv_to_wgsl(spv: &[u32], flags: u8) {
     let spv_u8 = u32_slice_to_u8_vec(spv);
     if flags & SPV_VALIDATE != 0 {
         let validator = val::create(None);
-        validator.validate(spv, None).unwrap();
+        let options = val::ValidatorOptions {
+            before_legalization: flags & SPV_BEFORE_LEGALIZATION != 0,
+            ..Default::default()
+        };
+        validator.validate(spv, Some(options)).unwrap();
     }
 
     if flags & NAGA_VALIDATE != 0 {
@@ -71,64 +101,1930 @@ test_with_spv_and_fn!(
     "./test/splitcombined/test_mixed.spv",
     combimgsampsplitter
 );
+test_with_spv_and_fn!(
+    splitcombined_test_mixed_append_bindings,
+    DO_ALL,
+    "./test/splitcombined/test_mixed.spv",
+    |spv, corrections| combimgsampsplitter_with_allocator(spv, corrections, &mut AppendBindings)
+);
+test_with_spv_and_fn!(
+    splitcombined_test_mixed_extra_descriptor_set,
+    DO_ALL,
+    "./test/splitcombined/test_mixed.spv",
+    |spv, corrections| {
+        combimgsampsplitter_with_allocator(spv, corrections, &mut ExtraDescriptorSet::default())
+    }
+);
+test_with_spv_and_fn!(
+    splitcombined_test_array,
+    DO_ALL,
+    "./test/splitcombined/test_array.spv",
+    combimgsampsplitter
+);
+test_with_spv_and_fn!(
+    splitcombined_test_array_nested,
+    SPV_VALIDATE,
+    "./test/splitcombined/test_array_nested.spv",
+    combimgsampsplitter
+);
+test_with_spv_and_fn!(
+    splitcombined_test_runtime_array,
+    SPV_VALIDATE,
+    "./test/splitcombined/test_runtime_array.spv",
+    combimgsampsplitter
+);
+test_with_spv_and_fn!(
+    splitcombined_test_image_before_void,
+    DO_ALL,
+    "./test/splitcombined/test_image_before_void.spv",
+    combimgsampsplitter
+);
+test_with_spv_and_fn!(
+    splitcombined_test_no_void,
+    SPV_VALIDATE,
+    "./test/splitcombined/test_no_void.spv",
+    combimgsampsplitter
+);
+test_with_spv_and_fn!(
+    splitcombined_test_forward,
+    SPV_VALIDATE | SPV_BEFORE_LEGALIZATION,
+    "./test/splitcombined/test_forward.spv",
+    combimgsampsplitter
+);
+test_with_spv_and_fn!(
+    splitcombined_test_value_parameter,
+    DO_ALL,
+    "./test/splitcombined/test_value_parameter.spv",
+    combimgsampsplitter
+);
+test_with_spv_and_fn!(
+    splitcombined_test_value_parameter_blocks,
+    DO_ALL,
+    "./test/splitcombined/test_value_parameter_blocks.spv",
+    combimgsampsplitter
+);
+test_with_spv_and_fn!(
+    splitcombined_test_forward_blocks,
+    SPV_VALIDATE | SPV_BEFORE_LEGALIZATION,
+    "./test/splitcombined/test_forward_blocks.spv",
+    combimgsampsplitter
+);
+test_with_spv_and_fn!(
+    splitcombined_test_fetch_only,
+    DO_ALL,
+    "./test/splitcombined/test_fetch_only.spv",
+    combimgsampsplitter
+);
+test_with_spv_and_fn!(
+    splitcombined_test_fetch_only_array,
+    SPV_VALIDATE,
+    "./test/splitcombined/test_fetch_only_array.spv",
+    combimgsampsplitter
+);
+test_with_spv_and_fn!(
+    splitcombined_test_spv14,
+    DO_ALL,
+    "./test/splitcombined/test_spv14.spv",
+    combimgsampsplitter
+);
 
 test_with_spv_and_fn!(
     splitdref_test_wrong_type_image,
-    SPV_VALIDATE,
+    DO_ALL,
     "./test/splitdref/test_wrong_type_image.spv",
     drefsplitter
 );
 test_with_spv_and_fn!(
     splitdref_test_image,
-    SPV_VALIDATE,
+    DO_ALL,
     "./test/splitdref/test_image.spv",
     drefsplitter
 );
 test_with_spv_and_fn!(
     splitdref_test_nested_image,
-    SPV_VALIDATE,
+    DO_ALL,
     "./test/splitdref/test_nested_image.spv",
     drefsplitter
 );
 test_with_spv_and_fn!(
     splitdref_test_nested2_image,
-    SPV_VALIDATE,
+    DO_ALL,
     "./test/splitdref/test_nested2_image.spv",
     drefsplitter
 );
 test_with_spv_and_fn!(
     splitdref_test_sampler,
-    SPV_VALIDATE,
+    DO_ALL,
     "./test/splitdref/test_sampler.spv",
     drefsplitter
 );
 test_with_spv_and_fn!(
     splitdref_test_nested_sampler,
-    SPV_VALIDATE,
+    DO_ALL,
     "./test/splitdref/test_nested_sampler.spv",
     drefsplitter
 );
 test_with_spv_and_fn!(
     splitdref_test_nested2_sampler,
-    SPV_VALIDATE,
+    DO_ALL,
     "./test/splitdref/test_nested2_sampler.spv",
     drefsplitter
 );
 test_with_spv_and_fn!(
     splitdref_test_hidden_dref,
-    SPV_VALIDATE,
+    DO_ALL,
     "./test/splitdref/test_hidden_dref.spv",
     drefsplitter
 );
 test_with_spv_and_fn!(
     splitdref_test_hidden2_dref,
-    SPV_VALIDATE,
+    DO_ALL,
     "./test/splitdref/test_hidden2_dref.spv",
     drefsplitter
 );
 test_with_spv_and_fn!(
     splitdref_test_hidden3_dref,
-    SPV_VALIDATE,
+    DO_ALL,
     "./test/splitdref/test_hidden3_dref.spv",
     drefsplitter
 );
+
+test_with_spv_and_fn!(
+    splitdref_test_specialize_dref,
+    DO_ALL,
+    "./test/splitdref/test_specialize_dref.spv",
+    drefsplitter
+);
+
+test_with_spv_and_fn!(
+    splitdref_test_unmixed_helper,
+    DO_ALL,
+    "./test/splitdref/test_unmixed_helper.spv",
+    drefsplitter
+);
+
+#[test]
+fn splitdref_test_unmixed_helper_unchanged() {
+    let spv =
+        u8_slice_to_u32_vec(include_bytes!("./test/splitdref/test_unmixed_helper.spv")).unwrap();
+    let mut corrections = None;
+    let out_spv = drefsplitter(&spv, &mut corrections).unwrap();
+
+    // - Nothing is mixed, so the helper is neither cloned nor stripped of its parameters
+    let count = |spv: &[u32], opcode: u16| {
+        Module::parse(spv)
+            .unwrap()
+            .instructions
+            .iter()
+            .filter(|instruction| instruction.opcode == opcode)
+            .count()
+    };
+    assert_eq!(count(&out_spv, SPV_INSTRUCTION_OP_FUNCTION), 2);
+    assert_eq!(
+        count(&out_spv, SPV_INSTRUCTION_OP_FUNCTION_PARAMTER),
+        count(&spv, SPV_INSTRUCTION_OP_FUNCTION_PARAMTER)
+    );
+    assert!(
+        corrections
+            .unwrap()
+            .resources
+            .iter()
+            .all(|resource| resource.variables.len() == 1)
+    );
+}
+
+test_with_spv_and_fn!(
+    splitdref_test_dead_dref,
+    SPV_VALIDATE,
+    "./test/splitdref/test_dead_dref.spv",
+    drefsplitter
+);
+
+#[test]
+fn splitdref_test_dead_dref_unchanged() {
+    let spv = u8_slice_to_u32_vec(include_bytes!("./test/splitdref/test_dead_dref.spv")).unwrap();
+    let mut corrections = None;
+    let out_spv = drefsplitter(&spv, &mut corrections).unwrap();
+
+    // - Only the dead function compares u_color, so neither it nor compare is cloned, and the dead
+    //   function is kept
+    let count = |spv: &[u32], opcode: u16| {
+        Module::parse(spv)
+            .unwrap()
+            .instructions
+            .iter()
+            .filter(|instruction| instruction.opcode == opcode)
+            .count()
+    };
+    assert_eq!(count(&out_spv, SPV_INSTRUCTION_OP_FUNCTION), 3);
+    assert_eq!(
+        count(&out_spv, SPV_INSTRUCTION_OP_VARIABLE),
+        count(&spv, SPV_INSTRUCTION_OP_VARIABLE)
+    );
+    assert!(
+        corrections
+            .unwrap()
+            .resources
+            .iter()
+            .all(|resource| resource.variables.len() == 1)
+    );
+}
+
+test_with_spv_and_fn!(
+    splitdref_test_shared_load,
+    DO_ALL,
+    "./test/splitdref/test_shared_load.spv",
+    drefsplitter
+);
+test_with_spv_and_fn!(
+    splitdref_test_forward_dref,
+    SPV_VALIDATE | SPV_BEFORE_LEGALIZATION,
+    "./test/splitdref/test_forward_dref.spv",
+    drefsplitter
+);
+#[test]
+fn splitdref_test_forward_dref_sources() {
+    let spv =
+        u8_slice_to_u32_vec(include_bytes!("./test/splitdref/test_forward_dref.spv")).unwrap();
+    let mut corrections = None;
+    let out_spv = drefsplitter(&spv, &mut corrections).unwrap();
+    assert_sampled_images_in_user_blocks(&out_spv);
+
+    // - u_texture and u_other_texture gain depth textures at (0, 1) and (0, 6), u_sampler a
+    //   comparison sampler at (0, 3)
+    let corrections = corrections.unwrap();
+    let depth_textures = corrections
+        .resources
+        .iter()
+        .flat_map(|resource| resource.variables.iter())
+        .filter(|variable| {
+            variable
+                .texture
+                .is_some_and(|texture| texture.sample_type == TextureSampleType::Depth)
+        })
+        .map(|variable| variable.location)
+        .collect::<Vec<_>>();
+    assert_eq!(depth_textures, vec![location(0, 1), location(0, 6)]);
+
+    // - Regular sampling keeps the original variables, comparisons use the new ones, through
+    //   the copy and both phis
+    assert_eq!(
+        sampled_image_sources(&out_spv),
+        vec![
+            (vec![location(0, 0)], vec![location(0, 2)]),
+            (vec![location(0, 1)], vec![location(0, 4)]),
+            (vec![location(0, 0), location(0, 5)], vec![location(0, 2)]),
+            (vec![location(0, 1), location(0, 6)], vec![location(0, 3)]),
+        ]
+    );
+}
+
+test_with_spv_and_fn!(
+    splitdref_test_spv14_dref,
+    DO_ALL,
+    "./test/splitdref/test_spv14_dref.spv",
+    drefsplitter
+);
+
+// The interface of every OpEntryPoint, in order
+fn entry_point_interfaces(spv: &[u32]) -> Vec<Vec<u32>> {
+    let module = Module::parse(spv).unwrap();
+    module
+        .instructions
+        .iter()
+        .filter(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_ENTRY_POINT)
+        .map(|instruction| {
+            let name_length = instruction.operands[2..]
+                .iter()
+                .position(|word| word.to_le_bytes().contains(&0))
+                .unwrap();
+            instruction.operands[2 + name_length + 1..].to_vec()
+        })
+        .collect()
+}
+
+#[test]
+fn spv14_entry_point_interfaces() {
+    // - Only main reaches u_texture, so only main gains its sampler
+    let spv = u8_slice_to_u32_vec(include_bytes!("./test/splitcombined/test_spv14.spv")).unwrap();
+    let out_spv = combimgsampsplitter(&spv, &mut None).unwrap();
+    let interfaces = entry_point_interfaces(&spv);
+    let out_interfaces = entry_point_interfaces(&out_spv);
+    assert_eq!(out_interfaces[0].len(), interfaces[0].len() + 1);
+    assert_eq!(out_interfaces[1], interfaces[1]);
+
+    // - Both entry points compare u_texture, so both gain its complement
+    let spv = u8_slice_to_u32_vec(include_bytes!("./test/splitdref/test_spv14_dref.spv")).unwrap();
+    let out_spv = drefsplitter(&spv, &mut None).unwrap();
+    let interfaces = entry_point_interfaces(&spv);
+    let out_interfaces = entry_point_interfaces(&out_spv);
+    for (interface, out_interface) in interfaces.iter().zip(out_interfaces.iter()) {
+        assert_eq!(out_interface.len(), interface.len() + 1);
+        assert_eq!(out_interface[..interface.len()], interface[..]);
+    }
+}
+
+#[test]
+fn splitcombined_test_forward_sources() {
+    for spv in [
+        &include_bytes!("./test/splitcombined/test_forward.spv")[..],
+        &include_bytes!("./test/splitcombined/test_forward_blocks.spv")[..],
+    ] {
+        let spv = u8_slice_to_u32_vec(spv).unwrap();
+        let out_spv = combimgsampsplitter(&spv, &mut None).unwrap();
+        assert_sampled_images_in_user_blocks(&out_spv);
+
+        // - The copy of u_a, then the phi of u_b and the copy, each recombined from forwarded
+        //   textures and samplers
+        assert_eq!(
+            sampled_image_sources(&out_spv),
+            vec![
+                (vec![location(0, 0)], vec![location(0, 1)]),
+                (
+                    vec![location(0, 0), location(0, 2)],
+                    vec![location(0, 1), location(0, 3)]
+                ),
+            ]
+        );
+    }
+}
+
+#[test]
+fn splitcombined_test_array_nested_sources() {
+    let spv =
+        u8_slice_to_u32_vec(include_bytes!("./test/splitcombined/test_array_nested.spv")).unwrap();
+    let out_spv = combimgsampsplitter(&spv, &mut None).unwrap();
+
+    // - main samples u_textures[1][2] and u_other, sample_one is passed u_textures[0][1]
+    assert_eq!(
+        sampled_image_sources(&out_spv),
+        vec![
+            (vec![location(0, 0)], vec![location(0, 1)]),
+            (vec![location(0, 2)], vec![location(0, 3)]),
+            (vec![location(0, 0)], vec![location(0, 1)]),
+        ]
+    );
+
+    // - Both arrays are indexed the same way
+    let module = Module::parse(&out_spv).unwrap();
+    let locations = decorated_locations(&module);
+    let mut access_chains = HashMap::<Vec<u32>, Vec<DescriptorBinding>>::new();
+    for instruction in module.instructions.iter() {
+        if instruction.opcode == SPV_INSTRUCTION_OP_ACCESS_CHAIN {
+            access_chains
+                .entry(instruction.operands[1..].to_vec())
+                .or_default()
+                .push(locations[&instruction.operands[0]]);
+        }
+    }
+    assert_eq!(access_chains.len(), 2);
+    for mut chain_locations in access_chains.into_values() {
+        chain_locations.sort();
+        assert_eq!(chain_locations, vec![location(0, 0), location(0, 1)]);
+    }
+}
+
+#[test]
+fn splitcombined_test_runtime_array_binding_array() {
+    let spv = u8_slice_to_u32_vec(include_bytes!(
+        "./test/splitcombined/test_runtime_array.spv"
+    ))
+    .unwrap();
+    let out_spv = combimgsampsplitter(&spv, &mut None).unwrap();
+
+    // naga rejects SPV_EXT_descriptor_indexing by name, even though it supports binding arrays
+    let mut module = Module::parse(&out_spv).unwrap();
+    module
+        .instructions
+        .retain(|instruction| instruction.opcode != SPV_INSTRUCTION_OP_EXTENSION);
+    let spv_u8 = u32_slice_to_u8_vec(&module.assemble().unwrap());
+
+    let module = front::spv::parse_u8_slice(&spv_u8, &front::spv::Options::default()).unwrap();
+    let mut caps = valid::Capabilities::default();
+    caps.set(
+        valid::Capabilities::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+        true,
+    );
+    caps.set(valid::Capabilities::SAMPLER_NON_UNIFORM_INDEXING, true);
+    let info = valid::Validator::new(valid::ValidationFlags::all(), caps)
+        .validate(&module)
+        .unwrap();
+    let wgsl = back::wgsl::write_string(&module, &info, back::wgsl::WriterFlags::empty()).unwrap();
+
+    assert!(wgsl.contains("binding_array<texture_2d<f32>>"));
+    assert!(wgsl.contains("binding_array<sampler>"));
+}
+
+fn location(descriptor_set: u32, binding: u32) -> DescriptorBinding {
+    DescriptorBinding {
+        descriptor_set,
+        binding,
+    }
+}
+
+fn split_test_mixed(
+    allocator: &mut dyn BindingAllocator,
+) -> Result<(Vec<u32>, CorrectionMap), TransformError> {
+    let spv = u8_slice_to_u32_vec(include_bytes!("./test/splitcombined/test_mixed.spv")).unwrap();
+    let mut corrections = None;
+    let out_spv = combimgsampsplitter_with_allocator(&spv, &mut corrections, allocator)?;
+    Ok((out_spv, corrections.unwrap()))
+}
+
+// Every location in the correction map must be decorated in the module
+fn assert_locations_decorated(spv: &[u32], corrections: &CorrectionMap) {
+    let module = Module::parse(spv).unwrap();
+    let mut decorated = std::collections::HashMap::<u32, (Option<u32>, Option<u32>)>::new();
+    for instruction in module.instructions.iter() {
+        if instruction.opcode != SPV_INSTRUCTION_OP_DECORATE {
+            continue;
+        }
+        match instruction.operands[1] {
+            SPV_DECORATION_DESCRIPTOR_SET => {
+                decorated.entry(instruction.operands[0]).or_default().0 =
+                    Some(instruction.operands[2])
+            }
+            SPV_DECORATION_BINDING => {
+                decorated.entry(instruction.operands[0]).or_default().1 =
+                    Some(instruction.operands[2])
+            }
+            _ => {}
+        }
+    }
+    let decorated = decorated
+        .into_values()
+        .map(|(descriptor_set, binding)| location(descriptor_set.unwrap(), binding.unwrap()))
+        .collect::<HashSet<_>>();
+
+    for set in corrections.sets.values() {
+        for binding in set.bindings.values() {
+            assert_eq!(binding.locations.len(), binding.corrections.len() + 1);
+            for location in binding.locations.iter() {
+                assert!(decorated.contains(location), "{:?}", location);
+            }
+        }
+    }
+}
+
+// Where every variable with both a DescriptorSet and a Binding decoration is bound
+fn decorated_locations(module: &Module) -> HashMap<u32, DescriptorBinding> {
+    let decoration = |id, decoration| {
+        module.instructions.iter().find_map(|instruction| {
+            (instruction.opcode == SPV_INSTRUCTION_OP_DECORATE
+                && instruction.operands[..2] == [id, decoration])
+            .then(|| instruction.operands[2])
+        })
+    };
+    module
+        .instructions
+        .iter()
+        .filter(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_DECORATE)
+        .filter_map(|instruction| {
+            let id = instruction.operands[0];
+            Some((
+                id,
+                location(
+                    decoration(id, SPV_DECORATION_DESCRIPTOR_SET)?,
+                    decoration(id, SPV_DECORATION_BINDING)?,
+                ),
+            ))
+        })
+        .collect()
+}
+
+// The locations of the images and the samplers of every OpSampledImage, in instruction order.
+// Values are followed through loads, access chains, forwarding instructions and function calls
+// back to the variables they come from.
+fn sampled_image_sources(spv: &[u32]) -> Vec<(Vec<DescriptorBinding>, Vec<DescriptorBinding>)> {
+    let module = Module::parse(spv).unwrap();
+    let definitions = module.definitions();
+    let locations = decorated_locations(&module);
+
+    let mut parameters = HashMap::<u32, Vec<u32>>::new();
+    let mut function_id = 0;
+    for instruction in module.instructions.iter() {
+        match instruction.opcode {
+            SPV_INSTRUCTION_OP_FUNCTION => function_id = instruction.id(),
+            SPV_INSTRUCTION_OP_FUNCTION_PARAMTER => parameters
+                .entry(function_id)
+                .or_default()
+                .push(instruction.id()),
+            _ => {}
+        }
+    }
+    let mut arguments = HashMap::<u32, Vec<u32>>::new();
+    for instruction in module.instructions.iter() {
+        if instruction.opcode == SPV_INSTRUCTION_OP_FUNCTION_CALL {
+            for (&parameter_id, &argument_id) in parameters[&instruction.operands[0]]
+                .iter()
+                .zip(instruction.operands[1..].iter())
+            {
+                arguments.entry(parameter_id).or_default().push(argument_id);
+            }
+        }
+    }
+
+    let sources = |id: u32| {
+        let mut sources = BTreeSet::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+            if let Some(&location) = locations.get(&id) {
+                sources.insert(location);
+                continue;
+            }
+            if let Some(argument_ids) = arguments.get(&id) {
+                stack.extend(argument_ids);
+                continue;
+            }
+            let instruction = &module.instructions[definitions[&id]];
+            match instruction.opcode {
+                SPV_INSTRUCTION_OP_LOAD
+                | SPV_INSTRUCTION_OP_COPY_OBJECT
+                | SPV_INSTRUCTION_OP_ACCESS_CHAIN
+                | SPV_INSTRUCTION_OP_IN_BOUNDS_ACCESS_CHAIN => stack.push(instruction.operands[0]),
+                SPV_INSTRUCTION_OP_PHI => stack.extend(instruction.operands.iter().step_by(2)),
+                SPV_INSTRUCTION_OP_SELECT => stack.extend(&instruction.operands[1..3]),
+                opcode => panic!("%{} comes from an unexpected opcode {}", id, opcode),
+            }
+        }
+        sources.into_iter().collect::<Vec<_>>()
+    };
+
+    module
+        .instructions
+        .iter()
+        .filter(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_SAMPLED_IMAGE)
+        .map(|instruction| {
+            (
+                sources(instruction.operands[0]),
+                sources(instruction.operands[1]),
+            )
+        })
+        .collect()
+}
+
+// Every OpSampledImage must be in the same block as its users
+fn assert_sampled_images_in_user_blocks(spv: &[u32]) {
+    let module = Module::parse(spv).unwrap();
+    let blocks = || {
+        let mut label_id = None;
+        module.instructions.iter().map(move |instruction| {
+            match instruction.opcode {
+                SPV_INSTRUCTION_OP_FUNCTION => label_id = None,
+                SPV_INSTRUCTION_OP_LABEL => label_id = Some(instruction.id()),
+                _ => {}
+            }
+            (label_id, instruction)
+        })
+    };
+
+    let sampled_image_blocks = blocks()
+        .filter(|(_, instruction)| instruction.opcode == SPV_INSTRUCTION_OP_SAMPLED_IMAGE)
+        .map(|(label_id, instruction)| (instruction.id(), label_id))
+        .collect::<HashMap<_, _>>();
+    for (label_id, instruction) in blocks().filter(|(label_id, _)| label_id.is_some()) {
+        for (operand_idx, operand) in instruction.operands.iter().enumerate() {
+            if spv_is_literal_operand(instruction.opcode, operand_idx) {
+                continue;
+            }
+            if let Some(&sampled_image_label_id) = sampled_image_blocks.get(operand) {
+                assert_eq!(
+                    sampled_image_label_id, label_id,
+                    "%{} is used outside of its block",
+                    operand
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn splitcombined_test_mixed_binding_allocators() {
+    // u_tex1 (0, 0) and u_tex_array (0, 4) are combined, u_tex2 (0, 1) is never sampled
+    let locations = |corrections: &CorrectionMap, binding: u32| {
+        corrections.sets[&0].bindings[&binding].locations.clone()
+    };
+
+    let (out_spv, corrections) = split_test_mixed(&mut ShiftBindings).unwrap();
+    assert_locations_decorated(&out_spv, &corrections);
+    assert_eq!(
+        corrections.sets[&0].bindings[&0].corrections,
+        vec![CorrectionType::SplitCombined]
+    );
+    assert_eq!(
+        locations(&corrections, 0),
+        vec![location(0, 0), location(0, 1)]
+    );
+    assert_eq!(locations(&corrections, 1), vec![location(0, 2)]);
+    assert_eq!(
+        locations(&corrections, 4),
+        vec![location(0, 5), location(0, 6)]
+    );
+    let u_tex1 = &corrections.resources[0];
+    assert_eq!(u_tex1.original, location(0, 0));
+    assert_eq!(u_tex1.texture().unwrap().id, u_tex1.original_id);
+    assert_eq!(u_tex1.texture().unwrap().location, location(0, 0));
+    assert_eq!(u_tex1.sampler().unwrap().location, location(0, 1));
+    assert_eq!(
+        u_tex1.sampler().unwrap().correction,
+        Some(CorrectionType::SplitCombined)
+    );
+    let u_tex2 = &corrections.resources[1];
+    assert_eq!(u_tex2.texture().unwrap().location, location(0, 2));
+    assert!(u_tex2.sampler().is_none());
+
+    let (out_spv, corrections) = split_test_mixed(&mut AppendBindings).unwrap();
+    assert_locations_decorated(&out_spv, &corrections);
+    assert_eq!(
+        locations(&corrections, 0),
+        vec![location(0, 0), location(0, 5)]
+    );
+    assert_eq!(locations(&corrections, 1), vec![location(0, 1)]);
+    assert_eq!(
+        locations(&corrections, 4),
+        vec![location(0, 4), location(0, 6)]
+    );
+
+    let (out_spv, corrections) = split_test_mixed(&mut ExtraDescriptorSet::default()).unwrap();
+    assert_locations_decorated(&out_spv, &corrections);
+    assert_eq!(
+        locations(&corrections, 0),
+        vec![location(0, 0), location(1, 0)]
+    );
+    assert_eq!(
+        locations(&corrections, 4),
+        vec![location(0, 4), location(1, 1)]
+    );
+
+    let mut explicit = ExplicitBindings::default();
+    explicit.bindings.insert(
+        (location(0, 0), CorrectionType::SplitCombined),
+        location(2, 7),
+    );
+    let err = split_test_mixed(&mut explicit.clone()).unwrap_err();
+    assert!(matches!(
+        err,
+        TransformError::BindingAllocation { location: l, .. } if l == location(0, 4)
+    ));
+
+    explicit.bindings.insert(
+        (location(0, 4), CorrectionType::SplitCombined),
+        location(2, 8),
+    );
+    let (out_spv, corrections) = split_test_mixed(&mut explicit.clone()).unwrap();
+    assert_locations_decorated(&out_spv, &corrections);
+    assert_eq!(
+        locations(&corrections, 0),
+        vec![location(0, 0), location(2, 7)]
+    );
+    assert_eq!(
+        locations(&corrections, 4),
+        vec![location(0, 4), location(2, 8)]
+    );
+
+    // - u_tex2 is already bound there
+    explicit.bindings.insert(
+        (location(0, 4), CorrectionType::SplitCombined),
+        location(0, 1),
+    );
+    let err = split_test_mixed(&mut explicit).unwrap_err();
+    assert!(matches!(
+        err,
+        TransformError::BindingAllocation { location: l, .. } if l == location(0, 4)
+    ));
+}
+
+#[test]
+fn splitdref_test_mixed_dref_resources() {
+    let spv = u8_slice_to_u32_vec(include_bytes!("./test/splitdref/test_mixed_dref.spv")).unwrap();
+    let mut corrections = None;
+    // - Corrections carry over from one splitter to the next
+    let out_spv = combimgsampsplitter(&spv, &mut corrections).unwrap();
+    let out_spv = drefsplitter(&out_spv, &mut corrections).unwrap();
+    let corrections = corrections.unwrap();
+    assert_locations_decorated(&out_spv, &corrections);
+
+    // u_sampler (0, 0), u_texture (0, 1) and useless (0, 2)
+    let variables = corrections
+        .resources
+        .iter()
+        .map(|resource| {
+            resource
+                .variables
+                .iter()
+                .map(|variable| (variable.kind, variable.location, variable.correction))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        variables,
+        vec![
+            vec![
+                (ResourceKind::Sampler, location(0, 0), None),
+                (
+                    ResourceKind::Sampler,
+                    location(0, 1),
+                    Some(CorrectionType::SplitDrefRegular)
+                ),
+            ],
+            vec![
+                (ResourceKind::Texture, location(0, 2), None),
+                (
+                    ResourceKind::Texture,
+                    location(0, 3),
+                    Some(CorrectionType::SplitDrefRegular)
+                ),
+            ],
+            vec![(ResourceKind::Texture, location(0, 4), None)],
+        ]
+    );
+
+    // - The dref half is a comparison sampler and a depth texture, `useless` is never sampled
+    let binding_types = corrections
+        .resources
+        .iter()
+        .flat_map(|resource| resource.variables.iter())
+        .map(|variable| {
+            (
+                variable.texture.map(|texture| texture.sample_type),
+                variable.sampler,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        binding_types,
+        vec![
+            (None, Some(SamplerBindingType::Filtering)),
+            (None, Some(SamplerBindingType::Comparison)),
+            (Some(TextureSampleType::Float), None),
+            (Some(TextureSampleType::Depth), None),
+            (Some(TextureSampleType::UnfilterableFloat), None),
+        ]
+    );
+
+    // - Visible to the stage of the only entry point, unless the entry point never uses it
+    let visibilities = corrections
+        .resources
+        .iter()
+        .flat_map(|resource| resource.variables.iter())
+        .map(|variable| variable.visibility)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        visibilities,
+        vec![
+            ShaderStages::FRAGMENT,
+            ShaderStages::FRAGMENT,
+            ShaderStages::FRAGMENT,
+            ShaderStages::FRAGMENT,
+            ShaderStages::NONE,
+        ]
+    );
+}
+
+#[test]
+fn splitcombined_test_fetch_only_binding_types() {
+    let spv =
+        u8_slice_to_u32_vec(include_bytes!("./test/splitcombined/test_fetch_only.spv")).unwrap();
+    let mut corrections = None;
+    combimgsampsplitter(&spv, &mut corrections).unwrap();
+    let corrections = corrections.unwrap();
+
+    // - u_gbuffer is only fetched from, so it cannot be filtered and gets no sampler
+    let [gbuffer, color] = corrections.resources.as_slice() else {
+        panic!("expected u_gbuffer and u_color");
+    };
+    assert_eq!(gbuffer.variables.len(), 1);
+    assert!(gbuffer.sampler().is_none());
+    assert_eq!(
+        gbuffer.texture().and_then(|variable| variable.texture),
+        Some(TextureBindingInfo {
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+            sample_type: TextureSampleType::UnfilterableFloat,
+        })
+    );
+
+    // - u_color is also sampled
+    assert_eq!(
+        color
+            .texture()
+            .and_then(|variable| variable.texture)
+            .map(|texture| texture.sample_type),
+        Some(TextureSampleType::Float)
+    );
+    assert_eq!(
+        color.sampler().and_then(|variable| variable.sampler),
+        Some(SamplerBindingType::Filtering)
+    );
+}
+
+test_with_spv_and_fn!(
+    splitcombined_test_dead_function,
+    SPV_VALIDATE,
+    "./test/splitcombined/test_dead_function.spv",
+    combimgsampsplitter
+);
+
+#[test]
+fn splitcombined_test_dead_function_resources() {
+    let spv = u8_slice_to_u32_vec(include_bytes!(
+        "./test/splitcombined/test_dead_function.spv"
+    ))
+    .unwrap();
+    let mut corrections = None;
+    let out_spv = combimgsampsplitter(&spv, &mut corrections).unwrap();
+    let corrections = corrections.unwrap();
+
+    // - The dead function is kept as it is, but u_unused is only used by it, so it is not split
+    //   and visible to no stage
+    let module = Module::parse(&out_spv).unwrap();
+    let names = module
+        .instructions
+        .iter()
+        .filter(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_NAME)
+        .map(|instruction| u32_slice_to_u8_vec(&instruction.operands[1..]))
+        .collect::<Vec<_>>();
+    assert!(names.iter().any(|name| name.starts_with(b"dead")));
+    assert!(names.iter().any(|name| name.starts_with(b"height")));
+
+    let [color, height, unused] = corrections.resources.as_slice() else {
+        panic!("expected u_color, u_height and u_unused");
+    };
+    let visibilities = |resource: &super::CorrectionResource| {
+        resource
+            .variables
+            .iter()
+            .map(|variable| variable.visibility)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(visibilities(color), vec![ShaderStages::FRAGMENT; 2]);
+    assert_eq!(visibilities(height), vec![ShaderStages::VERTEX; 2]);
+    assert_eq!(visibilities(unused), vec![ShaderStages::NONE]);
+    assert!(unused.sampler().is_none());
+
+    // - u_unused still points to the combimg the dead function loads
+    let definitions = module.definitions();
+    let type_of = |id: u32| &module.instructions[definitions[&id]];
+    let u_unused = type_of(unused.variables[0].id);
+    let pointee = type_of(type_of(u_unused.type_id()).operands[1]);
+    assert_eq!(pointee.opcode, SPV_INSTRUCTION_OP_TYPE_SAMPLED_IMAGE);
+}
+
+test_with_spv_and_fn!(
+    splitcombined_test_linkage_export,
+    SPV_VALIDATE,
+    "./test/splitcombined/test_linkage_export.spv",
+    combimgsampsplitter
+);
+
+#[test]
+fn splitcombined_test_linkage_export_kept() {
+    let spv = u8_slice_to_u32_vec(include_bytes!(
+        "./test/splitcombined/test_linkage_export.spv"
+    ))
+    .unwrap();
+    let mut corrections = None;
+    let out_spv = combimgsampsplitter(&spv, &mut corrections).unwrap();
+
+    // - No entry point calls exported_fn, but other modules can, so it and its linkage stay
+    let module = Module::parse(&out_spv).unwrap();
+    let count = |opcode: u16| {
+        module
+            .instructions
+            .iter()
+            .filter(|instruction| instruction.opcode == opcode)
+            .count()
+    };
+    assert_eq!(count(SPV_INSTRUCTION_OP_FUNCTION), 3);
+    assert!(module.instructions.iter().any(|instruction| {
+        instruction.opcode == SPV_INSTRUCTION_OP_DECORATE
+            && instruction.operands[1] == SPV_DECORATION_LINKAGE_ATTRIBUTES
+            && decode_string(&instruction.operands[2..]).as_deref() == Some("exported_fn")
+    }));
+
+    // - Both variables are split, u_export is only used by the exported function
+    let corrections = corrections.unwrap();
+    assert!(
+        corrections
+            .resources
+            .iter()
+            .all(|resource| resource.sampler().is_some())
+    );
+}
+
+#[test]
+fn entry_point_corrections() {
+    let spv = u8_slice_to_u32_vec(include_bytes!(
+        "./test/splitcombined/test_dead_function.spv"
+    ))
+    .unwrap();
+    let mut corrections = None;
+    combimgsampsplitter(&spv, &mut corrections).unwrap();
+    let corrections = corrections.unwrap();
+
+    // - Each entry point only gets the resources it uses, visible to its own stage
+    let locations = |name: &str, execution_model: ExecutionModel| {
+        let entry_point = corrections.entry_point(name, execution_model).unwrap();
+        entry_point
+            .resources
+            .iter()
+            .flat_map(|resource| resource.variables.iter())
+            .map(|variable| {
+                assert_eq!(variable.visibility, execution_model.stage());
+                (variable.kind, variable.location)
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        locations("fs_main", ExecutionModel::Fragment),
+        vec![
+            (ResourceKind::Texture, location(0, 0)),
+            (ResourceKind::Sampler, location(0, 1)),
+        ]
+    );
+    assert_eq!(
+        locations("vs_main", ExecutionModel::Vertex),
+        vec![
+            (ResourceKind::Texture, location(0, 2)),
+            (ResourceKind::Sampler, location(0, 3)),
+        ]
+    );
+    assert!(
+        corrections
+            .entry_point("fs_main", ExecutionModel::Vertex)
+            .is_none()
+    );
+
+    // - They survive being embedded
+    let out_spv = embed_corrections(&spv, &corrections).unwrap();
+    assert_eq!(read_embedded_corrections(&out_spv), Some(corrections));
+}
+
+test_with_spv_and_fn!(
+    splitcombined_test_gl_binding,
+    DO_ALL,
+    "./test/splitcombined/test_gl_binding.spv",
+    combimgsampsplitter
+);
+
+#[test]
+fn gl_binding_default_descriptor_set() {
+    let spv =
+        u8_slice_to_u32_vec(include_bytes!("./test/splitcombined/test_gl_binding.spv")).unwrap();
+
+    // - Binding alone is placed in set 0
+    let mut corrections = None;
+    let out_spv = combimgsampsplitter(&spv, &mut corrections).unwrap();
+    let corrections = corrections.unwrap();
+    assert_locations_decorated(&out_spv, &corrections);
+    let resource_locations = |corrections: &CorrectionMap| {
+        corrections
+            .resources
+            .iter()
+            .map(|resource| {
+                resource
+                    .variables
+                    .iter()
+                    .map(|variable| variable.location)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        resource_locations(&corrections),
+        vec![
+            vec![location(0, 0), location(0, 1)],
+            vec![location(0, 2), location(0, 3)],
+        ]
+    );
+
+    // - Or in the set of our choosing
+    let options = Options {
+        default_descriptor_set: 2,
+        ..Default::default()
+    };
+    let output = webgpu_transform(&spv, &options).unwrap();
+    try_spv_to_wgsl(&output.spv, DO_ALL);
+    let corrections = output.corrections.unwrap();
+    assert_locations_decorated(&output.spv, &corrections);
+    assert_eq!(
+        resource_locations(&corrections),
+        vec![
+            vec![location(2, 0), location(2, 1)],
+            vec![location(2, 2), location(2, 3)],
+        ]
+    );
+
+    // - DescriptorSet alone does not bind anything
+    let mut module = Module::parse(&spv).unwrap();
+    let u_other_binding = module
+        .instructions
+        .iter_mut()
+        .find(|instruction| {
+            instruction.opcode == SPV_INSTRUCTION_OP_DECORATE
+                && instruction.operands[1..] == [SPV_DECORATION_BINDING, 1]
+        })
+        .unwrap();
+    u_other_binding.operands[1..].copy_from_slice(&[SPV_DECORATION_DESCRIPTOR_SET, 3]);
+    let spv = module.assemble().unwrap();
+    let mut corrections = None;
+    combimgsampsplitter(&spv, &mut corrections).unwrap();
+    assert_eq!(
+        resource_locations(&corrections.unwrap()),
+        vec![vec![location(0, 0), location(0, 1)]]
+    );
+
+    // - Without a default set, the error says which binding has no set
+    let u_texture_id = module
+        .instructions
+        .iter()
+        .find(|instruction| {
+            instruction.opcode == SPV_INSTRUCTION_OP_NAME
+                && decode_string(&instruction.operands[1..]).as_deref() == Some("u_texture")
+        })
+        .unwrap()
+        .operands[0];
+    let result = PassManager::new()
+        .add(SplitCombinedPass::<ShiftBindings>::default())
+        .run(&spv, &mut None);
+    assert_eq!(
+        result.unwrap_err(),
+        TransformError::BindingWithoutDescriptorSet {
+            id: u_texture_id,
+            binding: 0
+        }
+    );
+}
+
+test_with_spv_and_fn!(
+    splitcombined_test_decoration_group,
+    DO_ALL,
+    "./test/splitcombined/test_decoration_group.spv",
+    combimgsampsplitter
+);
+
+#[test]
+fn decoration_group_bindings() {
+    let spv = u8_slice_to_u32_vec(include_bytes!(
+        "./test/splitcombined/test_decoration_group.spv"
+    ))
+    .unwrap();
+
+    // - Bindings from groups are split like any other, and every group is made explicit
+    let mut corrections = None;
+    let out_spv = combimgsampsplitter(&spv, &mut corrections).unwrap();
+    let corrections = corrections.unwrap();
+    assert_locations_decorated(&out_spv, &corrections);
+    assert_eq!(
+        corrections
+            .resources
+            .iter()
+            .map(|resource| (
+                resource.original,
+                resource
+                    .variables
+                    .iter()
+                    .map(|variable| variable.location)
+                    .collect::<Vec<_>>()
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (location(0, 0), vec![location(0, 0), location(0, 1)]),
+            (location(0, 1), vec![location(0, 2), location(0, 3)]),
+            (location(0, 2), vec![location(0, 4)]),
+        ]
+    );
+    let module = Module::parse(&out_spv).unwrap();
+    assert!(!module.instructions.iter().any(|instruction| matches!(
+        instruction.opcode,
+        SPV_INSTRUCTION_OP_DECORATION_GROUP
+            | SPV_INSTRUCTION_OP_GROUP_DECORATE
+            | SPV_INSTRUCTION_OP_GROUP_MEMBER_DECORATE
+    )));
+
+    let output = webgpu_transform(&spv, &Options::default()).unwrap();
+    try_spv_to_wgsl(&output.spv, DO_ALL);
+    assert_locations_decorated(&output.spv, output.corrections.as_ref().unwrap());
+}
+
+test_with_spv_and_fn!(
+    webgpu_test_combined_dref,
+    DO_ALL,
+    "./test/webgpu/test_combined_dref.spv",
+    |spv, _: &mut Option<CorrectionMap>| webgpu_transform(spv, &Options::default())
+        .map(|output| output.spv)
+);
+test_with_spv_and_fn!(
+    webgpu_test_combined_dref_extra_descriptor_set,
+    DO_ALL,
+    "./test/webgpu/test_combined_dref.spv",
+    |spv, _: &mut Option<CorrectionMap>| {
+        let options = Options {
+            binding_strategy: BindingStrategy::ExtraDescriptorSet(None),
+            ..Default::default()
+        };
+        webgpu_transform(spv, &options).map(|output| output.spv)
+    }
+);
+
+#[test]
+fn webgpu_transform_runs_both_splitters() {
+    let spv = u8_slice_to_u32_vec(include_bytes!("./test/webgpu/test_combined_dref.spv")).unwrap();
+    let mut corrections = None;
+    let chained_spv = combimgsampsplitter(&spv, &mut corrections).unwrap();
+    let chained_spv = drefsplitter(&chained_spv, &mut corrections).unwrap();
+
+    let output = webgpu_transform(&spv, &Options::default()).unwrap();
+    assert_eq!(output.spv, chained_spv);
+    assert_eq!(output.corrections, corrections);
+
+    // - Both passes put their new variables into the same extra set
+    let options = Options {
+        binding_strategy: BindingStrategy::ExtraDescriptorSet(None),
+        ..Default::default()
+    };
+    let output = webgpu_transform(&spv, &options).unwrap();
+    let new_locations = output
+        .corrections
+        .unwrap()
+        .resources
+        .iter()
+        .flat_map(|resource| resource.variables[1..].iter())
+        .map(|variable| variable.location)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        new_locations,
+        vec![location(1, 0), location(1, 1), location(1, 2)]
+    );
+
+    let options = Options {
+        split_dref: false,
+        embed_corrections: true,
+        ..Default::default()
+    };
+    let output = webgpu_transform(&spv, &options).unwrap();
+    let mut corrections = None;
+    combimgsampsplitter(&spv, &mut corrections).unwrap();
+    assert_eq!(read_embedded_corrections(&output.spv), corrections);
+}
+
+#[test]
+fn webgpu_transform_skips_passes() {
+    // - Nothing is combined
+    let spv = u8_slice_to_u32_vec(include_bytes!("./test/splitdref/test_mixed_dref.spv")).unwrap();
+    let output = webgpu_transform(&spv, &Options::default()).unwrap();
+    assert_eq!(output.spv, drefsplitter(&spv, &mut None).unwrap());
+
+    // - Nothing is sampled for comparison
+    let spv = u8_slice_to_u32_vec(include_bytes!("./test/splitcombined/test_mixed.spv")).unwrap();
+    let output = webgpu_transform(&spv, &Options::default()).unwrap();
+    assert_eq!(output.spv, combimgsampsplitter(&spv, &mut None).unwrap());
+
+    // - Nothing at all
+    let spv =
+        u8_slice_to_u32_vec(include_bytes!("./test/pipeline/test_vert_uniform_only.spv")).unwrap();
+    let output = webgpu_transform(&spv, &Options::default()).unwrap();
+    assert_eq!(output.spv, spv);
+    assert!(output.corrections.is_none());
+}
+
+// Whites out every debug name
+struct StripNames;
+
+impl Pass for StripNames {
+    fn name(&self) -> &str {
+        "strip-names"
+    }
+
+    fn run(
+        &mut self,
+        module: &mut Module,
+        _: &mut u32,
+        _: &mut Option<CorrectionMap>,
+    ) -> Result<(), TransformError> {
+        for instruction in module.instructions.iter_mut() {
+            if matches!(
+                instruction.opcode,
+                SPV_INSTRUCTION_OP_NAME | SPV_INSTRUCTION_OP_MEMBER_NAME
+            ) {
+                *instruction = Instruction::nop();
+            }
+        }
+        Ok(())
+    }
+}
+
+// Adds an OpString with a new id
+struct TagModule;
+
+impl Pass for TagModule {
+    fn name(&self) -> &str {
+        "tag-module"
+    }
+
+    fn run(
+        &mut self,
+        module: &mut Module,
+        instruction_bound: &mut u32,
+        _: &mut Option<CorrectionMap>,
+    ) -> Result<(), TransformError> {
+        let id = *instruction_bound;
+        *instruction_bound += 1;
+        let source_idx = module
+            .instructions
+            .iter()
+            .position(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_SOURCE)
+            .unwrap();
+        module.instructions.insert(
+            source_idx,
+            Instruction::new(
+                SPV_INSTRUCTION_OP_STRING,
+                None,
+                Some(id),
+                u8_slice_to_u32_vec(b"tagged\0\0").unwrap(),
+            ),
+        );
+        Ok(())
+    }
+}
+
+#[test]
+fn pass_manager_runs_custom_passes() {
+    let spv = u8_slice_to_u32_vec(include_bytes!("./test/webgpu/test_combined_dref.spv")).unwrap();
+    let expected = webgpu_transform(&spv, &Options::default()).unwrap();
+
+    let mut corrections = None;
+    let mut passes = PassManager::new();
+    passes
+        .add(StripNames)
+        .add(SplitCombinedPass::<ShiftBindings>::default())
+        .add(SplitDrefPass::<ShiftBindings>::default())
+        .add(TagModule);
+    assert_eq!(
+        passes.names(),
+        vec!["strip-names", "split-combined", "split-dref", "tag-module"]
+    );
+    let out_spv = passes.run(&spv, &mut corrections).unwrap();
+    try_spv_to_wgsl(&out_spv, DO_ALL);
+    assert_eq!(corrections, expected.corrections);
+
+    // - Names are pruned only at the end, and ids handed out by earlier passes are never reused
+    let module = Module::parse(&out_spv).unwrap();
+    let expected_module = Module::parse(&expected.spv).unwrap();
+    assert!(
+        module
+            .instructions
+            .iter()
+            .all(|instruction| instruction.opcode != SPV_INSTRUCTION_OP_NAME)
+    );
+    let tag_id = module
+        .instructions
+        .iter()
+        .find(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_STRING)
+        .unwrap()
+        .id();
+    assert_eq!(tag_id, expected_module.instruction_bound());
+    assert_eq!(module.instruction_bound(), tag_id + 1);
+
+    // - A failing pass leaves the corrections alone
+    struct Fail;
+    impl Pass for Fail {
+        fn name(&self) -> &str {
+            "fail"
+        }
+
+        fn run(
+            &mut self,
+            _: &mut Module,
+            _: &mut u32,
+            _: &mut Option<CorrectionMap>,
+        ) -> Result<(), TransformError> {
+            Err(TransformError::BindingWithoutDescriptorSet { id: 0, binding: 0 })
+        }
+    }
+    let mut corrections = None;
+    let result = PassManager::new()
+        .add(SplitCombinedPass::<ShiftBindings>::default())
+        .add(Fail)
+        .run(&spv, &mut corrections);
+    assert!(result.is_err());
+    assert!(corrections.is_none());
+}
+
+fn pipeline_stages(vert: &[u8], frag: &[u8]) -> Vec<PipelineStage> {
+    vec![
+        PipelineStage {
+            stage: ShaderStages::VERTEX,
+            spv: u8_slice_to_u32_vec(vert).unwrap(),
+            corrections: None,
+        },
+        PipelineStage {
+            stage: ShaderStages::FRAGMENT,
+            spv: u8_slice_to_u32_vec(frag).unwrap(),
+            corrections: None,
+        },
+    ]
+}
+
+// Where the variable called `name` is bound
+fn named_location(spv: &[u32], name: &str) -> DescriptorBinding {
+    let module = Module::parse(spv).unwrap();
+    let id = module
+        .instructions
+        .iter()
+        .find(|instruction| {
+            instruction.opcode == SPV_INSTRUCTION_OP_NAME
+                && u32_slice_to_u8_vec(&instruction.operands[1..]).starts_with(name.as_bytes())
+        })
+        .unwrap()
+        .operands[0];
+    let decoration = |decoration| {
+        module
+            .instructions
+            .iter()
+            .find(|instruction| {
+                instruction.opcode == SPV_INSTRUCTION_OP_DECORATE
+                    && instruction.operands[..2] == [id, decoration]
+            })
+            .unwrap()
+            .operands[2]
+    };
+    location(
+        decoration(SPV_DECORATION_DESCRIPTOR_SET),
+        decoration(SPV_DECORATION_BINDING),
+    )
+}
+
+#[test]
+fn pipeline_test_shared_bindings() {
+    let vert = include_bytes!("./test/pipeline/test_vert.spv");
+    let frag = include_bytes!("./test/pipeline/test_frag.spv");
+
+    // - On their own, each stage moves u_transform somewhere else
+    let vert_spv = combimgsampsplitter(&u8_slice_to_u32_vec(vert).unwrap(), &mut None).unwrap();
+    let frag_spv = combimgsampsplitter(&u8_slice_to_u32_vec(frag).unwrap(), &mut None).unwrap();
+    assert_ne!(
+        named_location(&vert_spv, "u_transform"),
+        named_location(&frag_spv, "u_transform")
+    );
+
+    let mut stages = pipeline_stages(vert, frag);
+    let corrections = pipeline_transform(
+        &mut stages,
+        &[combimgsampsplitter_with_allocator],
+        0,
+        &mut ShiftBindings,
+    )
+    .unwrap();
+    for stage in stages.iter() {
+        try_spv_to_wgsl(&stage.spv, DO_ALL);
+        assert_locations_decorated(&stage.spv, stage.corrections.as_ref().unwrap());
+        assert_eq!(named_location(&stage.spv, "u_transform"), location(0, 4));
+    }
+    assert_eq!(
+        named_location(&stages[0].spv, "u_heightmap"),
+        location(0, 0)
+    );
+    assert_eq!(named_location(&stages[1].spv, "u_albedo"), location(0, 2));
+
+    let variables = corrections
+        .resources
+        .iter()
+        .map(|resource| {
+            resource
+                .variables
+                .iter()
+                .map(|variable| (variable.kind, variable.location, variable.visibility))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        variables,
+        vec![
+            vec![
+                (ResourceKind::Texture, location(0, 0), ShaderStages::VERTEX),
+                (ResourceKind::Sampler, location(0, 1), ShaderStages::VERTEX),
+            ],
+            vec![
+                (
+                    ResourceKind::Texture,
+                    location(0, 2),
+                    ShaderStages::FRAGMENT
+                ),
+                (
+                    ResourceKind::Sampler,
+                    location(0, 3),
+                    ShaderStages::FRAGMENT
+                ),
+            ],
+            vec![(
+                ResourceKind::Other,
+                location(0, 4),
+                ShaderStages::VERTEX | ShaderStages::FRAGMENT
+            )],
+        ]
+    );
+    assert_eq!(
+        corrections.sets[&0].bindings[&1].locations,
+        vec![location(0, 2), location(0, 3)]
+    );
+}
+
+#[test]
+fn pipeline_test_stage_without_images() {
+    let mut stages = pipeline_stages(
+        include_bytes!("./test/pipeline/test_vert_uniform_only.spv"),
+        include_bytes!("./test/pipeline/test_frag.spv"),
+    );
+    let corrections = pipeline_transform(
+        &mut stages,
+        &[combimgsampsplitter_with_allocator],
+        0,
+        &mut ShiftBindings,
+    )
+    .unwrap();
+
+    // - The vertex stage has nothing to split, but u_transform still moves
+    for stage in stages.iter() {
+        try_spv_to_wgsl(&stage.spv, DO_ALL);
+        assert_eq!(named_location(&stage.spv, "u_transform"), location(0, 3));
+    }
+    let transform = corrections.resources.last().unwrap();
+    assert_eq!(transform.original, location(0, 2));
+    assert_eq!(
+        transform.variables[0].visibility,
+        ShaderStages::VERTEX | ShaderStages::FRAGMENT
+    );
+}
+
+#[test]
+fn pipeline_test_both_splits() {
+    let mut stages = pipeline_stages(
+        include_bytes!("./test/pipeline/test_vert.spv"),
+        include_bytes!("./test/pipeline/test_frag_shadow.spv"),
+    );
+    let corrections = pipeline_transform(
+        &mut stages,
+        &[
+            combimgsampsplitter_with_allocator,
+            drefsplitter_with_allocator,
+        ],
+        0,
+        &mut ShiftBindings,
+    )
+    .unwrap();
+    for stage in stages.iter() {
+        try_spv_to_wgsl(&stage.spv, DO_ALL);
+        assert_locations_decorated(&stage.spv, stage.corrections.as_ref().unwrap());
+    }
+    // - Both splits share one plan, the dref split moves u_transform a second time
+    for stage in stages.iter() {
+        assert_eq!(named_location(&stage.spv, "u_transform"), location(0, 6));
+    }
+    let variables = corrections
+        .resources
+        .iter()
+        .map(|resource| {
+            resource
+                .variables
+                .iter()
+                .map(|variable| (variable.kind, variable.location, variable.correction))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        variables,
+        vec![
+            vec![
+                (ResourceKind::Texture, location(0, 0), None),
+                (
+                    ResourceKind::Sampler,
+                    location(0, 1),
+                    Some(CorrectionType::SplitCombined)
+                ),
+            ],
+            vec![
+                (ResourceKind::Texture, location(0, 2), None),
+                (
+                    ResourceKind::Texture,
+                    location(0, 3),
+                    Some(CorrectionType::SplitDrefRegular)
+                ),
+                (
+                    ResourceKind::Sampler,
+                    location(0, 4),
+                    Some(CorrectionType::SplitCombined)
+                ),
+                (
+                    ResourceKind::Sampler,
+                    location(0, 5),
+                    Some(CorrectionType::SplitDrefRegular)
+                ),
+            ],
+            vec![(ResourceKind::Other, location(0, 6), None)],
+        ]
+    );
+}
+
+#[test]
+fn pipeline_test_default_descriptor_set() {
+    let mut stages = pipeline_stages(
+        include_bytes!("./test/pipeline/test_vert_uniform_only.spv"),
+        include_bytes!("./test/pipeline/test_frag_shadow.spv"),
+    );
+    let corrections = pipeline_transform(
+        &mut stages,
+        &[
+            combimgsampsplitter_with_allocator,
+            drefsplitter_with_allocator,
+        ],
+        1,
+        &mut ShiftBindings,
+    )
+    .unwrap();
+    for stage in stages.iter() {
+        try_spv_to_wgsl(&stage.spv, DO_ALL);
+        assert_locations_decorated(&stage.spv, stage.corrections.as_ref().unwrap());
+    }
+
+    // - Only the fragment stage lacks DescriptorSet decorations, u_transform is no longer shared
+    assert_eq!(
+        named_location(&stages[0].spv, "u_transform"),
+        location(0, 2)
+    );
+    let shadow = corrections
+        .resources
+        .iter()
+        .find(|resource| resource.original == location(1, 1))
+        .unwrap();
+    assert_eq!(shadow.variables.len(), 4);
+    assert!(
+        shadow
+            .variables
+            .iter()
+            .all(|variable| variable.location.descriptor_set == 1)
+    );
+    assert_eq!(
+        named_location(&stages[1].spv, "u_transform").descriptor_set,
+        1
+    );
+}
+
+#[test]
+fn embedded_corrections_round_trip() {
+    let spv = u8_slice_to_u32_vec(include_bytes!("./test/splitdref/test_mixed_dref.spv")).unwrap();
+    assert!(read_embedded_corrections(&spv).is_none());
+
+    let mut corrections = None;
+    let out_spv = combimgsampsplitter(&spv, &mut corrections).unwrap();
+    let out_spv = drefsplitter(&out_spv, &mut corrections).unwrap();
+    let corrections = corrections.unwrap();
+
+    let embedded_spv = embed_corrections(&out_spv, &corrections).unwrap();
+    try_spv_to_wgsl(&embedded_spv, DO_ALL);
+    assert_eq!(read_embedded_corrections(&embedded_spv), Some(corrections));
+
+    // - Embedding again replaces the old map instead of adding another
+    let empty = CorrectionMap::default();
+    let reembedded_spv = embed_corrections(&embedded_spv, &empty).unwrap();
+    assert!(reembedded_spv.len() < embedded_spv.len());
+    assert_eq!(read_embedded_corrections(&reembedded_spv), Some(empty));
+
+    // - Splitters leave the map alone
+    let mut corrections = None;
+    let resplit_spv = combimgsampsplitter(&reembedded_spv, &mut corrections).unwrap();
+    assert!(read_embedded_corrections(&resplit_spv).is_some());
+}
+
+#[test]
+fn embedded_corrections_large_map() {
+    let spv = u8_slice_to_u32_vec(include_bytes!("./test/splitcombined/test.spv")).unwrap();
+    let mut corrections = None;
+    let out_spv = combimgsampsplitter(&spv, &mut corrections).unwrap();
+    let mut corrections = corrections.unwrap();
+
+    // - Far more than fits into a single instruction
+    corrections.resources = corrections
+        .resources
+        .iter()
+        .cycle()
+        .take(20_000)
+        .cloned()
+        .collect();
+    let embedded_spv = embed_corrections(&out_spv, &corrections).unwrap();
+    try_spv_to_wgsl(&embedded_spv, DO_ALL);
+    assert_eq!(
+        read_embedded_corrections(&embedded_spv).as_ref(),
+        Some(&corrections)
+    );
+    let strings = |spv: &[u32]| {
+        Module::parse(spv)
+            .unwrap()
+            .instructions
+            .iter()
+            .filter(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_STRING)
+            .count()
+    };
+    assert!(strings(&embedded_spv) > 1);
+
+    // - A smaller map replaces every part of the old one
+    let empty = CorrectionMap::default();
+    let reembedded_spv = embed_corrections(&embedded_spv, &empty).unwrap();
+    assert_eq!(strings(&reembedded_spv), 1);
+    assert_eq!(read_embedded_corrections(&reembedded_spv), Some(empty));
+
+    // - A missing part is not a map
+    let mut module = Module::parse(&embedded_spv).unwrap();
+    let part_idx = module
+        .instructions
+        .iter()
+        .position(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_STRING)
+        .unwrap();
+    module.instructions.remove(part_idx);
+    assert!(read_embedded_corrections(&module.assemble().unwrap()).is_none());
+}
+
+#[test]
+fn embedded_corrections_parts() {
+    let spv = u8_slice_to_u32_vec(include_bytes!("./test/splitcombined/test.spv")).unwrap();
+    let mut corrections = None;
+    let out_spv = combimgsampsplitter(&spv, &mut corrections).unwrap();
+    let mut corrections = corrections.unwrap();
+    corrections.resources = corrections
+        .resources
+        .iter()
+        .cycle()
+        .take(20_000)
+        .cloned()
+        .collect();
+    let embedded_spv = embed_corrections(&out_spv, &corrections).unwrap();
+
+    // - Rewrite the parts of the embedded map by hand
+    let with_parts = |rewrite: &dyn Fn(Vec<String>) -> Vec<String>| {
+        let mut module = Module::parse(&embedded_spv).unwrap();
+        let part_idxs = module
+            .instructions
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| instruction.opcode == SPV_INSTRUCTION_OP_STRING)
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        let parts = part_idxs
+            .iter()
+            .map(|&idx| decode_string(&module.instructions[idx].operands).unwrap())
+            .collect::<Vec<_>>();
+        for (&idx, part) in part_idxs.iter().zip(rewrite(parts)) {
+            let mut bytes = part.into_bytes();
+            bytes.push(0);
+            bytes.resize(bytes.len().div_ceil(4) * 4, 0);
+            module.instructions[idx].operands = u8_slice_to_u32_vec(&bytes).unwrap();
+        }
+        read_embedded_corrections(&module.assemble().unwrap())
+    };
+
+    // - Every part carries the format version, its index and the number of parts
+    let unchanged = with_parts(&|parts| {
+        let part_count = parts.len();
+        assert!(part_count > 1);
+        for (idx, part) in parts.iter().enumerate() {
+            let prefix = format!("spirv-webgpu-transform corrections 1 {idx} {part_count} ");
+            assert!(part.starts_with(&prefix));
+        }
+        parts
+    });
+    assert_eq!(unchanged.as_ref(), Some(&corrections));
+
+    // - Parts are put back together by their index, not their order in the module
+    let reversed = with_parts(&|mut parts| {
+        parts.reverse();
+        parts
+    });
+    assert_eq!(reversed.as_ref(), Some(&corrections));
+
+    // - A part of another format version is not a map
+    let other_version = with_parts(&|mut parts| {
+        let last = parts.pop().unwrap();
+        parts.push(last.replacen(" corrections 1 ", " corrections 2 ", 1));
+        parts
+    });
+    assert!(other_version.is_none());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn correction_map_json_round_trip() {
+    let spv = u8_slice_to_u32_vec(include_bytes!("./test/splitdref/test_mixed_dref.spv")).unwrap();
+    let mut corrections = None;
+    let out_spv = combimgsampsplitter(&spv, &mut corrections).unwrap();
+    drefsplitter(&out_spv, &mut corrections).unwrap();
+    let corrections = corrections.unwrap();
+
+    let json = serde_json::to_string(&corrections).unwrap();
+    let loaded = serde_json::from_str::<CorrectionMap>(&json).unwrap();
+    assert_eq!(loaded, corrections);
+
+    // - Hash map order must not leak into the output
+    let mut reordered = CorrectionMap {
+        resources: corrections.resources.clone(),
+        entry_points: corrections.entry_points.clone(),
+        ..Default::default()
+    };
+    let mut sets = corrections.sets.clone().into_iter().collect::<Vec<_>>();
+    sets.sort_by_key(|&(set, _)| std::cmp::Reverse(set));
+    for (set_num, set) in sets {
+        let mut bindings = set.bindings.into_iter().collect::<Vec<_>>();
+        bindings.sort_by_key(|&(binding, _)| std::cmp::Reverse(binding));
+        reordered.sets.insert(
+            set_num,
+            super::CorrectionSet {
+                bindings: bindings.into_iter().collect(),
+            },
+        );
+    }
+    assert_eq!(serde_json::to_string(&reordered).unwrap(), json);
+    assert!(json.contains(r#""corrections":["split_dref_regular"]"#));
+    assert!(json.contains(r#""sample_type":"depth""#));
+    assert!(json.contains(r#""name":"main","execution_model":"fragment""#));
+
+    // - Execution models are named like WebGPU stages, others keep their SPIR-V number
+    for (execution_model, name) in [
+        (ExecutionModel::Vertex, r#""vertex""#),
+        (ExecutionModel::Fragment, r#""fragment""#),
+        (ExecutionModel::GLCompute, r#""compute""#),
+        (ExecutionModel::Other(5), r#"{"other":5}"#),
+    ] {
+        assert_eq!(serde_json::to_string(&execution_model).unwrap(), name);
+        assert_eq!(
+            serde_json::from_str::<ExecutionModel>(name).unwrap(),
+            execution_model
+        );
+    }
+
+    // - Maps written before resources and entry points were recorded still load
+    let loaded = serde_json::from_str::<CorrectionMap>(r#"{"sets":{}}"#).unwrap();
+    assert_eq!(loaded, CorrectionMap::default());
+}
+
+const CORRUPTION_SPVS: &[&[u8]] = &[
+    include_bytes!("./test/splitcombined/test.spv"),
+    include_bytes!("./test/splitcombined/test_mixed.spv"),
+    include_bytes!("./test/splitcombined/test_array_nested.spv"),
+    include_bytes!("./test/splitdref/test_nested2_image.spv"),
+    include_bytes!("./test/splitdref/test_nested2_sampler.spv"),
+    include_bytes!("./test/splitdref/test_hidden3_dref.spv"),
+    include_bytes!("./test/splitcombined/test_forward.spv"),
+    include_bytes!("./test/splitdref/test_forward_dref.spv"),
+    include_bytes!("./test/splitdref/test_unmixed_helper.spv"),
+    include_bytes!("./test/splitcombined/test_value_parameter.spv"),
+    include_bytes!("./test/splitcombined/test_value_parameter_blocks.spv"),
+    include_bytes!("./test/splitcombined/test_forward_blocks.spv"),
+    include_bytes!("./test/splitcombined/test_fetch_only.spv"),
+    include_bytes!("./test/splitcombined/test_fetch_only_array.spv"),
+    include_bytes!("./test/webgpu/test_combined_dref.spv"),
+    include_bytes!("./test/splitdref/test_spv14_dref.spv"),
+    include_bytes!("./test/splitcombined/test_dead_function.spv"),
+    include_bytes!("./test/splitcombined/test_linkage_export.spv"),
+    include_bytes!("./test/splitcombined/test_gl_binding.spv"),
+    include_bytes!("./test/splitcombined/test_decoration_group.spv"),
+    include_bytes!("./test/splitdref/test_dead_dref.spv"),
+];
+
+fn try_all_splitters(spv: &[u32]) {
+    // Errors are fine, we only care that nothing panics.
+    let _ = combimgsampsplitter(spv, &mut None);
+    let _ = drefsplitter(spv, &mut None);
+    let _ = read_embedded_corrections(spv);
+    let _ = webgpu_transform(spv, &Options::default());
+}
+
+#[test]
+fn literal_operands_match_grammar() {
+    // - Operand kinds after the result id, from the unified SPIR-V grammar, true for literals
+    for (opcode, name, literals) in [
+        // Execution, Value, LocalId
+        (263, "OpGroupBroadcast", [false, false, false]),
+        // Execution, Operation, X
+        (264, "OpGroupIAdd", [false, true, false]),
+        (271, "OpGroupSMax", [false, true, false]),
+        (342, "OpGroupNonUniformBallotBitCount", [false, true, false]),
+        (349, "OpGroupNonUniformIAdd", [false, true, false]),
+        (362, "OpGroupNonUniformLogicalXor", [false, true, false]),
+    ] {
+        for (operand_idx, &literal) in literals.iter().enumerate() {
+            assert_eq!(
+                spv_is_literal_operand(opcode, operand_idx),
+                literal,
+                "{} operand {}",
+                name,
+                operand_idx
+            );
+        }
+    }
+}
+
+#[test]
+fn deep_call_chain() {
+    use spirv_tools::assembler::{self, Assembler};
+
+    // - Valid, but far deeper than the native stack would allow with one frame per call
+    const DEPTH: usize = 200_000;
+    let mut text = String::from(
+        "OpCapability Shader
+        OpMemoryModel Logical GLSL450
+        OpEntryPoint GLCompute %f0 \"main\"
+        OpExecutionMode %f0 LocalSize 1 1 1
+        %void = OpTypeVoid
+        %void_fn = OpTypeFunction %void
+        %sampler = OpTypeSampler
+        ",
+    );
+    for function in 0..DEPTH {
+        text += &format!(
+            "%f{} = OpFunction %void None %void_fn\n%l{} = OpLabel\n",
+            function, function
+        );
+        if function + 1 < DEPTH {
+            text += &format!("%c{} = OpFunctionCall %void %f{}\n", function, function + 1);
+        }
+        text += "OpReturn\nOpFunctionEnd\n";
+    }
+    let spv = assembler::create(None)
+        .assemble(&text, assembler::AssemblerOptions::default())
+        .unwrap();
+
+    let mut corrections = None;
+    let out_spv = drefsplitter(spv.as_words(), &mut corrections).unwrap();
+    assert_eq!(out_spv, spv.as_words());
+    combimgsampsplitter(spv.as_words(), &mut None).unwrap();
+}
+
+#[test]
+fn corrupt_input_does_not_panic() {
+    // xorshift32, so failures are reproducible without pulling in a rng crate
+    let mut state = 0x2545f491u32;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+
+    for spv in CORRUPTION_SPVS {
+        let spv = u8_slice_to_u32_vec(spv).unwrap();
+
+        for len in 0..spv.len() {
+            try_all_splitters(&spv[..len]);
+        }
+
+        for _ in 0..500 {
+            let mut corrupt_spv = spv.clone();
+            for _ in 0..1 + next() % 4 {
+                let idx = next() as usize % corrupt_spv.len();
+                corrupt_spv[idx] = match next() % 4 {
+                    0 => next(),
+                    1 => corrupt_spv[idx] ^ (1 << (next() % 32)),
+                    2 => next() % 64,
+                    _ => corrupt_spv[idx].wrapping_add(1),
+                };
+            }
+            try_all_splitters(&corrupt_spv);
+        }
+    }
+}
+
+#[test]
+fn module_round_trip() {
+    for spv in CORRUPTION_SPVS {
+        let spv = u8_slice_to_u32_vec(spv).unwrap();
+        let module = Module::parse(&spv).unwrap();
+
+        let definitions = module.definitions();
+        for (id, idx) in definitions.iter() {
+            assert_eq!(module.instructions[*idx].result_id, Some(*id));
+        }
+
+        assert_eq!(module.assemble().unwrap(), spv);
+    }
+}
+
+#[test]
+fn unaligned_bytes() {
+    let spv_bytes = include_bytes!("./test/splitcombined/test.spv");
+    assert_eq!(
+        u8_slice_to_u32_vec(&spv_bytes[..spv_bytes.len() - 1]),
+        Err(TransformError::UnalignedByteCount {
+            byte_count: spv_bytes.len() - 1
+        })
+    );
+    assert_eq!(
+        u8_slice_to_u32_vec(&[0x03]),
+        Err(TransformError::UnalignedByteCount { byte_count: 1 })
+    );
+
+    let spv = u8_slice_to_u32_vec(spv_bytes).unwrap();
+    assert_eq!(u32_slice_to_u8_vec(&spv), spv_bytes);
+}
@@ -4,6 +4,22 @@ glslc splitcombined/test.frag -o splitcombined/test.spv
 glslc splitcombined/test_arrayed.frag -o splitcombined/test_arrayed.spv
 glslc splitcombined/test_nested.frag -o splitcombined/test_nested.spv
 glslc splitcombined/test_mixed.frag -o splitcombined/test_mixed.spv
+spirv-as splitcombined/test_array.spvasm -o splitcombined/test_array.spv
+spirv-as splitcombined/test_array_nested.spvasm -o splitcombined/test_array_nested.spv
+spirv-as splitcombined/test_runtime_array.spvasm -o splitcombined/test_runtime_array.spv
+spirv-as splitcombined/test_image_before_void.spvasm -o splitcombined/test_image_before_void.spv
+spirv-as splitcombined/test_no_void.spvasm -o splitcombined/test_no_void.spv
+spirv-as splitcombined/test_forward.spvasm -o splitcombined/test_forward.spv
+spirv-as splitcombined/test_value_parameter.spvasm -o splitcombined/test_value_parameter.spv
+spirv-as splitcombined/test_value_parameter_blocks.spvasm -o splitcombined/test_value_parameter_blocks.spv
+spirv-as splitcombined/test_forward_blocks.spvasm -o splitcombined/test_forward_blocks.spv
+spirv-as splitcombined/test_fetch_only.spvasm -o splitcombined/test_fetch_only.spv
+spirv-as splitcombined/test_fetch_only_array.spvasm -o splitcombined/test_fetch_only_array.spv
+spirv-as splitcombined/test_dead_function.spvasm -o splitcombined/test_dead_function.spv
+spirv-as splitcombined/test_linkage_export.spvasm -o splitcombined/test_linkage_export.spv
+spirv-as splitcombined/test_gl_binding.spvasm -o splitcombined/test_gl_binding.spv
+spirv-as splitcombined/test_decoration_group.spvasm -o splitcombined/test_decoration_group.spv
+spirv-as --target-env spv1.4 splitcombined/test_spv14.spvasm -o splitcombined/test_spv14.spv
 
 glslc splitdref/test_image.frag -o splitdref/test_image.spv
 glslc splitdref/test_nested_image.frag -o splitdref/test_nested_image.spv
@@ -16,3 +32,16 @@ glslc splitdref/test_hidden_dref.frag -o splitdref/test_hidden_dref.spv
 glslc splitdref/test_hidden2_dref.frag -o splitdref/test_hidden2_dref.spv
 glslc splitdref/test_hidden3_dref.frag -o splitdref/test_hidden3_dref.spv
 spirv-as splitdref/test_wrong_type_image.spvasm -o splitdref/test_wrong_type_image.spv
+spirv-as splitdref/test_specialize_dref.spvasm -o splitdref/test_specialize_dref.spv
+spirv-as splitdref/test_shared_load.spvasm -o splitdref/test_shared_load.spv
+spirv-as splitdref/test_forward_dref.spvasm -o splitdref/test_forward_dref.spv
+spirv-as splitdref/test_unmixed_helper.spvasm -o splitdref/test_unmixed_helper.spv
+spirv-as splitdref/test_dead_dref.spvasm -o splitdref/test_dead_dref.spv
+spirv-as --target-env spv1.4 splitdref/test_spv14_dref.spvasm -o splitdref/test_spv14_dref.spv
+
+spirv-as pipeline/test_vert.spvasm -o pipeline/test_vert.spv
+spirv-as pipeline/test_vert_uniform_only.spvasm -o pipeline/test_vert_uniform_only.spv
+spirv-as pipeline/test_frag.spvasm -o pipeline/test_frag.spv
+spirv-as pipeline/test_frag_shadow.spvasm -o pipeline/test_frag_shadow.spv
+
+spirv-as webgpu/test_combined_dref.spvasm -o webgpu/test_combined_dref.spv
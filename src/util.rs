@@ -1,12 +1,24 @@
 use super::*;
 
+mod call_graph;
 mod correct_decorate;
 mod decorate;
 mod function;
+mod interface;
+mod layout;
+mod resource;
+mod trace;
+mod usage;
 
+pub use call_graph::*;
 pub use correct_decorate::*;
 pub use decorate::*;
 pub use function::*;
+pub use interface::*;
+pub use layout::*;
+pub use resource::*;
+pub use trace::*;
+pub use usage::*;
 
 pub fn hiword(value: u32) -> u16 {
     ((value >> 16) & 0xFFFF) as u16
@@ -20,72 +32,80 @@ pub const fn encode_word(hiword: u16, loword: u16) -> u32 {
     ((hiword as u32) << 16) | (loword as u32)
 }
 
+// A nul terminated literal string
+pub fn decode_string(words: &[u32]) -> Option<String> {
+    let bytes = u32_slice_to_u8_vec(words);
+    let nul_idx = bytes.iter().position(|&byte| byte == 0)?;
+    String::from_utf8(bytes[..nul_idx].to_vec()).ok()
+}
+
+// Build the new instruction stream in one forward merge, so that rewriting stays linear in the
+// size of the module: inserts are sorted once, then every instruction is moved over with its new
+// operands, followed by its new instructions. Whited out instructions are dropped on the way.
 pub fn insert_new_instructions(
-    spv: &[u32],
-    new_spv: &mut Vec<u32>,
-    word_inserts: &[WordInsert],
+    new_module: &mut Module,
+    operand_inserts: &[OperandInsert],
     instruction_inserts: &[InstructionInsert],
 ) {
-    // 10. Insert New Instructions
-    enum Insert {
-        Word(WordInsert),
-        Instruction(InstructionInsert),
-    }
-    let mut inserts = word_inserts
+    // - Inserts sharing an index keep the order they were pushed in
+    let mut operand_inserts = operand_inserts.iter().collect::<Vec<_>>();
+    operand_inserts.sort_by_key(|insert| (insert.idx, insert.operand_idx));
+    let mut operand_inserts = operand_inserts.into_iter().peekable();
+
+    let mut instruction_inserts = instruction_inserts.iter().collect::<Vec<_>>();
+    instruction_inserts.sort_by_key(|insert| insert.previous_idx);
+    let inserted_count = instruction_inserts
         .iter()
-        .cloned()
-        .map(Insert::Word)
-        .chain(instruction_inserts.iter().cloned().map(Insert::Instruction))
-        .collect::<Vec<_>>();
+        .map(|insert| insert.instructions.len())
+        .sum::<usize>();
+    let mut instruction_inserts = instruction_inserts.into_iter().peekable();
 
-    inserts.sort_by_key(|instruction| match instruction {
-        Insert::Word(insert) => insert.idx,
-        Insert::Instruction(insert) => insert.previous_spv_idx,
-    });
-    inserts.iter().rev().for_each(|insert| match insert {
-        Insert::Word(new_word) => {
-            new_spv.insert(new_word.idx + 1, new_word.word);
-            new_spv[new_word.head_idx] = encode_word(
-                hiword(new_spv[new_word.head_idx]) + 1,
-                loword(new_spv[new_word.head_idx]),
-            );
-        }
-        Insert::Instruction(new_instruction) => {
-            let offset = hiword(spv[new_instruction.previous_spv_idx]);
-            for idx in 0..new_instruction.instruction.len() {
-                new_spv.insert(
-                    new_instruction.previous_spv_idx + offset as usize + idx,
-                    new_instruction.instruction[idx],
-                )
+    let instructions = std::mem::take(&mut new_module.instructions);
+    new_module
+        .instructions
+        .reserve(instructions.len() + inserted_count);
+    for (idx, mut instruction) in instructions.into_iter().enumerate() {
+        // - New operands go right after the operand at `operand_idx`
+        if operand_inserts
+            .peek()
+            .is_some_and(|insert| insert.idx == idx)
+        {
+            let operands = std::mem::take(&mut instruction.operands);
+            for (operand_idx, operand) in operands.into_iter().enumerate() {
+                instruction.operands.push(operand);
+                while let Some(insert) = operand_inserts
+                    .next_if(|insert| insert.idx == idx && insert.operand_idx == operand_idx)
+                {
+                    instruction.operands.push(insert.operand);
+                }
+            }
+            // - Past the last operand
+            while let Some(insert) = operand_inserts.next_if(|insert| insert.idx == idx) {
+                instruction.operands.push(insert.operand);
             }
         }
-    });
-}
-
-pub fn prune_noops(new_spv: &mut Vec<u32>) {
-    let mut i_idx = 0;
-    while i_idx < new_spv.len() {
-        let op = new_spv[i_idx];
-        let word_count = hiword(op);
-        let instruction = loword(op);
 
-        if instruction == SPV_INSTRUCTION_OP_NOP {
-            for _ in 0..word_count {
-                new_spv.remove(i_idx);
-            }
-        } else {
-            i_idx += word_count as usize;
+        if instruction.opcode != SPV_INSTRUCTION_OP_NOP {
+            new_module.instructions.push(instruction);
+        }
+        while let Some(insert) = instruction_inserts.next_if(|insert| insert.previous_idx == idx) {
+            new_module
+                .instructions
+                .extend(insert.instructions.iter().cloned());
         }
     }
 }
 
+pub fn prune_noops(new_module: &mut Module) {
+    new_module
+        .instructions
+        .retain(|instruction| instruction.opcode != SPV_INSTRUCTION_OP_NOP);
+}
+
 pub fn fuse_final(
-    mut spv_header: Vec<u32>,
-    mut new_spv: Vec<u32>,
+    mut new_module: Module,
     new_instruction_bound: u32,
-) -> Vec<u32> {
-    spv_header[SPV_HEADER_INSTRUCTION_BOUND_OFFSET] = new_instruction_bound;
-    let mut out_spv = spv_header;
-    out_spv.append(&mut new_spv);
-    out_spv
+) -> Result<Vec<u32>, TransformError> {
+    new_module.set_instruction_bound(new_instruction_bound);
+    new_module.assemble()
 }
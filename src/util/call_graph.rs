@@ -0,0 +1,224 @@
+use super::*;
+
+// An OpEntryPoint, and what its static call tree reaches
+#[derive(Debug, Clone)]
+pub struct EntryPointInfo {
+    pub name: String,
+    pub execution_model: ExecutionModel,
+    // Indices into `CallGraph::functions`, the entry point's own function first
+    pub reachable: Vec<usize>,
+    // Global variables referenced by any reachable function
+    pub variable_ids: HashSet<u32>,
+}
+
+// Caller and callee edges between the functions of a module, built once.
+pub struct CallGraph {
+    pub functions: Vec<FunctionInfo>,
+    // OpFunction id -> index into `functions`
+    pub function_ids: HashMap<u32, usize>,
+    // The OpFunctionCall index and callee of every call each function makes
+    pub calls: Vec<Vec<(usize, usize)>>,
+    pub entry_points: Vec<EntryPointInfo>,
+    live: Vec<bool>,
+}
+
+impl CallGraph {
+    pub fn new(module: &Module) -> Result<Self, TransformError> {
+        let functions = find_functions(module)?;
+        let function_ids = functions
+            .iter()
+            .enumerate()
+            .map(|(function, info)| (module.instructions[info.function_idx].id(), function))
+            .collect::<HashMap<_, _>>();
+
+        // - Calls to functions that are only declared lead nowhere
+        let mut calls = vec![vec![]; functions.len()];
+        for (function, info) in functions.iter().enumerate() {
+            for &call_idx in info.function_call_idxs.iter() {
+                let callee_id = module.instructions[call_idx].operands[0];
+                let Some(&callee) = function_ids.get(&callee_id) else {
+                    continue;
+                };
+                calls[function].push((call_idx, callee));
+            }
+        }
+
+        let global_variable_ids = module
+            .instructions
+            .iter()
+            .take_while(|instruction| instruction.opcode != SPV_INSTRUCTION_OP_FUNCTION)
+            .filter(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_VARIABLE)
+            .map(|instruction| instruction.id())
+            .collect::<HashSet<_>>();
+        let function_variable_ids = functions
+            .iter()
+            .map(|info| {
+                module.instructions[info.function_idx..=info.function_end_idx]
+                    .iter()
+                    .flat_map(|instruction| {
+                        instruction
+                            .operands
+                            .iter()
+                            .enumerate()
+                            .filter(|&(operand_idx, _)| {
+                                !spv_is_literal_operand(instruction.opcode, operand_idx)
+                            })
+                            .map(|(_, operand)| operand)
+                    })
+                    .filter(|operand| global_variable_ids.contains(operand))
+                    .copied()
+                    .collect::<HashSet<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut entry_points = vec![];
+        for instruction in module.instructions.iter() {
+            if instruction.opcode != SPV_INSTRUCTION_OP_ENTRY_POINT {
+                continue;
+            }
+            let (Some(&execution_model), Some(function)) = (
+                instruction.operands.first(),
+                instruction
+                    .operands
+                    .get(1)
+                    .and_then(|function_id| function_ids.get(function_id)),
+            ) else {
+                continue;
+            };
+
+            // - Recursion is not allowed in SPIR-V, but we still should not loop forever on it
+            let mut reachable = vec![];
+            let mut visited = vec![false; functions.len()];
+            let mut stack = vec![*function];
+            while let Some(function) = stack.pop() {
+                if std::mem::replace(&mut visited[function], true) {
+                    continue;
+                }
+                reachable.push(function);
+                stack.extend(calls[function].iter().rev().map(|&(_, callee)| callee));
+            }
+
+            let variable_ids = reachable
+                .iter()
+                .flat_map(|&function| function_variable_ids[function].iter().copied())
+                .collect();
+            entry_points.push(EntryPointInfo {
+                name: decode_string(instruction.operands.get(2..).unwrap_or_default())
+                    .unwrap_or_default(),
+                execution_model: ExecutionModel::from_spirv(execution_model),
+                reachable,
+                variable_ids,
+            });
+        }
+
+        // - Modules without entry points, such as libraries, keep every function
+        let mut live = vec![entry_points.is_empty(); functions.len()];
+        for entry_point in entry_points.iter() {
+            for &function in entry_point.reachable.iter() {
+                live[function] = true;
+            }
+        }
+
+        // - Functions exported through LinkageAttributes are called from other modules
+        let mut stack = module
+            .instructions
+            .iter()
+            .filter(|instruction| {
+                instruction.opcode == SPV_INSTRUCTION_OP_DECORATE
+                    && instruction.operands.get(1) == Some(&SPV_DECORATION_LINKAGE_ATTRIBUTES)
+                    && instruction.operands.last() == Some(&SPV_LINKAGE_TYPE_EXPORT)
+            })
+            .filter_map(|instruction| function_ids.get(&instruction.operands[0]).copied())
+            .collect::<Vec<_>>();
+        while let Some(function) = stack.pop() {
+            if std::mem::replace(&mut live[function], true) {
+                continue;
+            }
+            stack.extend(calls[function].iter().map(|&(_, callee)| callee));
+        }
+
+        Ok(CallGraph {
+            functions,
+            function_ids,
+            calls,
+            entry_points,
+            live,
+        })
+    }
+
+    // Whether any entry point reaches `function`
+    pub fn is_live(&self, function: usize) -> bool {
+        self.live[function]
+    }
+
+    // The function `idx` is part of, if any
+    pub fn function_of(&self, idx: usize) -> Option<usize> {
+        let function = self
+            .functions
+            .partition_point(|info| info.function_idx <= idx)
+            .checked_sub(1)?;
+        (idx <= self.functions[function].function_end_idx).then_some(function)
+    }
+
+    // The stages of the entry points that reference the variable `id`
+    pub fn variable_stages(&self, id: u32) -> ShaderStages {
+        self.entry_points
+            .iter()
+            .filter(|entry_point| entry_point.variable_ids.contains(&id))
+            .map(|entry_point| entry_point.execution_model.stage())
+            .fold(ShaderStages::NONE, |stages, stage| stages | stage)
+    }
+
+    // Order functions so that every caller comes before its callees.
+    // Returns indices into `functions`.
+    pub fn callers_first_order(&self, module: &Module) -> Result<Vec<usize>, TransformError> {
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum Visit {
+            New,
+            InProgress,
+            Done,
+        }
+
+        // - An explicit stack of functions and how many of their calls were followed, call chains
+        //   can be far deeper than the native stack
+        let mut visits = vec![Visit::New; self.functions.len()];
+        let mut order = vec![];
+        for root in 0..self.functions.len() {
+            if visits[root] != Visit::New {
+                continue;
+            }
+            visits[root] = Visit::InProgress;
+            let mut stack = vec![(root, 0)];
+            while let Some((function, next_call)) = stack.last_mut() {
+                let function = *function;
+                let Some(&(call_idx, callee)) = self.calls[function].get(*next_call) else {
+                    visits[function] = Visit::Done;
+                    order.push(function);
+                    stack.pop();
+                    continue;
+                };
+                *next_call += 1;
+                match visits[callee] {
+                    Visit::New => {
+                        visits[callee] = Visit::InProgress;
+                        stack.push((callee, 0));
+                    }
+                    // - Recursion is not allowed in SPIR-V, but we still should not loop forever
+                    //   on it
+                    Visit::InProgress => {
+                        return Err(TransformError::unsupported(
+                            module,
+                            call_idx,
+                            "recursive function calls are not supported",
+                        ));
+                    }
+                    Visit::Done => {}
+                }
+            }
+        }
+
+        // - Post order puts callees first
+        order.reverse();
+        Ok(order)
+    }
+}
@@ -1,66 +1,255 @@
 use super::*;
 
 pub struct CorrectDecorateIn<'a> {
-    pub new_spv: &'a mut [u32],
-    pub descriptor_sets_to_correct: HashSet<u32>,
+    pub new_module: &'a mut Module,
+    pub new_variables: &'a [NewVariable],
+    pub allocator: &'a mut dyn BindingAllocator,
+    pub corrections: &'a mut Option<CorrectionMap>,
 }
 
 // Correct descriptor sets whose binding index has been invalidated.
 // This should be called after instructions have been inserted.
-pub fn correct_decorate(cd_in: CorrectDecorateIn) {
+pub fn correct_decorate(cd_in: CorrectDecorateIn) -> Result<(), TransformError> {
     let CorrectDecorateIn {
-        new_spv,
-        descriptor_sets_to_correct,
+        new_module,
+        new_variables,
+        allocator,
+        corrections,
     } = cd_in;
     let mut candidates = HashMap::new();
 
-    let mut d_idx = 0;
-    while d_idx < new_spv.len() {
-        let op = new_spv[d_idx];
-        let word_count = hiword(op);
-        let instruction = loword(op);
-        if instruction == SPV_INSTRUCTION_OP_DECORATE {
-            match new_spv[d_idx + 2] {
+    for (d_idx, instruction) in new_module.instructions.iter().enumerate() {
+        if instruction.opcode == SPV_INSTRUCTION_OP_DECORATE {
+            match instruction.operands[1] {
                 SPV_DECORATION_DESCRIPTOR_SET => {
                     candidates
-                        .entry(new_spv[d_idx + 1])
+                        .entry(instruction.operands[0])
                         .or_insert((None, None))
-                        .0 = Some(new_spv[d_idx + 3])
+                        .0 = Some((d_idx, instruction.operands[2]))
                 }
                 SPV_DECORATION_BINDING => {
                     candidates
-                        .entry(new_spv[d_idx + 1])
+                        .entry(instruction.operands[0])
                         .or_insert((None, None))
-                        .1 = Some((d_idx, new_spv[d_idx + 3]))
+                        .1 = Some((d_idx, instruction.operands[2]))
                 }
                 _ => {}
             }
         }
+    }
+
+    // - A DescriptorSet alone does not bind anything
+    let mut candidates = candidates
+        .into_iter()
+        .filter_map(|(id, decorations)| match decorations {
+            (Some(descriptor_set), Some(binding)) => Some(Ok((id, (descriptor_set, binding)))),
+            (None, Some((_, binding))) => Some(Err(TransformError::BindingWithoutDescriptorSet {
+                id,
+                binding,
+            })),
+            (_, None) => None,
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    candidates.sort_by_key(|&(id, _)| id);
+
+    // - The decorations of new variables are only placeholders
+    let new_variable_ids = new_variables
+        .iter()
+        .map(|variable| variable.id)
+        .collect::<HashSet<_>>();
+    let variables = candidates
+        .iter()
+        .filter(|(id, _)| !new_variable_ids.contains(id))
+        .map(|&(id, ((_, descriptor_set), (_, binding)))| BoundVariable {
+            id,
+            location: DescriptorBinding {
+                descriptor_set,
+                binding,
+            },
+        })
+        .collect::<Vec<_>>();
+    let mut allocation = allocator.allocate(&variables, new_variables)?;
+    for variable in variables.iter() {
+        allocation.entry(variable.id).or_insert(variable.location);
+    }
+
+    // - Every new variable needs a binding of its own
+    let mut bound_ids = HashMap::new();
+    for variable in variables.iter() {
+        bound_ids.insert(allocation[&variable.id], variable.id);
+    }
+    for variable in new_variables.iter() {
+        let Some(&location) = allocation.get(&variable.id) else {
+            return Err(TransformError::BindingAllocation {
+                id: variable.id,
+                location: variable.original,
+                reason: "the binding allocator did not bind it",
+            });
+        };
+        if bound_ids.insert(location, variable.id).is_some() {
+            return Err(TransformError::BindingAllocation {
+                id: variable.id,
+                location: variable.original,
+                reason: "its binding is already in use",
+            });
+        }
+    }
 
-        d_idx += word_count as usize;
+    for &(id, ((descriptor_set_idx, _), (binding_idx, _))) in candidates.iter() {
+        let location = allocation[&id];
+        new_module.instructions[descriptor_set_idx].operands[2] = location.descriptor_set;
+        new_module.instructions[binding_idx].operands[2] = location.binding;
     }
 
-    for descriptor_set in descriptor_sets_to_correct {
-        let mut bindings = candidates
+    // - Move every binding in our correction map, then stamp it with the new variables right
+    //   after the variable they were split off of
+    if let Some(corrections) = corrections.as_mut() {
+        let moved_locations = variables
             .iter()
-            .filter_map(|(_, &(maybe_descriptor_set, maybe_binding))| {
-                let this_descriptor_set = maybe_descriptor_set.unwrap();
-                let (binding_idx, this_binding) = maybe_binding.unwrap();
-                (this_descriptor_set == descriptor_set).then_some((binding_idx, this_binding))
-            })
-            .collect::<Vec<_>>();
-        bindings.sort_by_cached_key(|&(_, binding)| binding);
+            .map(|variable| (variable.location, allocation[&variable.id]))
+            .collect::<HashMap<_, _>>();
+        for binding in corrections
+            .sets
+            .values_mut()
+            .flat_map(|set| set.bindings.values_mut())
+        {
+            for location in binding.locations.iter_mut() {
+                if let Some(&moved_location) = moved_locations.get(location) {
+                    *location = moved_location;
+                }
+            }
+        }
 
-        // We can assume that our new ~~samplers~~ variables will have a greater instruction ID than the original
-        // ~~combined image samplers~~ variables.
-        let mut prev_binding = -1;
-        let mut increment = 0;
-        for (d_idx, binding) in bindings {
-            if binding as i32 == prev_binding {
-                increment += 1;
+        let mut location_bindings = HashMap::new();
+        for (&set_num, set) in corrections.sets.iter() {
+            for (&binding_num, binding) in set.bindings.iter() {
+                for &location in binding.locations.iter() {
+                    location_bindings
+                        .entry(location)
+                        .or_insert((set_num, binding_num));
+                }
+            }
+        }
+        for variable in new_variables.iter() {
+            let original_location = allocation
+                .get(&variable.original_id)
+                .copied()
+                .unwrap_or(variable.original);
+            let Some((binding, location_idx)) = location_bindings
+                .get(&original_location)
+                .and_then(|(set_num, binding_num)| {
+                    corrections
+                        .sets
+                        .get_mut(set_num)?
+                        .bindings
+                        .get_mut(binding_num)
+                })
+                .and_then(|binding| {
+                    let location_idx = binding
+                        .locations
+                        .iter()
+                        .position(|&location| location == original_location)?;
+                    Some((binding, location_idx))
+                })
+            else {
+                continue;
+            };
+            // - Maps built by hand may not have a location for every correction
+            let insert_idx = location_idx.min(binding.corrections.len());
+            binding
+                .corrections
+                .insert(insert_idx, variable.correction_type);
+            binding
+                .locations
+                .insert(insert_idx + 1, allocation[&variable.id]);
+        }
+
+        // - Same for every resource, the original variable may also hold something else now
+        let definitions = new_module.definitions();
+        let usages = resource_usages(new_module)?;
+        let graph = CallGraph::new(new_module)?;
+        let describe = |variable: &mut CorrectionVariable| {
+            variable.visibility = graph.variable_stages(variable.id);
+            variable.kind = resource_kind(new_module, &definitions, variable.id);
+            variable.texture = texture_binding_info(new_module, &definitions, &usages, variable.id);
+            variable.sampler = sampler_binding_type(new_module, &definitions, &usages, variable.id);
+        };
+        for variable in corrections
+            .resources
+            .iter_mut()
+            .flat_map(|resource| resource.variables.iter_mut())
+        {
+            if let Some(&location) = allocation.get(&variable.id) {
+                variable.location = location;
+                describe(variable);
+            }
+        }
+        let mut variable_resource_idxs = HashMap::new();
+        for (resource_idx, resource) in corrections.resources.iter().enumerate() {
+            for variable in resource.variables.iter() {
+                variable_resource_idxs
+                    .entry(variable.id)
+                    .or_insert(resource_idx);
             }
-            new_spv[d_idx + 3] = binding + increment;
-            prev_binding = binding as i32;
         }
+        for variable in new_variables.iter() {
+            let Some(resource) = variable_resource_idxs
+                .get(&variable.original_id)
+                .map(|&resource_idx| &mut corrections.resources[resource_idx])
+            else {
+                continue;
+            };
+            let Some(variable_idx) = resource
+                .variables
+                .iter()
+                .position(|resource_variable| resource_variable.id == variable.original_id)
+            else {
+                continue;
+            };
+            let mut new_variable = CorrectionVariable {
+                id: variable.id,
+                location: allocation[&variable.id],
+                kind: ResourceKind::Other,
+                texture: None,
+                sampler: None,
+                correction: Some(variable.correction_type),
+                visibility: ShaderStages::NONE,
+            };
+            describe(&mut new_variable);
+            resource.variables.insert(variable_idx + 1, new_variable);
+        }
+
+        // - WebGPU builds every pipeline from a single entry point, so each one gets the part of
+        //   every resource it uses
+        corrections.entry_points = graph
+            .entry_points
+            .iter()
+            .map(|entry_point| CorrectionEntryPoint {
+                name: entry_point.name.clone(),
+                execution_model: entry_point.execution_model,
+                resources: corrections
+                    .resources
+                    .iter()
+                    .filter_map(|resource| {
+                        let variables = resource
+                            .variables
+                            .iter()
+                            .filter(|variable| entry_point.variable_ids.contains(&variable.id))
+                            .map(|&variable| CorrectionVariable {
+                                visibility: entry_point.execution_model.stage(),
+                                ..variable
+                            })
+                            .collect::<Vec<_>>();
+                        (!variables.is_empty()).then_some(CorrectionResource {
+                            original_id: resource.original_id,
+                            original: resource.original,
+                            variables,
+                        })
+                    })
+                    .collect(),
+            })
+            .collect();
     }
+
+    Ok(())
 }
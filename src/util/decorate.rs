@@ -7,10 +7,10 @@ pub struct DecorationVariable {
 }
 
 pub struct DecorateIn<'a> {
-    pub spv: &'a [u32],
+    pub module: &'a Module,
     pub instruction_inserts: &'a mut Vec<InstructionInsert>,
 
-    pub first_op_deocrate_idx: Option<usize>,
+    pub layout: &'a Layout,
     pub op_decorate_idxs: &'a [usize],
 
     pub affected_variables: &'a [DecorationVariable],
@@ -18,191 +18,455 @@ pub struct DecorateIn<'a> {
 }
 
 pub struct DecorateOut {
-    pub descriptor_sets_to_correct: HashSet<u32>,
+    // Sorted by original binding
+    pub new_variables: Vec<NewVariable>,
 }
 
-pub fn decorate(d_in: DecorateIn) -> DecorateOut {
+pub fn decorate(d_in: DecorateIn) -> Result<DecorateOut, TransformError> {
     let DecorateIn {
-        spv,
+        module,
         instruction_inserts,
-        first_op_deocrate_idx,
+        layout,
         op_decorate_idxs,
         affected_variables,
         corrections,
     } = d_in;
 
     let mut new_variable_id_to_decorations = HashMap::new();
-    let mut descriptor_sets_to_correct = HashSet::new();
+
+    let mut original_id_to_affected_variables: HashMap<u32, Vec<&DecorationVariable>> =
+        HashMap::new();
+    for variable in affected_variables.iter() {
+        original_id_to_affected_variables
+            .entry(variable.original_res_id)
+            .or_default()
+            .push(variable);
+    }
 
     // - If corrections is empty, we will need to build a new one using existing set bindings
     let mut all_descriptor_sets = corrections.is_none().then_some(HashMap::new());
 
     // - Find the current binding and descriptor set pair for each combimgsamp
     op_decorate_idxs.iter().for_each(|&d_idx| {
-        affected_variables.iter().for_each(
-            |&DecorationVariable {
-                 original_res_id,
-                 new_res_id,
-                 correction_type,
-             }| {
-                let target_id = spv[d_idx + 1];
-                let decoration_id = spv[d_idx + 2];
-                let decoration_value = spv[d_idx + 3];
-
-                if decoration_id == SPV_DECORATION_BINDING {
-                    if original_res_id == target_id {
-                        new_variable_id_to_decorations
-                            .entry((new_res_id, correction_type))
-                            .or_insert((None, None))
-                            .0 = Some((d_idx, spv[d_idx + 3]));
-                    }
-
-                    if let Some(all_descriptor_sets) = all_descriptor_sets.as_mut() {
-                        all_descriptor_sets
-                            .entry(target_id)
-                            .or_insert((None, None))
-                            .0 = Some(decoration_value);
-                    }
-                } else if decoration_id == SPV_DECORATION_DESCRIPTOR_SET {
-                    if original_res_id == target_id {
-                        new_variable_id_to_decorations
-                            .entry((new_res_id, correction_type))
-                            .or_insert((None, None))
-                            .1 = Some((d_idx, decoration_value));
-                        descriptor_sets_to_correct.insert(decoration_value);
-                    }
-
-                    if let Some(all_descriptor_sets) = all_descriptor_sets.as_mut() {
-                        all_descriptor_sets
-                            .entry(target_id)
-                            .or_insert((None, None))
-                            .1 = Some(decoration_value);
-                    }
-                }
-            },
-        );
+        let decoration = &module.instructions[d_idx];
+        let target_id = decoration.operands[0];
+        let decoration_id = decoration.operands[1];
+        // Only Binding and DescriptorSet are guaranteed to carry a value
+        let decoration_value = decoration.operands.get(2).copied().unwrap_or(0);
+
+        if decoration_id == SPV_DECORATION_BINDING {
+            original_id_to_affected_variables
+                .get(&target_id)
+                .into_iter()
+                .flatten()
+                .for_each(|variable| {
+                    new_variable_id_to_decorations
+                        .entry((
+                            variable.new_res_id,
+                            variable.original_res_id,
+                            variable.correction_type,
+                        ))
+                        .or_insert((None, None))
+                        .0 = Some((d_idx, decoration_value));
+                });
+
+            if let Some(all_descriptor_sets) = all_descriptor_sets.as_mut() {
+                all_descriptor_sets
+                    .entry(target_id)
+                    .or_insert((None, None))
+                    .0 = Some(decoration_value);
+            }
+        } else if decoration_id == SPV_DECORATION_DESCRIPTOR_SET {
+            original_id_to_affected_variables
+                .get(&target_id)
+                .into_iter()
+                .flatten()
+                .for_each(|variable| {
+                    new_variable_id_to_decorations
+                        .entry((
+                            variable.new_res_id,
+                            variable.original_res_id,
+                            variable.correction_type,
+                        ))
+                        .or_insert((None, None))
+                        .1 = Some(decoration_value);
+                });
+
+            if let Some(all_descriptor_sets) = all_descriptor_sets.as_mut() {
+                all_descriptor_sets
+                    .entry(target_id)
+                    .or_insert((None, None))
+                    .1 = Some(decoration_value);
+            }
+        }
     });
 
-    // - Sort and unwrap set binding pairs.
+    // - Sort and unwrap set binding pairs. Variables with only a DescriptorSet are not bound, so
+    //   neither are the variables split off of them
     let mut new_variable_id_to_decorations = new_variable_id_to_decorations
         .into_iter()
-        .collect::<Vec<_>>();
-    new_variable_id_to_decorations.sort_by_key(|(_, (maybe_binding, _))| {
-        let (_, binding) = maybe_binding.unwrap();
-        binding
-    });
-    let new_variable_id_to_decorations = new_variable_id_to_decorations
-        .into_iter()
-        .map(|(new_res_id, (maybe_binding, maybe_descriptor_set))| {
-            let (binding_idx, binding) = maybe_binding.unwrap();
-            let (descriptor_set_idx, descriptor_set) = maybe_descriptor_set.unwrap();
-
-            (
-                new_res_id,
-                ((binding_idx, binding), (descriptor_set_idx, descriptor_set)),
-            )
-        })
-        .collect::<HashMap<_, _>>();
+        .filter_map(
+            |((new_res_id, original_res_id, correction_type), decorations)| {
+                let (Some((binding_idx, binding)), Some(descriptor_set)) = decorations else {
+                    return decorations.0.map(|(_, binding)| {
+                        Err(TransformError::BindingWithoutDescriptorSet {
+                            id: original_res_id,
+                            binding,
+                        })
+                    });
+                };
+
+                Some(Ok((
+                    binding_idx,
+                    NewVariable {
+                        id: new_res_id,
+                        original_id: original_res_id,
+                        original: DescriptorBinding {
+                            descriptor_set,
+                            binding,
+                        },
+                        correction_type,
+                    },
+                )))
+            },
+        )
+        .collect::<Result<Vec<_>, _>>()?;
+    new_variable_id_to_decorations.sort_by_key(|(_, variable)| (variable.original, variable.id));
 
     // - If we need to, build a new correction map
     if let Some(all_descriptor_sets) = all_descriptor_sets {
-        let mut new_corrections = CorrectionMap::default();
-        let mut all_descriptor_sets = all_descriptor_sets.into_iter().collect::<Vec<_>>();
-        all_descriptor_sets.sort_by_key(|(_, (maybe_binding, _))| maybe_binding.unwrap());
-
-        for (_, (binding, set)) in all_descriptor_sets {
-            let set = set.unwrap();
-            let binding = binding.unwrap();
-
-            new_corrections
-                .sets
-                .entry(set)
-                .or_insert(CorrectionSet::default())
-                .bindings
-                .insert(
-                    binding,
-                    CorrectionBinding {
-                        corrections: vec![],
+        let variables = all_descriptor_sets
+            .into_iter()
+            .filter_map(|(id, decorations)| match decorations {
+                (Some(binding), Some(descriptor_set)) => Some(Ok(BoundVariable {
+                    id,
+                    location: DescriptorBinding {
+                        descriptor_set,
+                        binding,
                     },
-                );
+                })),
+                (Some(binding), None) => Some(Err(TransformError::BindingWithoutDescriptorSet {
+                    id,
+                    binding,
+                })),
+                (None, _) => None,
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        *corrections = Some(initial_corrections(variables));
+    }
+
+    // - Insert new descriptor set and binding for new ~~sampler~~ variable, the binding is only a
+    //   placeholder until the binding allocator has run
+    for (binding_idx, variable) in new_variable_id_to_decorations.iter() {
+        let new_binding = variable.original.binding.checked_add(1).ok_or_else(|| {
+            TransformError::unsupported(module, *binding_idx, "binding overflows")
+        })?;
+
+        // - Create the decorations for the new variable
+        instruction_inserts.push(InstructionInsert {
+            // NOTE: If bindings are not ordered reasonably in spv, the original
+            // implementation may fail.
+            // Example:
+            //      %u_other = (0, 1)
+            //      %u_combined = (0, 0)
+            //      %inserted_sampler = (0, 0)
+            // becomes
+            //      %u_other = (0, 1)
+            //      %u_combined = (0, 0)
+            //      %inserted_sampler = (0, 2)
+            // previous_idx: descriptor_set_idx.max(binding_idx),
+            previous_idx: layout
+                .previous_idx(LayoutSection::Annotation, &[])
+                .unwrap_or(*binding_idx),
+            instructions: vec![
+                Instruction::new(
+                    SPV_INSTRUCTION_OP_DECORATE,
+                    None,
+                    None,
+                    vec![
+                        variable.id,
+                        SPV_DECORATION_DESCRIPTOR_SET,
+                        variable.original.descriptor_set,
+                    ],
+                ),
+                Instruction::new(
+                    SPV_INSTRUCTION_OP_DECORATE,
+                    None,
+                    None,
+                    vec![variable.id, SPV_DECORATION_BINDING, new_binding],
+                ),
+            ],
+        });
+    }
+
+    Ok(DecorateOut {
+        new_variables: new_variable_id_to_decorations
+            .into_iter()
+            .map(|(_, variable)| variable)
+            .collect(),
+    })
+}
+
+// Copy a NonUniform decoration found at `decorate_idx` onto `ids`.
+pub fn decorate_non_uniform(decorate_idx: usize, ids: &[u32]) -> InstructionInsert {
+    InstructionInsert {
+        previous_idx: decorate_idx,
+        instructions: ids
+            .iter()
+            .map(|&id| {
+                Instruction::new(
+                    SPV_INSTRUCTION_OP_DECORATE,
+                    None,
+                    None,
+                    vec![id, SPV_DECORATION_NON_UNIFORM],
+                )
+            })
+            .collect(),
+    }
+}
+
+// A correction map where nothing has been split yet
+pub fn initial_corrections(mut variables: Vec<BoundVariable>) -> CorrectionMap {
+    let mut corrections = CorrectionMap::default();
+    variables.sort_by_key(|variable| (variable.location, variable.id));
+    for BoundVariable { id, location } in variables {
+        corrections
+            .sets
+            .entry(location.descriptor_set)
+            .or_insert(CorrectionSet::default())
+            .bindings
+            .insert(
+                location.binding,
+                CorrectionBinding {
+                    corrections: vec![],
+                    locations: vec![location],
+                },
+            );
+        corrections.resources.push(CorrectionResource {
+            original_id: id,
+            original: location,
+            // - Described once the module has been transformed, see `correct_decorate`
+            variables: vec![CorrectionVariable {
+                id,
+                location,
+                kind: ResourceKind::Other,
+                texture: None,
+                sampler: None,
+                correction: None,
+                visibility: ShaderStages::NONE,
+            }],
+        });
+    }
+    corrections
+}
+
+// Every variable with both a Binding and a DescriptorSet decoration, a DescriptorSet alone does
+// not bind anything
+pub fn bound_variables(module: &Module) -> Result<Vec<BoundVariable>, TransformError> {
+    let mut decorations = HashMap::new();
+    for instruction in module.instructions.iter() {
+        if instruction.opcode != SPV_INSTRUCTION_OP_DECORATE {
+            continue;
+        }
+        match instruction.operands[1] {
+            SPV_DECORATION_DESCRIPTOR_SET => {
+                decorations
+                    .entry(instruction.operands[0])
+                    .or_insert((None, None))
+                    .0 = Some(instruction.operands[2])
+            }
+            SPV_DECORATION_BINDING => {
+                decorations
+                    .entry(instruction.operands[0])
+                    .or_insert((None, None))
+                    .1 = Some(instruction.operands[2])
+            }
+            _ => {}
+        }
+    }
+
+    let mut variables = decorations
+        .into_iter()
+        .filter_map(|(id, decorations)| match decorations {
+            (Some(descriptor_set), Some(binding)) => Some(Ok(BoundVariable {
+                id,
+                location: DescriptorBinding {
+                    descriptor_set,
+                    binding,
+                },
+            })),
+            (None, Some(binding)) => Some(Err(TransformError::BindingWithoutDescriptorSet {
+                id,
+                binding,
+            })),
+            (_, None) => None,
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    variables.sort_by_key(|variable| variable.id);
+    Ok(variables)
+}
+
+// GL_ARB_gl_spirv modules bind with a Binding decoration alone. Give every id like that a
+// DescriptorSet decoration for `descriptor_set`, right after its Binding decoration.
+pub fn add_missing_descriptor_sets(module: &mut Module, descriptor_set: u32) {
+    let decorated_ids = |decoration: u32| {
+        module
+            .instructions
+            .iter()
+            .filter(|instruction| {
+                instruction.opcode == SPV_INSTRUCTION_OP_DECORATE
+                    && instruction.operands[1] == decoration
+            })
+            .map(|instruction| instruction.operands[0])
+            .collect::<HashSet<_>>()
+    };
+    let mut missing_ids =
+        &decorated_ids(SPV_DECORATION_BINDING) - &decorated_ids(SPV_DECORATION_DESCRIPTOR_SET);
+    if missing_ids.is_empty() {
+        return;
+    }
+
+    let instructions = std::mem::take(&mut module.instructions);
+    module
+        .instructions
+        .reserve(instructions.len() + missing_ids.len());
+    for instruction in instructions {
+        let missing_id = if instruction.opcode == SPV_INSTRUCTION_OP_DECORATE
+            && instruction.operands[1] == SPV_DECORATION_BINDING
+            && missing_ids.remove(&instruction.operands[0])
+        {
+            Some(instruction.operands[0])
+        } else {
+            None
+        };
+        module.instructions.push(instruction);
+        if let Some(id) = missing_id {
+            module.instructions.push(Instruction::new(
+                SPV_INSTRUCTION_OP_DECORATE,
+                None,
+                None,
+                vec![id, SPV_DECORATION_DESCRIPTOR_SET, descriptor_set],
+            ));
         }
+    }
+}
 
-        *corrections = Some(new_corrections);
+// Older tools share decorations through OpDecorationGroup and OpGroupDecorate, which hides
+// bindings from everything that only reads OpDecorate. Replace every use of a group with explicit
+// decorations on its targets, and remove the groups.
+pub fn flatten_decoration_groups(module: &mut Module) {
+    let group_ids = module
+        .instructions
+        .iter()
+        .filter(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_DECORATION_GROUP)
+        .map(|instruction| instruction.id())
+        .collect::<HashSet<_>>();
+    if group_ids.is_empty() {
+        return;
     }
 
-    let old_corrections = corrections.clone();
-
-    // - Insert new descriptor set and binding for new ~~sampler~~ variable
-    new_variable_id_to_decorations.iter().for_each(
-        |(
-            (new_res_id, correction_type),
-            ((_binding_idx, binding), (_descriptor_set_idx, descriptor_set)),
-        )| {
-            // - Create the decorations for the new variable
-            instruction_inserts.push(InstructionInsert {
-                // NOTE: If bindings are not ordered reasonably in spv, the original
-                // implementation may fail.
-                // Example:
-                //      %u_other = (0, 1)
-                //      %u_combined = (0, 0)
-                //      %inserted_sampler = (0, 0)
-                // becomes
-                //      %u_other = (0, 1)
-                //      %u_combined = (0, 0)
-                //      %inserted_sampler = (0, 2)
-                // previous_spv_idx: descriptor_set_idx.max(binding_idx),
-                previous_spv_idx: first_op_deocrate_idx.unwrap(),
-                instruction: vec![
-                    encode_word(4, SPV_INSTRUCTION_OP_DECORATE),
-                    *new_res_id,
-                    SPV_DECORATION_DESCRIPTOR_SET,
-                    *descriptor_set,
-                    encode_word(4, SPV_INSTRUCTION_OP_DECORATE),
-                    *new_res_id,
-                    SPV_DECORATION_BINDING,
-                    binding + 1,
-                ],
-            });
-
-            // - Stamp our correction map with new variables
-            if let Some(bindings) = corrections.as_mut().unwrap().sets.get_mut(descriptor_set) {
-                // NOTE: We do expect this to be sorted by binding
-                let mut input_bindings = old_corrections
-                    .as_ref()
-                    .unwrap()
-                    .sets
-                    .get(descriptor_set)
-                    .unwrap()
-                    .bindings
-                    .iter()
-                    .collect::<Vec<_>>();
-                input_bindings.sort_by_key(|(k, _)| **k);
-                let input_bindings = input_bindings
-                    .iter()
-                    .map(|(binding, correction)| (binding, correction.corrections.len() + 1))
-                    .collect::<Vec<_>>();
-
-                let mut my_binding = *binding as isize;
-                for &(binding, binding_count) in input_bindings.iter() {
-                    if my_binding <= 0 {
-                        // The leftover `my_binding` corresponds with the case of having to insert
-                        // between or after previously inserted variables
-                        bindings
-                            .bindings
-                            .get_mut(binding)
-                            .unwrap()
-                            .corrections
-                            .insert(my_binding.unsigned_abs(), *correction_type);
-
-                        break;
-                    }
-                    my_binding -= binding_count as isize;
+    // - Decorations applied to a group apply to every target of the group
+    let mut group_decorations: HashMap<u32, Vec<Instruction>> = HashMap::new();
+    for instruction in module.instructions.iter() {
+        if matches!(
+            instruction.opcode,
+            SPV_INSTRUCTION_OP_DECORATE
+                | SPV_INSTRUCTION_OP_DECORATE_ID
+                | SPV_INSTRUCTION_OP_DECORATE_STRING
+        ) && instruction
+            .operands
+            .first()
+            .is_some_and(|target_id| group_ids.contains(target_id))
+        {
+            group_decorations
+                .entry(instruction.operands[0])
+                .or_default()
+                .push(instruction.clone());
+        }
+    }
+
+    let instructions = std::mem::take(&mut module.instructions);
+    module.instructions.reserve(instructions.len());
+    for instruction in instructions {
+        match instruction.opcode {
+            SPV_INSTRUCTION_OP_DECORATION_GROUP => {}
+            SPV_INSTRUCTION_OP_NAME
+            | SPV_INSTRUCTION_OP_DECORATE
+            | SPV_INSTRUCTION_OP_DECORATE_ID
+            | SPV_INSTRUCTION_OP_DECORATE_STRING
+                if instruction
+                    .operands
+                    .first()
+                    .is_some_and(|target_id| group_ids.contains(target_id)) => {}
+            SPV_INSTRUCTION_OP_GROUP_DECORATE => {
+                let (Some(group_id), Some(target_ids)) =
+                    (instruction.operands.first(), instruction.operands.get(1..))
+                else {
+                    continue;
+                };
+                let decorations = group_decorations
+                    .get(group_id)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                for &target_id in target_ids.iter() {
+                    module
+                        .instructions
+                        .extend(decorations.iter().map(|decoration| {
+                            Instruction::new(
+                                decoration.opcode,
+                                None,
+                                None,
+                                [target_id]
+                                    .into_iter()
+                                    .chain(
+                                        decoration
+                                            .operands
+                                            .get(1..)
+                                            .unwrap_or_default()
+                                            .iter()
+                                            .copied(),
+                                    )
+                                    .collect(),
+                            )
+                        }));
                 }
             }
-        },
-    );
-
-    DecorateOut {
-        descriptor_sets_to_correct,
+            // - There is no member form of OpDecorateId
+            SPV_INSTRUCTION_OP_GROUP_MEMBER_DECORATE => {
+                let (Some(group_id), Some(targets)) =
+                    (instruction.operands.first(), instruction.operands.get(1..))
+                else {
+                    continue;
+                };
+                let decorations = group_decorations
+                    .get(group_id)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                for target in targets.chunks_exact(2) {
+                    module
+                        .instructions
+                        .extend(decorations.iter().filter_map(|decoration| {
+                            let opcode = match decoration.opcode {
+                                SPV_INSTRUCTION_OP_DECORATE => SPV_INSTRUCTION_OP_MEMBER_DECORATE,
+                                SPV_INSTRUCTION_OP_DECORATE_STRING => {
+                                    SPV_INSTRUCTION_OP_MEMBER_DECORATE_STRING
+                                }
+                                _ => return None,
+                            };
+                            Some(Instruction::new(
+                                opcode,
+                                None,
+                                None,
+                                target
+                                    .iter()
+                                    .chain(decoration.operands.get(1..).unwrap_or_default())
+                                    .copied()
+                                    .collect(),
+                            ))
+                        }));
+                }
+            }
+            _ => module.instructions.push(instruction),
+        }
     }
 }
@@ -0,0 +1,57 @@
+use super::*;
+
+pub struct EntryPointInterfaceIn<'a> {
+    pub module: &'a Module,
+    pub operand_inserts: &'a mut Vec<OperandInsert>,
+
+    pub affected_variables: &'a [DecorationVariable],
+}
+
+// Starting with SPIR-V 1.4, an OpEntryPoint lists every global variable its static call tree
+// references, not just Input and Output.
+// A new variable is only ever used where the variable it was split from is used, so it joins
+// the interface of every entry point that lists the original. Older modules never list
+// UniformConstant variables, so they are left as they are.
+pub fn entry_point_interface(ei_in: EntryPointInterfaceIn) {
+    let EntryPointInterfaceIn {
+        module,
+        operand_inserts,
+        affected_variables,
+    } = ei_in;
+
+    let mut original_id_to_new_ids: HashMap<u32, Vec<u32>> = HashMap::new();
+    for variable in affected_variables.iter() {
+        original_id_to_new_ids
+            .entry(variable.original_res_id)
+            .or_default()
+            .push(variable.new_res_id);
+    }
+
+    for (ep_idx, entry_point) in module.instructions.iter().enumerate() {
+        if entry_point.opcode != SPV_INSTRUCTION_OP_ENTRY_POINT {
+            continue;
+        }
+        // - Execution model, function and a nul terminated name come before the interface
+        let Some(name_length) = entry_point
+            .operands
+            .iter()
+            .skip(2)
+            .position(|word| word.to_le_bytes().contains(&0))
+        else {
+            continue;
+        };
+        let interface = &entry_point.operands[2 + name_length + 1..];
+
+        let new_ids = interface
+            .iter()
+            .filter_map(|id| original_id_to_new_ids.get(id))
+            .flatten();
+        for &new_id in new_ids {
+            operand_inserts.push(OperandInsert {
+                idx: ep_idx,
+                operand_idx: entry_point.operands.len() - 1,
+                operand: new_id,
+            });
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use super::*;
+
+/// The sections of the SPIR-V logical layout, in the order they must appear.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LayoutSection {
+    Capability,
+    Extension,
+    ExtInstImport,
+    MemoryModel,
+    EntryPoint,
+    ExecutionMode,
+    Debug,
+    Annotation,
+    // Types, constants, and global variables
+    Global,
+    Function,
+}
+
+impl LayoutSection {
+    const COUNT: usize = LayoutSection::Function as usize + 1;
+
+    // Only correct for instructions before the first OpFunction.
+    fn of_opcode(opcode: u16) -> Self {
+        match opcode {
+            SPV_INSTRUCTION_OP_CAPABILITY => LayoutSection::Capability,
+            SPV_INSTRUCTION_OP_EXTENSION => LayoutSection::Extension,
+            SPV_INSTRUCTION_OP_EXT_INST_IMPORT => LayoutSection::ExtInstImport,
+            SPV_INSTRUCTION_OP_MEMORY_MODEL => LayoutSection::MemoryModel,
+            SPV_INSTRUCTION_OP_ENTRY_POINT => LayoutSection::EntryPoint,
+            SPV_INSTRUCTION_OP_EXECUTION_MODE | SPV_INSTRUCTION_OP_EXECUTION_MODE_ID => {
+                LayoutSection::ExecutionMode
+            }
+            SPV_INSTRUCTION_OP_SOURCE_CONTINUED
+            | SPV_INSTRUCTION_OP_SOURCE
+            | SPV_INSTRUCTION_OP_SOURCE_EXTENSION
+            | SPV_INSTRUCTION_OP_NAME
+            | SPV_INSTRUCTION_OP_MEMBER_NAME
+            | SPV_INSTRUCTION_OP_STRING
+            | SPV_INSTRUCTION_OP_MODULE_PROCESSED => LayoutSection::Debug,
+            SPV_INSTRUCTION_OP_DECORATE
+            | SPV_INSTRUCTION_OP_MEMBER_DECORATE
+            | SPV_INSTRUCTION_OP_DECORATION_GROUP
+            | SPV_INSTRUCTION_OP_GROUP_DECORATE
+            | SPV_INSTRUCTION_OP_GROUP_MEMBER_DECORATE
+            | SPV_INSTRUCTION_OP_DECORATE_ID
+            | SPV_INSTRUCTION_OP_DECORATE_STRING
+            | SPV_INSTRUCTION_OP_MEMBER_DECORATE_STRING => LayoutSection::Annotation,
+            SPV_INSTRUCTION_OP_FUNCTION => LayoutSection::Function,
+            _ => LayoutSection::Global,
+        }
+    }
+}
+
+/// Finds where new instructions belong in the logical layout of a module.
+/// Indices refer to the module as it was parsed, so the results can be used as
+/// [InstructionInsert::previous_idx].
+pub struct Layout {
+    section_last_idxs: [Option<usize>; LayoutSection::COUNT],
+    definitions: HashMap<u32, usize>,
+}
+
+impl Layout {
+    pub fn new(module: &Module) -> Self {
+        let mut section_last_idxs = [None; LayoutSection::COUNT];
+        let mut section = LayoutSection::Capability;
+        for (idx, instruction) in module.instructions.iter().enumerate() {
+            // - Earlier passes may leave whited out instructions anywhere
+            if instruction.opcode == SPV_INSTRUCTION_OP_NOP {
+                continue;
+            }
+            // - Everything after the first OpFunction is part of a function
+            if section != LayoutSection::Function {
+                section = LayoutSection::of_opcode(instruction.opcode);
+            }
+            section_last_idxs[section as usize] = Some(idx);
+        }
+
+        Layout {
+            section_last_idxs,
+            definitions: module.definitions(),
+        }
+    }
+
+    /// The index to insert after so that new instructions land in `section`, after every id in
+    /// `dependencies` has been defined.
+    /// Returns `None` if nothing precedes `section`, which can only happen in modules without an
+    /// OpMemoryModel.
+    pub fn previous_idx(&self, section: LayoutSection, dependencies: &[u32]) -> Option<usize> {
+        // - Begin right after the end of the closest non-empty preceding section
+        let section_start = self.section_last_idxs[..section as usize]
+            .iter()
+            .rev()
+            .find_map(|&idx| idx);
+        dependencies
+            .iter()
+            .filter_map(|id| self.definitions.get(id).copied())
+            .chain(section_start)
+            .max()
+    }
+}
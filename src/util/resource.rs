@@ -0,0 +1,268 @@
+use super::*;
+
+// OpTypeImage Dim
+const SPV_DIM_1D: u32 = 0;
+const SPV_DIM_2D: u32 = 1;
+const SPV_DIM_3D: u32 = 2;
+const SPV_DIM_CUBE: u32 = 3;
+const SPV_DIM_RECT: u32 = 4;
+
+// The OpTypeImage, OpTypeSampler or OpTypeSampledImage behind the variable `id`, looking through
+// pointers and arrays.
+fn resource_type<'a>(
+    module: &'a Module,
+    definitions: &HashMap<u32, usize>,
+    id: u32,
+) -> Option<&'a Instruction> {
+    let mut type_id = definitions
+        .get(&id)
+        .and_then(|&idx| module.instructions[idx].result_type);
+    // - Arrays may nest, but never loop back on themselves in a valid module
+    for _ in 0..definitions.len() {
+        let instruction = type_id
+            .and_then(|type_id| definitions.get(&type_id))
+            .map(|&idx| &module.instructions[idx])?;
+        match instruction.opcode {
+            SPV_INSTRUCTION_OP_TYPE_POINTER => type_id = instruction.operands.get(1).copied(),
+            SPV_INSTRUCTION_OP_TYPE_ARRAY | SPV_INSTRUCTION_OP_TYPE_RUNTIME_ARRAY => {
+                type_id = instruction.operands.first().copied()
+            }
+            SPV_INSTRUCTION_OP_TYPE_IMAGE
+            | SPV_INSTRUCTION_OP_TYPE_SAMPLER
+            | SPV_INSTRUCTION_OP_TYPE_SAMPLED_IMAGE => return Some(instruction),
+            _ => return None,
+        }
+    }
+    None
+}
+
+// What the variable `id` holds, looking through pointers and arrays.
+pub fn resource_kind(module: &Module, definitions: &HashMap<u32, usize>, id: u32) -> ResourceKind {
+    match resource_type(module, definitions, id).map(|instruction| instruction.opcode) {
+        Some(SPV_INSTRUCTION_OP_TYPE_IMAGE) => ResourceKind::Texture,
+        Some(SPV_INSTRUCTION_OP_TYPE_SAMPLER) => ResourceKind::Sampler,
+        Some(SPV_INSTRUCTION_OP_TYPE_SAMPLED_IMAGE) => ResourceKind::CombinedImageSampler,
+        _ => ResourceKind::Other,
+    }
+}
+
+// How a bound variable is used by sampling operations
+#[derive(Debug, Clone, Default)]
+pub struct ResourceUsage {
+    pub usage: Usage,
+    // Variables this image or sampler is combined with by OpSampledImage
+    pub paired_ids: HashSet<u32>,
+}
+
+// Find the sampling operations every variable reaches, and what it is combined with, through
+// access chains, forwarding instructions and function calls.
+pub fn resource_usages(module: &Module) -> Result<HashMap<u32, ResourceUsage>, TransformError> {
+    let definitions = module.definitions();
+    let trace = ValueTrace::new(module);
+
+    // - Parameters take every argument they are called with, dead functions are ignored
+    let graph = CallGraph::new(module)?;
+    let mut arguments: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (function, calls) in graph.calls.iter().enumerate() {
+        if !graph.is_live(function) {
+            continue;
+        }
+        for &(fc_idx, callee) in calls.iter() {
+            let fc = &module.instructions[fc_idx];
+            for (&argument_id, &fp_idx) in fc
+                .operands
+                .iter()
+                .skip(1)
+                .zip(graph.functions[callee].parameter_idxs.iter())
+            {
+                arguments
+                    .entry(module.instructions[fp_idx].id())
+                    .or_default()
+                    .push(argument_id);
+            }
+        }
+    }
+
+    // - The variables a pointer may point into
+    let variables = |pointer_id: u32| {
+        let mut variable_ids = vec![];
+        let mut visited = HashSet::new();
+        let mut stack = vec![pointer_id];
+        while let Some(id) = stack.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+            let Some(instruction) = definitions.get(&id).map(|&idx| &module.instructions[idx])
+            else {
+                continue;
+            };
+            match instruction.opcode {
+                SPV_INSTRUCTION_OP_VARIABLE => variable_ids.push(id),
+                SPV_INSTRUCTION_OP_ACCESS_CHAIN | SPV_INSTRUCTION_OP_IN_BOUNDS_ACCESS_CHAIN => {
+                    stack.push(instruction.operands[0])
+                }
+                SPV_INSTRUCTION_OP_FUNCTION_PARAMTER => {
+                    stack.extend(arguments.get(&id).into_iter().flatten())
+                }
+                _ => stack.extend(trace.sources(id)),
+            }
+        }
+        variable_ids
+    };
+    // - The variables a value may have been loaded from
+    let loaded_variables = |value_id: u32| {
+        trace
+            .roots(value_id)
+            .into_iter()
+            .filter_map(|id| definitions.get(&id).map(|&idx| &module.instructions[idx]))
+            .filter(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_LOAD)
+            .flat_map(|load| variables(load.operands[0]))
+            .collect::<Vec<_>>()
+    };
+
+    let mut usages: HashMap<u32, ResourceUsage> = HashMap::new();
+    for (idx, instruction) in module.instructions.iter().enumerate() {
+        let Some(variant) = operation_variant(instruction.opcode) else {
+            continue;
+        };
+        if graph
+            .function_of(idx)
+            .is_some_and(|function| !graph.is_live(function))
+        {
+            continue;
+        }
+        for root_id in trace.roots(instruction.operands[0]) {
+            let Some(root) = definitions
+                .get(&root_id)
+                .map(|&idx| &module.instructions[idx])
+            else {
+                continue;
+            };
+            match root.opcode {
+                SPV_INSTRUCTION_OP_SAMPLED_IMAGE => {
+                    let image_ids = loaded_variables(root.operands[0]);
+                    let sampler_ids = loaded_variables(root.operands[1]);
+                    for &image_id in image_ids.iter() {
+                        let image_usage = usages.entry(image_id).or_default();
+                        image_usage.usage.insert(variant);
+                        image_usage.paired_ids.extend(sampler_ids.iter().copied());
+                    }
+                    for &sampler_id in sampler_ids.iter() {
+                        let sampler_usage = usages.entry(sampler_id).or_default();
+                        sampler_usage.usage.insert(variant);
+                        sampler_usage.paired_ids.extend(image_ids.iter().copied());
+                    }
+                }
+                // - Combimgs that have not been split
+                SPV_INSTRUCTION_OP_LOAD => {
+                    for variable_id in variables(root.operands[0]) {
+                        let usage = usages.entry(variable_id).or_default();
+                        usage.usage.insert(variant);
+                        usage.paired_ids.insert(variable_id);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(usages)
+}
+
+// How WebGPU sees the texture held by the variable `id`
+pub fn texture_binding_info(
+    module: &Module,
+    definitions: &HashMap<u32, usize>,
+    usages: &HashMap<u32, ResourceUsage>,
+    id: u32,
+) -> Option<TextureBindingInfo> {
+    let mut image = resource_type(module, definitions, id)?;
+    if image.opcode == SPV_INSTRUCTION_OP_TYPE_SAMPLED_IMAGE {
+        image = definitions
+            .get(image.operands.first()?)
+            .map(|&idx| &module.instructions[idx])?;
+    }
+    if image.opcode != SPV_INSTRUCTION_OP_TYPE_IMAGE {
+        return None;
+    }
+
+    let &[
+        sampled_type_id,
+        dim,
+        depth,
+        arrayed,
+        multisampled,
+        sampled,
+        ..,
+    ] = image.operands.as_slice()
+    else {
+        return None;
+    };
+    // - Storage images are not textures to WebGPU
+    if sampled == 2 {
+        return None;
+    }
+    let view_dimension = match (dim, arrayed) {
+        (SPV_DIM_1D, 0) => TextureViewDimension::D1,
+        (SPV_DIM_2D | SPV_DIM_RECT, 0) => TextureViewDimension::D2,
+        (SPV_DIM_2D | SPV_DIM_RECT, _) => TextureViewDimension::D2Array,
+        (SPV_DIM_3D, 0) => TextureViewDimension::D3,
+        (SPV_DIM_CUBE, 0) => TextureViewDimension::Cube,
+        (SPV_DIM_CUBE, _) => TextureViewDimension::CubeArray,
+        _ => return None,
+    };
+
+    let usage = usages.get(&id).map(|usage| usage.usage).unwrap_or_default();
+    let sampled_type = definitions
+        .get(&sampled_type_id)
+        .map(|&idx| &module.instructions[idx])?;
+    let sample_type = match sampled_type.opcode {
+        SPV_INSTRUCTION_OP_TYPE_INT => match sampled_type.operands.get(1) {
+            Some(0) => TextureSampleType::Uint,
+            _ => TextureSampleType::Sint,
+        },
+        // - Depth 2 means that the image may or may not be a depth image
+        _ if depth == 1 || (depth == 2 && usage.dref) => TextureSampleType::Depth,
+        _ if usage.regular || usage.dref => TextureSampleType::Float,
+        _ => TextureSampleType::UnfilterableFloat,
+    };
+
+    Some(TextureBindingInfo {
+        view_dimension,
+        multisampled: multisampled == 1,
+        sample_type,
+    })
+}
+
+// How WebGPU sees the sampler held by the variable `id`
+pub fn sampler_binding_type(
+    module: &Module,
+    definitions: &HashMap<u32, usize>,
+    usages: &HashMap<u32, ResourceUsage>,
+    id: u32,
+) -> Option<SamplerBindingType> {
+    if !matches!(
+        resource_kind(module, definitions, id),
+        ResourceKind::Sampler | ResourceKind::CombinedImageSampler
+    ) {
+        return None;
+    }
+    let Some(usage) = usages.get(&id) else {
+        return Some(SamplerBindingType::Filtering);
+    };
+    if usage.usage.dref {
+        return Some(SamplerBindingType::Comparison);
+    }
+
+    // - Filtering samplers can only be combined with float textures
+    let is_filterable = usage.paired_ids.is_empty()
+        || usage.paired_ids.iter().any(|&image_id| {
+            texture_binding_info(module, definitions, usages, image_id)
+                .is_some_and(|info| info.sample_type == TextureSampleType::Float)
+        });
+    Some(if is_filterable {
+        SamplerBindingType::Filtering
+    } else {
+        SamplerBindingType::NonFiltering
+    })
+}
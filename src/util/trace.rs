@@ -0,0 +1,88 @@
+use super::*;
+
+// OpCopyObject, OpPhi and OpSelect pass one of their operands through unchanged.
+// Optimized modules route images, samplers and sampled images through them, so any backtracking
+// by id equality has to look through them.
+pub fn is_value_forward(opcode: u16) -> bool {
+    matches!(
+        opcode,
+        SPV_INSTRUCTION_OP_COPY_OBJECT | SPV_INSTRUCTION_OP_PHI | SPV_INSTRUCTION_OP_SELECT
+    )
+}
+
+// The operands of a forwarding instruction that are forwarded values
+pub fn forwarded_operand_idxs(instruction: &Instruction) -> Vec<usize> {
+    match instruction.opcode {
+        SPV_INSTRUCTION_OP_COPY_OBJECT => vec![0],
+        // - Operands alternate between a value and the block it comes from
+        SPV_INSTRUCTION_OP_PHI => (0..instruction.operands.len()).step_by(2).collect(),
+        // - The first operand is the condition
+        SPV_INSTRUCTION_OP_SELECT => vec![1, 2],
+        _ => vec![],
+    }
+}
+
+/// Def-use edges of every forwarding instruction in a module.
+pub struct ValueTrace {
+    // Forwarding instruction result id -> forwarded operands
+    sources: HashMap<u32, Vec<u32>>,
+    // Value id -> forwarding instructions it is forwarded by
+    users: HashMap<u32, Vec<u32>>,
+}
+
+impl ValueTrace {
+    pub fn new(module: &Module) -> Self {
+        let mut sources: HashMap<u32, Vec<u32>> = HashMap::new();
+        let mut users: HashMap<u32, Vec<u32>> = HashMap::new();
+        for instruction in module.instructions.iter() {
+            if !is_value_forward(instruction.opcode) {
+                continue;
+            }
+            for operand_idx in forwarded_operand_idxs(instruction) {
+                let source_id = instruction.operands[operand_idx];
+                sources.entry(instruction.id()).or_default().push(source_id);
+                users.entry(source_id).or_default().push(instruction.id());
+            }
+        }
+        ValueTrace { sources, users }
+    }
+
+    pub fn sources(&self, id: u32) -> &[u32] {
+        self.sources
+            .get(&id)
+            .map_or(&[], |sources| sources.as_slice())
+    }
+
+    // Every value that `id` may originate from, looking through forwarding instructions.
+    // A value that is not forwarded is its own root.
+    pub fn roots(&self, id: u32) -> Vec<u32> {
+        let mut roots = vec![];
+        let mut visited = HashSet::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            // - Phis in loops may forward each other
+            if !visited.insert(id) {
+                continue;
+            }
+            match self.sources.get(&id) {
+                Some(sources) => stack.extend(sources.iter().rev()),
+                None => roots.push(id),
+            }
+        }
+        roots
+    }
+
+    // Every forwarding instruction that any of `ids` may flow into
+    pub fn forwards(&self, ids: &[u32]) -> HashSet<u32> {
+        let mut forwards = HashSet::new();
+        let mut stack = ids.to_vec();
+        while let Some(id) = stack.pop() {
+            for &user_id in self.users.get(&id).into_iter().flatten() {
+                if forwards.insert(user_id) {
+                    stack.push(user_id);
+                }
+            }
+        }
+        forwards
+    }
+}
@@ -0,0 +1,68 @@
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationVariant {
+    Regular,
+    Dref,
+}
+
+pub fn operation_variant(opcode: u16) -> Option<OperationVariant> {
+    match opcode {
+        SPV_INSTRUCTION_OP_IMAGE_SAMPLE_IMPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_EXPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_PROJ_IMPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_PROJ_EXPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_GATHER
+        | SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_IMPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_EXPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SPARSE_GATHER => Some(OperationVariant::Regular),
+        SPV_INSTRUCTION_OP_IMAGE_SAMPLE_DREF_IMPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_DREF_EXPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_PROJ_DREF_IMPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SAMPLE_PROJ_DREF_EXPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_DREF_GATHER
+        | SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_DREF_IMPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SPARSE_SAMPLE_DREF_EXPLICIT_LOD
+        | SPV_INSTRUCTION_OP_IMAGE_SPARSE_DREF_GATHER => Some(OperationVariant::Dref),
+        _ => None,
+    }
+}
+
+// Which operations an image or sampler reaches, through every call path
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub regular: bool,
+    pub dref: bool,
+}
+
+impl Usage {
+    pub fn insert(&mut self, variant: OperationVariant) {
+        match variant {
+            OperationVariant::Regular => self.regular = true,
+            OperationVariant::Dref => self.dref = true,
+        }
+    }
+
+    // Returns whether anything was added
+    pub fn merge(&mut self, other: Usage) -> bool {
+        let merged = Usage {
+            regular: self.regular || other.regular,
+            dref: self.dref || other.dref,
+        };
+        let changed = merged != *self;
+        *self = merged;
+        changed
+    }
+
+    pub fn is_mixed(self) -> bool {
+        self.regular && self.dref
+    }
+
+    pub fn only(self) -> Option<OperationVariant> {
+        match (self.regular, self.dref) {
+            (true, false) => Some(OperationVariant::Regular),
+            (false, true) => Some(OperationVariant::Dref),
+            _ => None,
+        }
+    }
+}
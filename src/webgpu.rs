@@ -0,0 +1,135 @@
+use super::*;
+
+/// Which [BindingAllocator] [webgpu_transform] binds new variables with.
+#[derive(Debug, Clone, Default)]
+pub enum BindingStrategy {
+    /// [ShiftBindings]
+    #[default]
+    Shift,
+    /// [AppendBindings]
+    Append,
+    /// [ExtraDescriptorSet], both passes share the same set.
+    ExtraDescriptorSet(Option<u32>),
+    /// [ExplicitBindings]
+    Explicit(ExplicitBindings),
+}
+
+/// What [webgpu_transform] should do.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Split combined image samplers with [combimgsampsplitter].
+    pub split_combined: bool,
+    /// Split images and samplers used for both regular and comparison sampling with
+    /// [drefsplitter].
+    pub split_dref: bool,
+    pub binding_strategy: BindingStrategy,
+    /// The descriptor set of variables that only have a `Binding` decoration, as found in
+    /// `GL_ARB_gl_spirv` modules. The missing `DescriptorSet` decorations are added.
+    pub default_descriptor_set: u32,
+    /// Store the corrections in the output with [embed_corrections].
+    pub embed_corrections: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            split_combined: true,
+            split_dref: true,
+            binding_strategy: BindingStrategy::default(),
+            default_descriptor_set: 0,
+            embed_corrections: false,
+        }
+    }
+}
+
+/// The result of [webgpu_transform].
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub spv: Vec<u32>,
+    /// The corrections of every pass that ran, `None` if nothing needed to be done.
+    pub corrections: Option<CorrectionMap>,
+}
+
+/// Make `spv` usable with WebGPU, running every pass that is needed in the right order.
+/// Combined image samplers are split first, so that the images and samplers they become can then
+/// be split for comparison sampling.
+/// Passes that would not change anything are skipped.
+pub fn webgpu_transform(spv: &[u32], options: &Options) -> Result<Output, TransformError> {
+    let mut module = Module::parse(spv)?;
+    util::flatten_decoration_groups(&mut module);
+    util::add_missing_descriptor_sets(&mut module, options.default_descriptor_set);
+
+    // - Pin the extra set now, it would otherwise move with every pass
+    let extra_descriptor_set = match &options.binding_strategy {
+        BindingStrategy::ExtraDescriptorSet(Some(descriptor_set)) => Some(*descriptor_set),
+        BindingStrategy::ExtraDescriptorSet(None) => util::bound_variables(&module)?
+            .iter()
+            .map(|variable| variable.location.descriptor_set)
+            .max()
+            .map_or(Some(0), |descriptor_set| descriptor_set.checked_add(1)),
+        _ => None,
+    };
+    let allocator = || -> Box<dyn BindingAllocator> {
+        match &options.binding_strategy {
+            BindingStrategy::Shift => Box::new(ShiftBindings),
+            BindingStrategy::Append => Box::new(AppendBindings),
+            BindingStrategy::ExtraDescriptorSet(_) => Box::new(ExtraDescriptorSet {
+                descriptor_set: extra_descriptor_set,
+            }),
+            BindingStrategy::Explicit(explicit) => Box::new(explicit.clone()),
+        }
+    };
+
+    // - Splitting combined image samplers never adds comparison sampling, so both can be decided
+    //   up front
+    let mut passes = PassManager::new();
+    passes.add(FlattenDecorationGroupsPass);
+    passes.add(DefaultDescriptorSetPass {
+        descriptor_set: options.default_descriptor_set,
+    });
+    if options.split_combined && has_combined_image_samplers(&module) {
+        passes.add(SplitCombinedPass {
+            allocator: allocator(),
+        });
+    }
+    if options.split_dref && has_dref_operations(&module) {
+        passes.add(SplitDrefPass {
+            allocator: allocator(),
+        });
+    }
+
+    let mut corrections = None;
+    let mut out_spv = passes.run(spv, &mut corrections)?;
+
+    if options.embed_corrections
+        && let Some(corrections) = corrections.as_ref()
+    {
+        out_spv = embed_corrections(&out_spv, corrections)?;
+    }
+
+    Ok(Output {
+        spv: out_spv,
+        corrections,
+    })
+}
+
+// Only bound combined image samplers are split
+fn has_combined_image_samplers(module: &Module) -> bool {
+    let definitions = module.definitions();
+    module
+        .instructions
+        .iter()
+        .filter(|instruction| instruction.opcode == SPV_INSTRUCTION_OP_VARIABLE)
+        .any(|instruction| {
+            util::resource_kind(module, &definitions, instruction.id())
+                == ResourceKind::CombinedImageSampler
+        })
+}
+
+// Images and samplers are only ever split for comparison sampling
+fn has_dref_operations(module: &Module) -> bool {
+    module
+        .instructions
+        .iter()
+        .any(|instruction| operation_variant(instruction.opcode) == Some(OperationVariant::Dref))
+}